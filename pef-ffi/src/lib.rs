@@ -0,0 +1,7 @@
+//! Builds pef as a shared library, so crates depending on pef don't all produce one. The `ffi`
+//! feature exports the C interface and `wasm` the WebAssembly bindings.
+
+#[cfg(feature = "ffi")]
+pub use pef::ffi::*;
+#[cfg(feature = "wasm")]
+pub use pef::WasmEliasFano;
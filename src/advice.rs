@@ -0,0 +1,114 @@
+use crate::elias_fano::{validate_sorted, EliasFano};
+use crate::errors::Error;
+
+/// What `advise` found about a list that Elias-Fano handles poorly, for picking another codec or
+/// construction mode. Each field is `None` when that problem isn't there.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Advice {
+    pub outliers: Option<Outliers>,
+    pub clustered: Option<Clustered>,
+}
+
+/// A few ids far above the rest, setting a universe that spreads every other id's bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outliers {
+    pub count: usize,
+    /// The smallest of them.
+    pub first: usize,
+    pub bytes: usize,
+    /// Bytes with the outliers kept aside as plain `u64`s and the rest encoded on their own.
+    pub bytes_without: usize,
+}
+
+/// Ids mostly in long runs of consecutive values, which a run-length encoding stores in a
+/// fraction of the space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clustered {
+    pub runs: usize,
+    pub mean_run_len: f64,
+    pub bytes: usize,
+    /// Bytes for a start and a length per run as `u32`s.
+    pub run_length_bytes: usize,
+}
+
+impl Advice {
+    pub fn is_empty(&self) -> bool {
+        self.outliers.is_none() && self.clustered.is_none()
+    }
+}
+
+/// At most this many of the largest ids are considered outliers.
+const MAX_OUTLIERS: usize = 16;
+
+/// Flags inputs that Elias-Fano encodes much larger than they need to be: a few huge outliers,
+/// or clustering better served by run-length encoding. Either is reported when the alternative
+/// would take at most three quarters of the bytes.
+pub fn advise(ids: &[usize]) -> Result<Advice, Error> {
+    validate_sorted(ids)?;
+    Ok(advise_sorted(ids))
+}
+
+pub(crate) fn advise_sorted(ids: &[usize]) -> Advice {
+    let Some(&last) = ids.last() else {
+        return Advice::default();
+    };
+    let bytes = EliasFano::estimate_size(ids.len(), last);
+    let worthwhile = |alternative: usize| alternative * 4 <= bytes * 3;
+
+    let outliers = outlier_count(ids).map(|count| Outliers {
+        count,
+        first: ids[ids.len() - count],
+        bytes,
+        bytes_without: split_size(ids, count),
+    });
+    let runs = 1 + ids.windows(2).filter(|pair| pair[1] - pair[0] != 1).count();
+    let clustered = Some(Clustered { runs, mean_run_len: ids.len() as f64 / runs as f64, bytes, run_length_bytes: 8 * runs })
+        .filter(|clustered| worthwhile(clustered.run_length_bytes));
+    Advice { outliers: outliers.filter(|outliers| worthwhile(outliers.bytes_without)), clustered }
+}
+
+/// How many of the largest ids to keep aside to make the rest smallest, if any.
+pub(crate) fn outlier_count(ids: &[usize]) -> Option<usize> {
+    let last = *ids.last()?;
+    let whole = EliasFano::estimate_size(ids.len(), last);
+    (1..=MAX_OUTLIERS.min(ids.len() / MAX_OUTLIERS))
+        .map(|count| (split_size(ids, count), count))
+        .filter(|(size, _)| *size < whole)
+        .min()
+        .map(|(_, count)| count)
+}
+
+/// Bytes for `ids` with the largest `count` kept as `u64`s after a `u64` count of them, and the
+/// rest encoded, as `SplitEliasFano` stores them.
+fn split_size(ids: &[usize], count: usize) -> usize {
+    let rest = ids.len() - count;
+    EliasFano::estimate_size(rest, ids[rest - 1]) + 8 * (count + 1)
+}
+
+impl EliasFano<Vec<u64>> {
+    /// `from_sorted_slice` that also returns what `advise` finds, so an ingestion pipeline can
+    /// switch codecs for the next list, or rebuild this one, without another pass.
+    pub fn with_advice(ids: &[usize]) -> Result<(Self, Advice), Error> {
+        let ef = Self::from_sorted_slice(ids)?;
+        Ok((ef, advise_sorted(ids)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advice_flags_outliers_and_clusters() {
+        let ids: Vec<usize> = (0..100).chain(1000..1023).chain(1060..1400).chain(20000..20001).collect();
+        let (ef, advice) = EliasFano::with_advice(&ids).expect("elias fano encoding");
+        let outliers = advice.outliers.expect("a lone outlier");
+        assert_eq!((outliers.count, outliers.first, outliers.bytes), (1, 20000, ef.as_bytes().len()));
+        assert!(outliers.bytes_without * 3 < outliers.bytes * 2);
+        assert_eq!(advice.clustered.map(|clustered| clustered.runs), Some(4));
+
+        let spread: Vec<usize> = (0..1000).map(|i| i * 37 + i % 5).collect();
+        assert!(advise(&spread).expect("sorted").is_empty());
+        assert!(advise(&[3, 2]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::bits::Bits;
+use crate::elias_fano::{ceil_log2, parameters, EliasFano};
+use crate::errors::Error;
+use crate::select_index::{LazySelectIndex, DEFAULT_SAMPLE_RATE};
+use crate::sequence::Strictness;
+use std::sync::{Arc, RwLock};
+
+/// A sequence that ids can be appended to. Appended ids wait in a buffer until `seal` encodes
+/// them onto the end of the sequence so far, which is streamed rather than decoded.
+#[derive(Debug, Clone)]
+pub struct AppendableEliasFano {
+    sealed: Arc<EliasFano<Vec<u64>>>,
+    pending: Vec<u64>,
+    // Set by `with_universe`, which fixes the layout so `seal` can append in place
+    universe: Option<u64>,
+}
+
+impl Default for AppendableEliasFano {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppendableEliasFano {
+    pub fn new() -> Self {
+        Self { sealed: Arc::new(EliasFano::empty()), pending: vec![], universe: None }
+    }
+
+    /// Like `new`, for ids up to `universe`, laid out as `new` would for `expected_len` of them.
+    /// With the layout fixed, `seal` appends to the bits already sealed instead of re-encoding
+    /// them. Going past `expected_len` is fine, it just compresses less well than re-encoding.
+    pub fn with_universe(universe: u64, expected_len: usize) -> Self {
+        let sealed = EliasFano::empty_within(universe, expected_len);
+        Self { sealed: Arc::new(sealed), pending: vec![], universe: Some(universe) }
+    }
+
+    /// Appends `id`, which must be above every id before it, and at most the universe if one was
+    /// given.
+    pub fn push(&mut self, id: u64) -> Result<(), Error> {
+        if let Some(last) = self.last().filter(|last| *last >= id) {
+            return Err(Error::out_of_order(self.len(), last, id));
+        }
+        if let Some(universe) = self.universe.filter(|universe| id > *universe) {
+            return Err(Error::value_above_universe(id as usize, universe as usize));
+        }
+        self.pending.push(id);
+        Ok(())
+    }
+
+    /// Number of ids, sealed or not.
+    pub fn len(&self) -> usize {
+        self.sealed.len() + self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn last(&self) -> Option<u64> {
+        self.pending.last().copied().or_else(|| self.sealed.last_u64())
+    }
+
+    /// Encodes the pending ids and returns the whole sequence. With nothing pending it is the
+    /// same `Arc` as last time.
+    ///
+    /// After `with_universe` the pending ids are appended to the sealed bits, costing time in
+    /// proportion to them; the words are copied first if an earlier snapshot is still held, e.g.
+    /// by readers. Otherwise the whole sequence is re-encoded with the new universe.
+    pub fn seal(&mut self) -> Arc<EliasFano<Vec<u64>>> {
+        if let Some(last) = self.pending.last().copied() {
+            if self.universe.is_some() {
+                Arc::make_mut(&mut self.sealed).append_within_universe(self.pending.drain(..));
+            } else {
+                let n = self.len();
+                let ids = self.sealed.iter_u64().chain(self.pending.drain(..));
+                let ef = EliasFano::encode(n, last, ids, Strictness::StrictlyIncreasing);
+                self.sealed = Arc::new(ef.expect("push keeps the ids increasing"));
+            }
+        }
+        self.sealed.clone()
+    }
+
+    /// Splits into a writer that appends and publishes, and a reader that sees what was last
+    /// published.
+    pub fn split(mut self) -> (EliasFanoWriter, EliasFanoReader) {
+        let published = Arc::new(RwLock::new(self.seal()));
+        (EliasFanoWriter { appendable: self, published: published.clone() }, EliasFanoReader { published })
+    }
+}
+
+/// The appending half of `AppendableEliasFano::split`. Appends stay private to the writer until
+/// `publish`.
+#[derive(Debug)]
+pub struct EliasFanoWriter {
+    appendable: AppendableEliasFano,
+    published: Arc<RwLock<Arc<EliasFano<Vec<u64>>>>>,
+}
+
+impl EliasFanoWriter {
+    pub fn push(&mut self, id: u64) -> Result<(), Error> {
+        self.appendable.push(id)
+    }
+
+    /// Seals the pending ids and swaps the result in for readers. The encoding happens before the
+    /// lock is taken, which is held only for the swap.
+    pub fn publish(&mut self) {
+        let sealed = self.appendable.seal();
+        *self.published.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = sealed;
+    }
+
+    pub fn reader(&self) -> EliasFanoReader {
+        EliasFanoReader { published: self.published.clone() }
+    }
+}
+
+/// The reading half of `AppendableEliasFano::split`, cheap to clone for each thread. Readers
+/// aren't lock-free: `snapshot` takes a short read lock, which a `publish` swapping in a new
+/// sequence holds off for as long as the swap takes.
+#[derive(Debug, Clone)]
+pub struct EliasFanoReader {
+    published: Arc<RwLock<Arc<EliasFano<Vec<u64>>>>>,
+}
+
+impl EliasFanoReader {
+    /// The latest published sequence. The lock is held only to clone the `Arc`; queries on the
+    /// snapshot take none, and it stays valid however much is published after it.
+    pub fn snapshot(&self) -> Arc<EliasFano<Vec<u64>>> {
+        self.published.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl EliasFano<Vec<u64>> {
+    /// No values yet, laid out for `expected_len` ids up to `universe` so that
+    /// `append_within_universe` never has to move a bit.
+    fn empty_within(universe: u64, expected_len: usize) -> Self {
+        let (num_lower_bits, _) = parameters(expected_len.max(1), universe);
+        let mut upper_bits = Bits::new();
+        upper_bits.append_zeros((universe >> num_lower_bits) as usize + 1);
+        Self {
+            upper_bits,
+            lower_bits: Bits::new(),
+            num_lower_bits,
+            num_upper_bits: 0,
+            size: 0,
+            universe,
+            strictness: Strictness::StrictlyIncreasing,
+            upper_index: Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE)),
+        }
+    }
+
+    /// Appends increasing `ids`, all above the last value and at most the universe. Everything
+    /// after the last value's bit is a zero closing a bucket, so an id's one goes in among them and
+    /// one more zero at the end keeps the count, the same bits a fresh encoding would have.
+    fn append_within_universe(&mut self, ids: impl IntoIterator<Item=u64>) {
+        for id in ids {
+            debug_assert!(id <= self.universe);
+            let high = (id >> self.num_lower_bits) as usize;
+            self.upper_bits.set_1(high + self.size);
+            self.upper_bits.append_zeros(1);
+            self.lower_bits.append_from(id, self.num_lower_bits);
+            self.size += 1;
+        }
+        self.num_upper_bits = ceil_log2(self.size as u128);
+        // The old index doesn't cover the new ones, so the next query builds another
+        self.upper_index = Arc::new(LazySelectIndex::new(self.select_sample_rate()));
+        debug_assert!(self.invariants_hold());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_see_published_snapshots() {
+        let (mut writer, reader) = AppendableEliasFano::new().split();
+        assert!(reader.snapshot().is_empty());
+
+        let handle = std::thread::spawn(move || {
+            for batch in 0..10u64 {
+                for i in 0..100 {
+                    writer.push(batch * 1000 + i * 3).expect("increasing");
+                }
+                writer.publish();
+            }
+            assert!(writer.push(5).is_err());
+            writer
+        });
+        let mut seen = 0;
+        while seen < 1000 {
+            let snapshot = reader.snapshot();
+            assert_eq!(snapshot.len() % 100, 0);
+            seen = snapshot.len();
+        }
+        handle.join().expect("writer finished");
+        assert_eq!(reader.snapshot().get_u64(999), Some(9297));
+    }
+
+    #[test]
+    fn fixed_universe_appends_in_place() {
+        let ids: Vec<u64> = (0..3000).map(|i| i * 37 + i % 5).collect();
+        let mut appendable = AppendableEliasFano::with_universe(200_000, 1000);
+        let mut held = vec![];
+        for batch in ids.chunks(700) {
+            batch.iter().for_each(|id| appendable.push(*id).expect("increasing"));
+            held.push(appendable.seal());
+        }
+        let sealed = appendable.seal();
+        assert_eq!(sealed.iter_u64().collect::<Vec<_>>(), ids);
+        assert_eq!((sealed.get_u64(2999), sealed.rank_u64(37 * 1000), sealed.universe_u64()), (Some(ids[2999]), 1000, 200_000));
+        assert_eq!(held[0].len(), 700);
+        assert!(appendable.push(200_001).is_err());
+
+        let loaded = EliasFano::from_bytes_owned(&sealed.as_bytes()).expect("loaded");
+        assert_eq!(loaded.iter_u64().collect::<Vec<_>>(), ids);
+        assert_eq!(AppendableEliasFano::with_universe(10, 4).seal().len(), 0);
+    }
+}
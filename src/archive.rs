@@ -0,0 +1,255 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::lazy::LazyEliasFano;
+use crate::metadata::align_up;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Write};
+
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"PEFA";
+
+pub const ARCHIVE_VERSION: u8 = 1;
+
+/*
+Archive layout, all integers little-endian:
+
+ 0..8   magic "PEFA", version, 3 reserved zero bytes
+ 8..    sequence blobs as written by EliasFano::write_to, each starting at a multiple of 8
+        table of contents: an EliasFano blob of the blob start offsets (absent when empty)
+        names: for each sequence a u64 byte length then UTF-8, empty for unnamed sequences
+last 32 toc offset, names offset, count, magic "PEFA", 4 reserved zero bytes
+ */
+const PREFIX_LEN: usize = 8;
+const FOOTER_LEN: usize = 32;
+
+/// Writes many sequences into one file. Each can be given a name or looked up by its number.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    offset: usize,
+    offsets: Vec<usize>,
+    names: Vec<String>,
+    taken: HashSet<String>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&ARCHIVE_MAGIC)?;
+        writer.write_all(&[ARCHIVE_VERSION, 0, 0, 0])?;
+        Ok(Self { writer, offset: PREFIX_LEN, offsets: vec![], names: vec![], taken: HashSet::new() })
+    }
+
+    /// Appends a sequence and returns its number.
+    pub fn add<V: AsRef<[u64]>>(&mut self, ef: &EliasFano<V>) -> io::Result<usize> {
+        self.add_named("", ef)
+    }
+
+    /// Like `add` but looked up by `name` too, which fails with `InvalidInput` if another
+    /// sequence already has it.
+    pub fn add_named<V: AsRef<[u64]>>(&mut self, name: &str, ef: &EliasFano<V>) -> io::Result<usize> {
+        if !name.is_empty() && !self.taken.insert(name.to_string()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("duplicate sequence name {:?}", name)));
+        }
+        self.pad()?;
+        self.offsets.push(self.offset);
+        self.names.push(name.to_string());
+        self.offset += ef.write_to(&mut self.writer)?;
+        Ok(self.offsets.len() - 1)
+    }
+
+    /// Writes the table of contents and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.pad()?;
+        let toc_offset = self.offset;
+        if !self.offsets.is_empty() {
+            let toc = EliasFano::new(&self.offsets).expect("offsets are increasing");
+            self.offset += toc.write_to(&mut self.writer)?;
+        }
+        let names_offset = self.offset;
+        for name in self.names.iter() {
+            self.writer.write_all(&(name.len() as u64).to_le_bytes())?;
+            self.writer.write_all(name.as_bytes())?;
+        }
+        for field in [toc_offset, names_offset, self.offsets.len()] {
+            self.writer.write_all(&(field as u64).to_le_bytes())?;
+        }
+        self.writer.write_all(&ARCHIVE_MAGIC)?;
+        self.writer.write_all(&[0; 4])?;
+        Ok(self.writer)
+    }
+
+    fn pad(&mut self) -> io::Result<()> {
+        let padding = align_up(self.offset, 8) - self.offset;
+        self.writer.write_all(&[0; 8][..padding])?;
+        self.offset += padding;
+        Ok(())
+    }
+}
+
+/// Reads an archive in place. Opening only parses the table of contents and names; sequences are
+/// loaded when asked for.
+pub struct Archive<'a> {
+    data: &'a [u8],
+    toc: Option<EliasFano<Cow<'a, [u64]>>>,
+    names: HashMap<&'a str, usize>,
+    len: usize,
+}
+
+impl<'a> Archive<'a> {
+    /// Opens `data`, checking the footer's offsets against it and that no two sequences share a
+    /// name.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < PREFIX_LEN + FOOTER_LEN {
+            return Err(Error::truncated_data(data.len(), PREFIX_LEN + FOOTER_LEN));
+        }
+        if !data.starts_with(&ARCHIVE_MAGIC) {
+            return Err(Error::corrupt_header(0));
+        }
+        if data[4] != ARCHIVE_VERSION {
+            return Err(Error::unsupported_version(data[4]));
+        }
+        let footer = data.len() - FOOTER_LEN;
+        if data[footer + 24..] != [b'P', b'E', b'F', b'A', 0, 0, 0, 0] {
+            return Err(Error::corrupt_header(footer + 24));
+        }
+        let field = |i: usize| {
+            let offset = footer + i * 8;
+            usize::try_from(u64::from_le_bytes(data[offset..offset + 8].try_into().expect("8 bytes"))).map_err(|_| Error::corrupt_header(offset))
+        };
+        let (toc_offset, names_offset, len) = (field(0)?, field(1)?, field(2)?);
+        if toc_offset < PREFIX_LEN || toc_offset > names_offset || names_offset > footer {
+            return Err(Error::corrupt_header(footer));
+        }
+
+        let toc = if len == 0 { None } else { Some(EliasFano::load(&data[toc_offset..names_offset])?) };
+        if toc.as_ref().map_or(0, |toc| toc.len()) != len {
+            return Err(Error::corrupt_header(footer + 16));
+        }
+
+        let mut names = HashMap::new();
+        let mut position = names_offset;
+        for index in 0..len {
+            let name_len = data[..footer].get(position..position + 8).ok_or(Error::truncated_data(footer, position + 8))?;
+            let name_len = usize::try_from(u64::from_le_bytes(name_len.try_into().expect("8 bytes"))).map_err(|_| Error::corrupt_header(position))?;
+            let end = (position + 8).checked_add(name_len).filter(|end| *end <= footer)
+                .ok_or(Error::truncated_data(footer, (position + 8).saturating_add(name_len)))?;
+            let name = std::str::from_utf8(&data[position + 8..end]).map_err(|_| Error::corrupt_header(position + 8))?;
+            if !name.is_empty() && names.insert(name, index).is_some() {
+                return Err(Error::corrupt_header(position));
+            }
+            position = end;
+        }
+
+        Ok(Self { data, toc, names, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get(&self, index: usize) -> Result<EliasFano<Cow<'a, [u64]>>, Error> {
+        EliasFano::load(self.blob(index)?)
+    }
+
+    /// Like `get` but reads only the sequence's header until it is first queried.
+    pub fn get_lazy(&self, index: usize) -> Result<LazyEliasFano<'a>, Error> {
+        LazyEliasFano::open(self.blob(index)?)
+    }
+
+    /// The bytes from where sequence `index` starts.
+    fn blob(&self, index: usize) -> Result<&'a [u8], Error> {
+        let offset = self.toc.as_ref()
+            .and_then(|toc| toc.get(index))
+            .filter(|_| index < self.len)
+            .ok_or(Error::index_out_of_range(index, self.len))?;
+        self.data.get(offset..).ok_or(Error::truncated_data(self.data.len(), offset))
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<Result<EliasFano<Cow<'a, [u64]>>, Error>> {
+        self.index_of(name).map(|index| self.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+
+    #[test]
+    fn write_and_read_archive() {
+        let lists: Vec<Vec<usize>> = (1..50).map(|k| (0..k * 10).map(|i| i * k).collect()).collect();
+        let mut writer = ArchiveWriter::new(vec![]).expect("writer");
+        for (k, list) in lists.iter().enumerate() {
+            let ef = EliasFano::new(list).expect("elias fano encoding");
+            if k % 2 == 0 {
+                writer.add_named(&format!("list-{}", k), &ef).expect("added");
+            } else {
+                writer.add(&ef).expect("added");
+            }
+        }
+        let data = writer.finish().expect("finished");
+
+        let archive = Archive::new(&data).expect("archive");
+        assert_eq!(archive.len(), lists.len());
+        for (k, list) in lists.iter().enumerate() {
+            assert_eq!(&archive.get(k).expect("sequence").iter().collect::<Vec<_>>(), list);
+        }
+        let named = archive.get_named("list-10").expect("present").expect("sequence");
+        assert_eq!(named.iter().collect::<Vec<_>>(), lists[10]);
+        assert!(archive.get_named("list-11").is_none());
+        assert!(archive.get(lists.len()).is_err());
+        let lazy = archive.get_lazy(20).expect("opened");
+        assert_eq!((lazy.len(), lazy.is_loaded()), (lists[20].len(), false));
+        assert_eq!(lazy.sequence().expect("loaded").iter().collect::<Vec<_>>(), lists[20]);
+    }
+
+    #[test]
+    fn empty_archive() {
+        let data = ArchiveWriter::new(vec![]).expect("writer").finish().expect("finished");
+        let archive = Archive::new(&data).expect("archive");
+        assert!(archive.is_empty());
+        assert!(archive.get(0).is_err());
+        assert!(Archive::new(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn rejects_duplicate_names_and_bad_footers() {
+        let ef = EliasFano::new([1, 2, 3]).expect("elias fano encoding");
+        let mut writer = ArchiveWriter::new(vec![]).expect("writer");
+        writer.add_named("a", &ef).expect("added");
+        writer.add(&ef).expect("added");
+        writer.add(&ef).expect("added");
+        assert_eq!(writer.add_named("a", &ef).expect_err("duplicate").kind(), io::ErrorKind::InvalidInput);
+        let data = writer.finish().expect("finished");
+        let footer = data.len() - FOOTER_LEN;
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(Archive::new(&bad_magic).map(|_| ()).map_err(|e| e.kind().clone()), Err(ErrorKind::CorruptHeader { offset: 0 })));
+
+        // Rename the second, unnamed, sequence to the first's name
+        let names_offset = u64::from_le_bytes(data[footer + 8..footer + 16].try_into().expect("8 bytes")) as usize;
+        let mut duplicate = data[..names_offset].to_vec();
+        for name in ["a", "a", ""] {
+            duplicate.extend_from_slice(&(name.len() as u64).to_le_bytes());
+            duplicate.extend_from_slice(name.as_bytes());
+        }
+        duplicate.extend_from_slice(&data[footer..]);
+        assert!(matches!(Archive::new(&duplicate).map(|_| ()).map_err(|e| e.kind().clone()), Err(ErrorKind::CorruptHeader { .. })));
+        assert!(Archive::new(&data).is_ok());
+
+        for i in 0..3 {
+            let mut huge = data.clone();
+            huge[footer + i * 8..footer + i * 8 + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            assert!(Archive::new(&huge).is_err());
+        }
+    }
+}
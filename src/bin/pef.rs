@@ -0,0 +1,125 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use pef::{describe, EliasFano};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &str = "\
+usage: pef <command> [--binary] <args>
+
+  encode [--binary] <input> <output>   encode integers into an Elias-Fano blob
+  decode [--binary] <input> <output>   decode a blob back into integers
+  inspect <blob>                       print the blob's header
+  stats [--binary] <input>             compare the encoding with raw and gzip sizes
+
+Integers are whitespace separated text, or little-endian u64s with --binary. A path of - is
+stdin or stdout.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let binary = args.iter().any(|a| a == "--binary");
+    let args: Vec<&str> = args.iter().map(String::as_str).filter(|a| *a != "--binary").collect();
+    match args.as_slice() {
+        ["encode", input, output] => {
+            let ids = parse_ids(&read(input)?, binary)?;
+            let ef = EliasFano::from_u64s(&ids).map_err(|e| e.to_string())?;
+            write(output, &ef.as_bytes())
+        }
+        ["decode", input, output] => {
+            let ef = EliasFano::from_bytes_owned(&read(input)?).map_err(|e| e.to_string())?;
+            write(output, &format_ids(ef.iter_u64(), binary))
+        }
+        ["inspect", blob] => {
+            let metadata = describe(&read(blob)?).map_err(|e| e.to_string())?;
+            println!("{:#?}", metadata);
+            Ok(())
+        }
+        ["stats", input] => {
+            let ids = parse_ids(&read(input)?, binary)?;
+            let ef = EliasFano::from_u64s(&ids).map_err(|e| e.to_string())?;
+            print_stats(&ids, &ef).map_err(|e| e.to_string())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    let mut data = vec![];
+    let result = if path == "-" { io::stdin().read_to_end(&mut data).map(|_| ()) } else { fs::read(path).map(|d| data = d) };
+    result.map_err(|e| format!("{}: {}", path, e))?;
+    Ok(data)
+}
+
+fn write(path: &str, data: &[u8]) -> Result<(), String> {
+    let result = if path == "-" { io::stdout().write_all(data) } else { fs::write(path, data) };
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+fn parse_ids(data: &[u8], binary: bool) -> Result<Vec<u64>, String> {
+    if binary {
+        if data.len() % 8 != 0 {
+            return Err(format!("binary input of {} bytes is not a whole number of u64s", data.len()));
+        }
+        return Ok(data.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("chunks of 8"))).collect());
+    }
+    let text = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+    text.split_whitespace()
+        .map(|word| word.parse().map_err(|_| format!("not an integer: {}", word)))
+        .collect()
+}
+
+fn format_ids(ids: impl Iterator<Item=u64>, binary: bool) -> Vec<u8> {
+    if binary {
+        ids.flat_map(|id| id.to_le_bytes()).collect()
+    } else {
+        ids.map(|id| format!("{}\n", id)).collect::<String>().into_bytes()
+    }
+}
+
+fn print_stats(ids: &[u64], ef: &EliasFano<Vec<u64>>) -> io::Result<()> {
+    let raw = format_ids(ids.iter().copied(), true);
+    let mut gzip = GzEncoder::new(vec![], Compression::best());
+    gzip.write_all(&raw)?;
+    let gzip_len = gzip.finish()?.len();
+    let ef_len = ef.serialized_len();
+
+    println!("ids:          {}", ids.len());
+    println!("universe:     {}", ids.last().copied().unwrap_or(0));
+    println!("raw u64:      {} bytes", raw.len());
+    println!("gzip:         {} bytes ({:.2}x)", gzip_len, raw.len() as f64 / gzip_len as f64);
+    println!("elias-fano:   {} bytes ({:.2}x)", ef_len, raw.len() as f64 / ef_len as f64);
+    println!("bits per id:  {:.2}", ef_len as f64 * 8.0 / ids.len() as f64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_text_and_binary() {
+        assert_eq!(parse_ids(b" 1\n2 \t30\n", false), Ok(vec![1, 2, 30]));
+        assert!(parse_ids(b"1 x", false).is_err());
+        assert_eq!(parse_ids(&format_ids(vec![7, 1 << 40].into_iter(), true), true), Ok(vec![7, 1 << 40]));
+        assert!(parse_ids(&[0; 7], true).is_err());
+    }
+
+    #[test]
+    fn format_text() {
+        assert_eq!(format_ids(vec![1, 2].into_iter(), false), b"1\n2\n");
+    }
+
+    #[test]
+    fn unknown_command_prints_usage() {
+        assert_eq!(run(&["frobnicate".to_string()]), Err(USAGE.to_string()));
+    }
+}
@@ -1,5 +1,8 @@
 use std::fmt::{Debug, Formatter};
+use std::io::{self, Read, Write};
 use crate::errors::Error;
+use std::borrow::Cow;
+use std::convert::TryInto;
 
 /*
 Appending bits happens right-to-left in each u64, but left-to-right in the vec. So imaging
@@ -25,10 +28,10 @@ print : 11110000 01110000
 
  */
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub struct Bits<V: AsRef<[u64]>> {
-    bits: V,
-    current_location: usize,
+    pub(crate) bits: V,
+    pub(crate) current_location: usize,
 }
 
 impl<V: AsRef<[u64]>> Debug for Bits<V> {
@@ -42,22 +45,36 @@ impl<V: AsRef<[u64]>> Debug for Bits<V> {
 }
 
 
+impl Default for Bits<Vec<u64>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Bits<Vec<u64>> {
     pub fn new() -> Self {
         Self { bits: vec![0], current_location: 0 }
     }
 
+    /// Room for appending `num_bits` bits without reallocating.
+    pub fn with_capacity(num_bits: usize) -> Self {
+        // Appending always leaves a word open after the last full one
+        let mut bits = Vec::with_capacity(num_bits / 64 + 1);
+        bits.push(0);
+        Self { bits, current_location: 0 }
+    }
+
     pub fn append_ones(&mut self, mut number_of_ones: usize) -> &mut Self {
         while number_of_ones > 0 {
             let to_move = number_of_ones.min(64 - self.current_location);
             let last_u64 = self.bits.last_mut().unwrap();
-            *last_u64 = *last_u64 | ((u64::MAX >> (64 - to_move)) << self.current_location);
+            *last_u64 |= (u64::MAX >> (64 - to_move)) << self.current_location;
             self.current_location += to_move;
             if self.current_location >= 64 {
                 self.bits.push(0);
                 self.current_location %= 64;
             }
-            number_of_ones = if number_of_ones >= 64 { number_of_ones - 64 } else { 0 };
+            number_of_ones -= to_move;
         }
         self
     }
@@ -68,113 +85,385 @@ impl Bits<Vec<u64>> {
         self
     }
 
-    pub fn append_from(&mut self, other: u64, mut num_bits: usize) -> &mut Self {
+    pub fn append_from(&mut self, other: u64, num_bits: usize) -> &mut Self {
+        debug_assert!(num_bits <= 64, "at most a word at a time");
+        if num_bits == 0 {
+            return self;
+        }
         let other = other.reverse_bits() >> (64 - num_bits);
 
-        // Two cases here, (1) where the other bits overlap a boundary in our vec, and (2) where
-        // it does not
-        if self.current_location + num_bits <= 64 {
-            // no boundary overlap (case 2)
-            let last_u64 = self.bits.last_mut().unwrap();
-            let bit_mask = u64::MAX >> (64 - num_bits);
-            *last_u64 = *last_u64 | ((other & bit_mask) << self.current_location);
-            self.current_location += num_bits;
-
-            if self.current_location == 64 {
-                self.bits.push(0);
-                self.current_location = 0;
-            }
+        // Fill what's left of the current u64, then carry any overflow into a new one
+        let last_u64 = self.bits.last_mut().unwrap();
+        *last_u64 |= other << self.current_location;
+        let remaining_bits = 64 - self.current_location;
+        if num_bits >= remaining_bits {
+            self.bits.push(other.checked_shr(remaining_bits as u32).unwrap_or(0));
+            self.current_location = num_bits - remaining_bits;
         } else {
-            // boundary overlap (case 1)
-            // first fill current bit vec index
-            let last_u64 = self.bits.last_mut().unwrap();
-            let remaining_bits = 64 - self.current_location;
-            *last_u64 = *last_u64 | ((other << (64 - num_bits + remaining_bits)) << self.current_location);
-            self.current_location = 0;
-            self.bits.push(0);
-            let other = other >> remaining_bits;
-            num_bits -= remaining_bits;
-
-            // Then place the remaining bits in the new vec entry, same code as case 2
-            let last_u64 = self.bits.last_mut().unwrap();
-            let bit_mask = u64::MAX >> (64 - num_bits);
-            *last_u64 = *last_u64 | ((other & bit_mask) << self.current_location);
             self.current_location += num_bits;
-
-            if self.current_location == 64 {
-                self.bits.push(0);
-                self.current_location = 0;
-            }
         }
         self
     }
 }
 
+impl Bits<Vec<u64>> {
+    /// Sets the bit at `position`, which has to have been appended already.
+    pub(crate) fn set_1(&mut self, position: usize) {
+        self.bits[position / 64] |= 1 << (position % 64);
+    }
+
+    /// Moves the words into another owning container, e.g. `Arc<[u64]>` to share them.
+    pub fn into_storage<S: AsRef<[u64]> + From<Vec<u64>>>(self) -> Bits<S> {
+        Bits { bits: self.bits.into(), current_location: self.current_location }
+    }
+
+    /// Writes out every completely filled word and drops it from memory, keeping only the word
+    /// still being appended to. Returns the bytes written.
+    pub(crate) fn spill_full_words(&mut self, writer: &mut impl Write) -> io::Result<usize> {
+        let full = self.bits.len() - 1;
+        for word in self.bits.drain(..full) {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        Ok(full * 8)
+    }
+
+    /// Reads `byte_len` bytes as written by `write_to`. Bits read this way are for querying, not
+    /// for appending to.
+    pub fn read_from(reader: &mut impl Read, byte_len: usize) -> io::Result<Self> {
+        if byte_len % 8 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bits length is not a multiple of 8"));
+        }
+        let mut buffer = [0u8; 512];
+        // Don't trust byte_len for the allocation, a corrupt header could ask for anything
+        let mut bits = Vec::with_capacity((byte_len / 8).min(1 << 16));
+        let mut remaining = byte_len;
+        while remaining > 0 {
+            let chunk = &mut buffer[..remaining.min(512)];
+            reader.read_exact(chunk)?;
+            bits.extend(chunk.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("chunks of 8"))));
+            remaining -= chunk.len();
+        }
+        Ok(Self::from_words(bits))
+    }
+}
+
 impl<V: AsRef<[u64]>> Bits<V> {
     pub fn select_1(&self, index: usize) -> Option<usize> {
-        let mut total = 0;
+        let mut remaining = index;
         for (vec_index, i) in self.bits.as_ref().iter().enumerate() {
             let c = i.count_ones() as usize;
-            if total + c >= index {
-                // It's in our current u64, so drop down and count
-                for bit_index in 0..63 {
-                    if i & (1 << bit_index) == 1 << bit_index {
-                        total += 1;
-                    }
-                    if total == index + 1 {
-                        return Some(64 * vec_index + bit_index);
-                    }
-                }
+            if remaining < c {
+                // It's in our current u64, so clear the lower set bits and take the next one
+                return Some(64 * vec_index + nth_set_bit(*i, remaining));
             }
+            remaining -= c;
         }
         None
     }
 
     pub fn select_0(&self, index: usize) -> Option<usize> {
-        let mut total = 0;
+        let mut remaining = index;
         for (vec_index, i) in self.bits.as_ref().iter().enumerate() {
             let c = i.count_zeros() as usize;
-            if total + c >= index {
-                // It's in our current u64, so drop down and count
-                for bit_index in 0..63 {
-                    if ((1 << bit_index) & i) ^ (1 << bit_index) == 1 << bit_index {
-                        total += 1;
-                    }
-                    if total == index + 1 {
-                        return Some(64 * vec_index + bit_index);
-                    }
-                }
+            if remaining < c {
+                return Some(64 * vec_index + nth_set_bit(!*i, remaining));
             }
+            remaining -= c;
         }
         None
     }
 
+    /// Like `select_0` but counts only the zeros at or after `from`, so moving forward from a known
+    /// position costs the distance covered rather than a scan from the start.
+    pub fn select_0_from(&self, from: usize, index: usize) -> Option<usize> {
+        let words = self.bits.as_ref();
+        let mut vec_index = from / 64;
+        let mut word = !*words.get(vec_index)? & (u64::MAX << (from % 64));
+        let mut remaining = index;
+        loop {
+            let c = word.count_ones() as usize;
+            if remaining < c {
+                return Some(64 * vec_index + nth_set_bit(word, remaining));
+            }
+            remaining -= c;
+            vec_index += 1;
+            word = !*words.get(vec_index)?;
+        }
+    }
+
+    /// Like `select_1` but counts only the ones at or after `from`, so a scan can resume where it
+    /// left off.
+    pub fn select_1_from(&self, from: usize, index: usize) -> Option<usize> {
+        let words = self.bits.as_ref();
+        let mut vec_index = from / 64;
+        let mut word = *words.get(vec_index)? & (u64::MAX << (from % 64));
+        let mut remaining = index;
+        loop {
+            let c = word.count_ones() as usize;
+            if remaining < c {
+                return Some(64 * vec_index + nth_set_bit(word, remaining));
+            }
+            remaining -= c;
+            vec_index += 1;
+            word = *words.get(vec_index)?;
+        }
+    }
+
+    /// Position of the first zero bit at or after `from`.
+    pub fn next_0(&self, from: usize) -> Option<usize> {
+        let words = self.bits.as_ref();
+        let mut vec_index = from / 64;
+        let mut word = !*words.get(vec_index)? & (u64::MAX << (from % 64));
+        while word == 0 {
+            vec_index += 1;
+            word = !*words.get(vec_index)?;
+        }
+        Some(64 * vec_index + word.trailing_zeros() as usize)
+    }
+
+    /// Position of the first one bit at or after `from`.
+    pub fn next_1(&self, from: usize) -> Option<usize> {
+        let words = self.bits.as_ref();
+        let mut vec_index = from / 64;
+        let mut word = *words.get(vec_index)? & (u64::MAX << (from % 64));
+        while word == 0 {
+            vec_index += 1;
+            word = *words.get(vec_index)?;
+        }
+        Some(64 * vec_index + word.trailing_zeros() as usize)
+    }
+
     pub fn slice(&self, start: usize, end: usize) -> Option<u64> {
-        let started = *self.bits.as_ref().get(start / 64)? >> (start % 64);
-        Some((started << (64 - (end - start))).reverse_bits())
+        let len = end.checked_sub(start)?;
+        if len == 0 {
+            return Some(0);
+        }
+        if len > 64 {
+            return None;
+        }
+        let words = self.bits.as_ref();
+        let offset = start % 64;
+        let low = *words.get(start / 64)?;
+        let high = if offset + len > 64 { *words.get(start / 64 + 1)? } else { 0 };
+        Some((extract(low, high, offset, len) << (64 - len)).reverse_bits())
+    }
+
+    /// Decodes `out.len()` consecutive `width`-bit fields starting at bit `start`, the bulk form of
+    /// calling `slice` once per field. Returns false, leaving `out` untouched, if the fields run past
+    /// the end or `width` is over 64.
+    pub fn unpack(&self, start: usize, width: usize, out: &mut [u64]) -> bool {
+        let words = self.bits.as_ref();
+        let end = out.len().checked_mul(width).and_then(|len| len.checked_add(start));
+        if width > 64 || end.map_or(true, |end| end > words.len().saturating_mul(64)) {
+            return false;
+        }
+        if width == 0 {
+            out.fill(0);
+        } else if !out.is_empty() {
+            unpack_fields(words, start, width, out);
+        }
+        true
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
         self.bits.as_ref().iter().flat_map(|i| i.to_le_bytes()).collect()
     }
+
+    /// The same bytes as `as_bytes`, borrowed from the words on little-endian targets.
+    pub fn byte_view(&self) -> Cow<'_, [u8]> {
+        if cfg!(target_endian = "little") {
+            Cow::Borrowed(bytes_from_words(self.bits.as_ref()))
+        } else {
+            Cow::Owned(self.as_bytes())
+        }
+    }
+
+    /// Copies the words into owned storage, detaching them from whatever buffer backs `self`.
+    pub fn to_owned(&self) -> Bits<Vec<u64>> {
+        Bits { bits: self.bits.as_ref().to_vec(), current_location: self.current_location }
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.bits.as_ref().len() * 8
+    }
+
+    /// Writes the same bytes as `as_bytes`, staging through a small stack buffer.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<usize> {
+        let mut buffer = [0u8; 512];
+        for chunk in self.bits.as_ref().chunks(buffer.len() / 8) {
+            for (word, out) in chunk.iter().zip(buffer.chunks_exact_mut(8)) {
+                out.copy_from_slice(&word.to_le_bytes());
+            }
+            writer.write_all(&buffer[..chunk.len() * 8])?;
+        }
+        Ok(self.byte_len())
+    }
+}
+
+impl<V: AsRef<[u64]>> Bits<V> {
+    /// Wraps words that were already read or mapped, for querying rather than appending.
+    pub(crate) fn from_words(bits: V) -> Self {
+        Self { bits, current_location: 0 }
+    }
 }
 
 impl<'a> Bits<&'a [u64]> {
     pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
-        if data.len() % 8 != 0 {
-            return Err(Error::invalid_bits_data(data.len()));
-        }
-        Ok(Self {
-            bits: unsafe { std::mem::transmute::<_, &[u64]>(data) },
-            current_location: 0,
-        })
+        Ok(Self::from_words(words_from_bytes(data)?))
+    }
+}
+
+impl<'a> Bits<Cow<'a, [u64]>> {
+    /// Borrows `data` in place when it is suitably aligned, otherwise copies it.
+    pub fn load(data: &'a [u8]) -> Result<Self, Error> {
+        let bits = match words_from_bytes(data) {
+            Ok(words) => Cow::Borrowed(words),
+            Err(_) if data.len() % 8 == 0 => Cow::Owned(
+                data.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("chunks of 8"))).collect()
+            ),
+            Err(e) => return Err(e),
+        };
+        Ok(Self::from_words(bits))
+    }
+
+    pub fn into_owned(self) -> Bits<Vec<u64>> {
+        Bits { bits: self.bits.into_owned(), current_location: self.current_location }
+    }
+}
+
+impl<'a> From<Bits<&'a [u64]>> for Bits<Cow<'a, [u64]>> {
+    fn from(bits: Bits<&'a [u64]>) -> Self {
+        Self { bits: Cow::Borrowed(bits.bits), current_location: bits.current_location }
+    }
+}
+
+impl From<Bits<Vec<u64>>> for Bits<Cow<'static, [u64]>> {
+    fn from(bits: Bits<Vec<u64>>) -> Self {
+        Self { bits: Cow::Owned(bits.bits), current_location: bits.current_location }
+    }
+}
+
+#[cfg(not(feature = "safe"))]
+pub(crate) fn words_from_bytes(data: &[u8]) -> Result<&[u64], Error> {
+    if data.len() % 8 != 0 || (data.as_ptr() as usize) % 8 != 0 {
+        return Err(Error::invalid_bits_data(data.len()));
+    }
+    // Length and alignment were checked above, so every u64 read stays within `data`
+    Ok(unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u64, data.len() / 8) })
+}
+
+/// Same checks as the default version, done by bytemuck so this crate has no unsafe code.
+#[cfg(feature = "safe")]
+pub(crate) fn words_from_bytes(data: &[u8]) -> Result<&[u64], Error> {
+    bytemuck::try_cast_slice(data).map_err(|_| Error::invalid_bits_data(data.len()))
+}
+
+#[cfg(not(feature = "safe"))]
+fn bytes_from_words(words: &[u64]) -> &[u8] {
+    // Any u64 is also 8 valid bytes, and u8 needs no alignment
+    unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) }
+}
+
+#[cfg(feature = "safe")]
+fn bytes_from_words(words: &[u64]) -> &[u8] {
+    bytemuck::cast_slice(words)
+}
+
+/// The `len` bits starting at `offset` of `low`, continuing into `high`, in the low bits of the
+/// result. `len` is 1 to 64. BMI2 is picked at compile time, e.g. with `-C target-cpu=native`:
+/// detecting it at run time on every field cost as much as `pext` saved.
+fn extract(low: u64, high: u64, offset: usize, len: usize) -> u64 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "safe")))]
+    {
+        // The build targets CPUs with BMI2
+        return unsafe { extract_bmi2(low, high, offset, len) };
+    }
+    #[allow(unreachable_code)]
+    extract_portable(low, high, offset, len)
+}
+
+fn extract_portable(low: u64, high: u64, offset: usize, len: usize) -> u64 {
+    let mut raw = low >> offset;
+    if offset + len > 64 {
+        raw |= high << (64 - offset);
+    }
+    raw
+}
+
+/// One `pext` per word the field touches, in place of the shifts and branch.
+#[cfg(all(target_arch = "x86_64", target_feature = "bmi2", not(feature = "safe")))]
+unsafe fn extract_bmi2(low: u64, high: u64, offset: usize, len: usize) -> u64 {
+    use std::arch::x86_64::_pext_u64;
+    let mask = u64::MAX >> (64 - len);
+    let from_low = 64 - offset;
+    let raw = _pext_u64(low, mask << offset);
+    if len > from_low {
+        raw | (_pext_u64(high, mask >> from_low) << from_low)
+    } else {
+        raw
+    }
+}
+
+/// Branch-free per field so the compiler can vectorize it. `unpack` has checked the fields are in
+/// bounds and `width` is 1 to 64.
+fn unpack_fields(words: &[u64], start: usize, width: usize, out: &mut [u64]) {
+    let last = words.len() - 1;
+    for (i, value) in out.iter_mut().enumerate() {
+        let position = start + i * width;
+        let word = position / 64;
+        let offset = position % 64;
+        // Always read the next word, clamped at the end. Shifting in two steps keeps an offset of
+        // 0 from becoming a shift by 64, and bits beyond `width` are shifted out below anyway.
+        let raw = (words[word] >> offset) | ((words[(word + 1).min(last)] << 1) << (63 - offset));
+        *value = (raw << (64 - width)).reverse_bits();
     }
 }
 
+pub(crate) fn nth_set_bit(mut word: u64, n: usize) -> usize {
+    for _ in 0..n {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn extract_matches_portable() {
+        let (low, high) = (0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210);
+        for offset in 0..64 {
+            for len in 1..=64 {
+                assert_eq!(extract(low, high, offset, len) & (u64::MAX >> (64 - len)), extract_portable(low, high, offset, len) & (u64::MAX >> (64 - len)));
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_matches_slice() {
+        for width in [0, 1, 5, 13, 31, 63, 64] {
+            let mask = u64::MAX.checked_shr(64 - width as u32).unwrap_or(0);
+            let fields: Vec<u64> = (0..200u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) & mask).collect();
+            let mut bits = Bits::new();
+            fields.iter().for_each(|f| { bits.append_from(*f, width); });
+            let mut out = vec![u64::MAX; 150];
+            assert!(bits.unpack(50 * width, width, &mut out));
+            assert_eq!(out, fields[50..]);
+            assert!(!bits.unpack(50 * width + 1 + bits.byte_len() * 8, width.max(1), &mut out));
+        }
+        assert!(!Bits::new().unpack(0, 65, &mut [0]));
+    }
+
+    #[test]
+    fn with_capacity_is_exact() {
+        for num_bits in [0, 1, 63, 64, 65, 1000] {
+            let mut bits = Bits::with_capacity(num_bits);
+            bits.append_ones(num_bits);
+            assert_eq!(bits.bits.capacity(), bits.bits.len());
+        }
+    }
+
     #[test]
     fn can_append_bits_on_first_64_bits() {
         assert_eq!(Bits::new().append_from(7, 3), Bits::new().append_ones(3));
@@ -236,6 +525,28 @@ mod tests {
         )
     }
 
+    #[test]
+    fn select_0_from_counts_forward() {
+        let mut bits = Bits::new();
+        bits.append_zeros(3).append_ones(100).append_zeros(2).append_ones(3).append_zeros(1);
+        assert_eq!(bits.select_0_from(0, 3), bits.select_0(3));
+        assert_eq!(bits.select_0_from(2, 0), Some(2));
+        assert_eq!(bits.select_0_from(3, 1), Some(104));
+        assert_eq!(bits.select_0_from(104, 1), Some(108));
+        assert_eq!(bits.select_0_from(10_000, 0), None);
+    }
+
+    #[test]
+    fn select_1_from_counts_forward() {
+        let mut bits = Bits::new();
+        bits.append_ones(3).append_zeros(100).append_ones(2).append_zeros(3).append_ones(1);
+        assert_eq!(bits.select_1_from(0, 3), bits.select_1(3));
+        assert_eq!(bits.select_1_from(2, 0), Some(2));
+        assert_eq!(bits.select_1_from(3, 1), Some(104));
+        assert_eq!(bits.select_1_from(104, 1), Some(108));
+        assert_eq!(bits.select_1_from(10_000, 0), None);
+    }
+
     #[test]
     fn select_1_in_first_u64() {
         assert_eq!(
@@ -252,6 +563,16 @@ mod tests {
         )
     }
 
+    #[test]
+    fn select_1_after_full_u64() {
+        let mut bits = Bits::new();
+        bits.append_ones(70).append_zeros(3).append_ones(1);
+        assert_eq!(bits.select_1(63), Some(63));
+        assert_eq!(bits.select_1(69), Some(69));
+        assert_eq!(bits.select_1(70), Some(73));
+        assert_eq!(bits.select_0(1), Some(71));
+    }
+
     #[test]
     fn select_1_real_example() {
         let mut bits = Bits::new();
@@ -271,6 +592,78 @@ mod tests {
         )
     }
 
+    #[test]
+    fn slice_across_u64_boundary() {
+        let mut bits = Bits::new();
+        bits.append_zeros(60).append_from(0b101101, 6);
+        assert_eq!(bits.slice(60, 66), Some(0b101101));
+        assert_eq!(bits.slice(60, 60), Some(0));
+    }
+
+    #[test]
+    fn slice_rejects_bad_ranges() {
+        let mut bits = Bits::new();
+        bits.append_ones(200);
+        assert_eq!(bits.slice(10, 5), None);
+        assert_eq!(bits.slice(0, 65), None);
+        assert_eq!(bits.slice(0, 64), Some(u64::MAX));
+    }
+
+    #[test]
+    fn next_0_skips_ones() {
+        let mut bits = Bits::new();
+        bits.append_zeros(2).append_ones(100).append_zeros(1);
+        assert_eq!(bits.next_0(0), Some(0));
+        assert_eq!(bits.next_0(2), Some(102));
+        assert_eq!(bits.next_0(103), Some(103));
+    }
+
+    #[test]
+    fn to_owned_outlives_buffer() {
+        let mut bits = Bits::new();
+        bits.append_zeros(4).append_ones(4);
+        let owned = {
+            let data = bits.as_bytes();
+            Bits::from_bytes(data.as_slice()).expect("bits").to_owned()
+        };
+        assert_eq!(owned.slice(2, 6), Some(3));
+    }
+
+    #[test]
+    fn load_unaligned_copies() {
+        let mut bits = Bits::new();
+        bits.append_zeros(4).append_ones(4);
+        let mut data = vec![0];
+        data.extend(bits.as_bytes());
+        let loaded = Bits::load(&data[1..]).expect("bits");
+        assert!(matches!(loaded.bits, Cow::Owned(_)));
+        assert_eq!(loaded.slice(2, 6), Some(3));
+    }
+
+    #[test]
+    fn words_from_bytes_checks_length_and_alignment() {
+        let words = [1u64, 2];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let aligned = Bits::from_bytes(&bytes).map(|b| b.as_bytes());
+        if (bytes.as_ptr() as usize) % 8 == 0 {
+            assert_eq!(aligned.expect("aligned"), bytes);
+        }
+        assert!(words_from_bytes(&bytes[..15]).is_err());
+        let mut offset = vec![0];
+        offset.extend_from_slice(&bytes);
+        let start = (offset.as_ptr() as usize + 1) % 8 == 0;
+        assert_eq!(words_from_bytes(&offset[1..]).is_ok(), start);
+    }
+
+    #[test]
+    fn next_1_skips_zeros() {
+        let mut bits = Bits::new();
+        bits.append_ones(1).append_zeros(100).append_ones(1);
+        assert_eq!(bits.next_1(0), Some(0));
+        assert_eq!(bits.next_1(1), Some(101));
+        assert_eq!(bits.next_1(102), None);
+    }
+
     #[test]
     fn serialize_and_deserialize() {
         let mut bits = Bits::new();
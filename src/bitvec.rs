@@ -0,0 +1,88 @@
+use crate::bits::Bits;
+use crate::elias_fano::EliasFano;
+use bitvec::prelude::*;
+
+impl<V: AsRef<[u64]>> Bits<V> {
+    /// Views the words as bitvec bits, without copying. Bit `i` here is bit `i` of the view. The
+    /// view covers every word, so it runs past the last appended bit to the end of its word, and
+    /// over the spare word that owned bits keep.
+    pub fn as_bitslice(&self) -> &BitSlice<u64, Lsb0> {
+        self.bits.as_ref().view_bits()
+    }
+}
+
+/// Copies bits in the order the slice presents them, whatever its store and ordering, so the result
+/// can be appended to like any other owned `Bits`.
+impl<T: BitStore, O: BitOrder> From<&BitSlice<T, O>> for Bits<Vec<u64>> {
+    fn from(slice: &BitSlice<T, O>) -> Self {
+        let mut words: Vec<u64> = slice.chunks(64)
+            .map(|chunk| chunk.iter().by_vals().enumerate().fold(0, |word, (i, bit)| word | (bit as u64) << i))
+            .collect();
+        if slice.len() % 64 == 0 {
+            words.push(0);
+        }
+        Bits { bits: words, current_location: slice.len() % 64 }
+    }
+}
+
+/// Encodes the positions of the set bits.
+impl<T: BitStore, O: BitOrder> From<&BitSlice<T, O>> for EliasFano<Vec<u64>> {
+    fn from(slice: &BitSlice<T, O>) -> Self {
+        if slice.not_any() {
+            return Self::empty();
+        }
+        Self::new(slice.iter_ones()).expect("set bits come in increasing order")
+    }
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// A bit vector one longer than the largest value with the bit of every value set.
+    pub fn to_bitvec(&self) -> BitVec<u64, Lsb0> {
+        let len = self.last().map_or(0, |last| last + 1);
+        let mut bits = bitvec![u64, Lsb0; 0; len];
+        for value in self.iter() {
+            bits.set(value, true);
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_to_bitslice() {
+        let mut bits = Bits::new();
+        bits.append_zeros(3).append_ones(2).append_zeros(64).append_ones(1);
+        let slice = bits.as_bitslice();
+        assert_eq!(slice.iter_ones().collect::<Vec<_>>(), vec![3, 4, 69]);
+        assert_eq!(Bits::from(&slice[..70]), bits);
+    }
+
+    #[test]
+    fn bitslice_orderings_to_bits() {
+        let msb = bitvec![u8, Msb0; 1, 0, 1, 1];
+        let bits = Bits::from(msb.as_bitslice());
+        assert_eq!(bits.select_1(1), Some(2));
+        assert_eq!(bits.next_0(2), Some(4));
+
+        // Exactly one word keeps the spare word owned bits always have
+        let full = bitvec![u64, Lsb0; 1; 64];
+        let mut bits = Bits::from(full.as_bitslice());
+        bits.append_ones(1);
+        assert_eq!(bits.select_1(64), Some(64));
+    }
+
+    #[test]
+    fn elias_fano_from_set_bits() {
+        let mut bits = bitvec![u32, Msb0; 0; 1000];
+        for i in (5..1000).step_by(7) {
+            bits.set(i, true);
+        }
+        let ef = EliasFano::from(bits.as_bitslice());
+        assert_eq!(ef.iter().collect::<Vec<_>>(), (5..1000).step_by(7).collect::<Vec<_>>());
+        assert_eq!(ef.to_bitvec().iter_ones().collect::<Vec<_>>(), bits.iter_ones().collect::<Vec<_>>());
+        assert!(EliasFano::from(bitvec![0; 10].as_bitslice()).is_empty());
+    }
+}
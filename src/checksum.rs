@@ -0,0 +1,126 @@
+use std::io::{self, Read, Write};
+
+// CRC-32 (IEEE 802.3, the one zlib and PNG use), table driven.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !update(u32::MAX, data)
+}
+
+fn update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Running CRC-32 over data fed in pieces, for callers that can't go through `Crc32Writer` or
+/// `Crc32Reader`, such as async ones.
+pub(crate) struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { crc: u32::MAX }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc = update(self.crc, data);
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// Passes writes through to `inner` while keeping a running CRC-32 and byte count of them.
+pub(crate) struct Crc32Writer<W: Write> {
+    inner: W,
+    crc: u32,
+    written: usize,
+}
+
+impl<W: Write> Crc32Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, crc: u32::MAX, written: 0 }
+    }
+
+    /// Returns the inner writer, the checksum and the number of bytes written so far.
+    pub fn finish(self) -> (W, u32, usize) {
+        (self.inner, !self.crc, self.written)
+    }
+}
+
+impl<W: Write> Write for Crc32Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = update(self.crc, &buf[..n]);
+        self.written += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reading counterpart of `Crc32Writer`.
+pub(crate) struct Crc32Reader<R: Read> {
+    inner: R,
+    crc: u32,
+}
+
+impl<R: Read> Crc32Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, crc: u32::MAX }
+    }
+
+    /// Returns the inner reader and the checksum of everything read so far.
+    pub fn finish(self) -> (R, u32) {
+        (self.inner, !self.crc)
+    }
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn writer_matches_one_shot() {
+        let mut writer = Crc32Writer::new(vec![]);
+        writer.write_all(b"1234").expect("write");
+        writer.write_all(b"56789").expect("write");
+        let (out, crc, written) = writer.finish();
+        assert_eq!(out, b"123456789");
+        assert_eq!(crc, 0xCBF4_3926);
+        assert_eq!(written, 9);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use std::borrow::Cow;
+
+/// Encodes sorted ids straight to bytes, the same ones `EliasFano::as_bytes` gives. No ids is
+/// fine here and gives a blob `decompress` reads back as empty.
+pub fn compress(ids: &[usize]) -> Result<Vec<u8>, Error> {
+    if ids.is_empty() {
+        return Ok(EliasFano::empty().as_bytes());
+    }
+    Ok(EliasFano::from_sorted_slice(ids)?.as_bytes())
+}
+
+/// Decodes every id of a blob from `compress` or `as_bytes`, reading it in place when it is
+/// aligned.
+pub fn decompress(data: &[u8]) -> Result<Vec<usize>, Error> {
+    let ef = EliasFano::<Cow<'_, [u64]>>::load(data)?;
+    let mut ids = vec![0; ef.len()];
+    ef.decode_into(0, &mut ids);
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let ids: Vec<usize> = (0..10_000).map(|i| i * 7 + i % 3).collect();
+        assert_eq!(decompress(&compress(&ids).expect("compressed")).expect("decompressed"), ids);
+        assert_eq!(decompress(&compress(&[]).expect("compressed")).expect("decompressed"), Vec::<usize>::new());
+        assert!(compress(&[3, 2]).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+use crate::bits::Bits;
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::sequence::Strictness;
+use std::convert::{TryFrom, TryInto};
+
+pub const CHECKPOINT_MAGIC: [u8; 4] = *b"PEFC";
+
+pub const CHECKPOINT_LEN: usize = 32;
+
+/// Sequential position in an `EliasFano`. It remembers where the current element's bit is in the
+/// upper bits, so stepping forward and `advance_to` never restart from the beginning.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a, V: AsRef<[u64]>> {
+    ef: &'a EliasFano<V>,
+    index: usize,
+    upper_position: usize,
+}
+
+/// Decodes a sequence a chunk at a time into a reused buffer, see `EliasFano::iter_chunks`.
+#[derive(Debug, Clone)]
+pub struct Chunks<'a, V: AsRef<[u64]>> {
+    cursor: Cursor<'a, V>,
+    decoded: Vec<u64>,
+    chunk: Vec<usize>,
+}
+
+impl<'a, V: AsRef<[u64]>> Chunks<'a, V> {
+    /// The next `chunk_len` values, fewer at the end, or `None` once everything was decoded. The
+    /// slice is only valid until the next call, as the buffer is reused.
+    pub fn next_chunk(&mut self) -> Option<&[usize]> {
+        let count = self.cursor.next_chunk_u64(&mut self.decoded);
+        if count == 0 {
+            return None;
+        }
+        self.chunk.clear();
+        self.chunk.extend(self.decoded[..count].iter().map(|v| *v as usize));
+        Some(&self.chunk)
+    }
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Decodes the values `chunk_len` at a time into slices, e.g. for vectorized sums or compares.
+    /// Panics if `chunk_len` is 0.
+    pub fn iter_chunks(&self, chunk_len: usize) -> Chunks<'_, V> {
+        assert!(chunk_len > 0, "chunk_len must be positive");
+        Chunks { cursor: self.cursor(), decoded: vec![0; chunk_len], chunk: Vec::with_capacity(chunk_len) }
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, V> {
+        Cursor {
+            ef: self,
+            index: 0,
+            upper_position: self.upper_bits.next_1(0).unwrap_or(0),
+        }
+    }
+
+    /// A cursor on the value of rank `index`, or past the end if there is none.
+    pub fn cursor_at(&self, index: usize) -> Cursor<'_, V> {
+        match self.select_index().select_1(index).filter(|_| index < self.size) {
+            Some(upper_position) => Cursor { ef: self, index, upper_position },
+            None => Cursor { ef: self, index: self.size, upper_position: 0 },
+        }
+    }
+
+    /// `next_geq` for each of `probes`, answered in one forward pass of a single cursor when the
+    /// probes are sorted. A probe below the one before it restarts the pass, so unsorted probes
+    /// still get correct answers, just without the saving.
+    pub fn next_geq_many(&self, probes: &[usize]) -> Vec<Option<usize>> {
+        self.probe_many(probes, |cursor, probe| cursor.advance_to(probe))
+    }
+
+    /// The first value `>= value` at rank `rank_hint` or later, with its rank. The search walks on
+    /// from the hint instead of locating `value`'s bucket from scratch, so callers keeping their own
+    /// position, e.g. in a merge join, can feed the returned rank back in as the next hint.
+    pub fn next_geq_from(&self, rank_hint: usize, value: usize) -> Option<(usize, usize)> {
+        self.next_geq_from_u64(rank_hint, value as u64).map(|(rank, v)| (rank, v as usize))
+    }
+
+    pub fn next_geq_from_u64(&self, rank_hint: usize, value: u64) -> Option<(usize, u64)> {
+        let mut cursor = self.cursor_at(rank_hint);
+        let found = cursor.advance_to_u64(value)?;
+        Some((Cursor::position(&cursor), found))
+    }
+
+    /// A mask with bit `i` set when `queries[i]` is present, e.g. to filter a large candidate list
+    /// in a join. Sorted queries are answered in one merged pass, as in `next_geq_many`.
+    pub fn contains_many(&self, queries: &[usize]) -> Bits<Vec<u64>> {
+        let mut mask = Bits::with_capacity(queries.len());
+        self.probe_many(queries, |cursor, query| {
+            if cursor.advance_to(query) == Some(query) { mask.append_ones(1) } else { mask.append_zeros(1) };
+        });
+        mask
+    }
+
+    /// Runs `answer` for each probe on one cursor, restarting it whenever a probe goes backwards.
+    pub(crate) fn probe_many<T>(&self, probes: &[usize], mut answer: impl FnMut(&mut Cursor<'_, V>, usize) -> T) -> Vec<T> {
+        let mut cursor = self.cursor();
+        let mut previous = 0;
+        probes.iter().map(|&probe| {
+            if probe < previous {
+                cursor = self.cursor();
+            }
+            previous = probe;
+            answer(&mut cursor, probe)
+        }).collect()
+    }
+
+    /// Decodes the values from rank `start` on into `out`, as many as fit, and returns how many were
+    /// written. Pass a slice of `len() - start` to decode the rest of the sequence.
+    pub fn decode_into(&self, start: usize, out: &mut [usize]) -> usize {
+        trace_span!("pef::decode", start = start, len = out.len());
+        let mut cursor = self.cursor_at(start);
+        let mut buffer = [0u64; 256];
+        let mut written = 0;
+        while written < out.len() {
+            let wanted = (out.len() - written).min(buffer.len());
+            let count = cursor.next_chunk_u64(&mut buffer[..wanted]);
+            if count == 0 {
+                break;
+            }
+            out[written..written + count].iter_mut().zip(&buffer[..count]).for_each(|(o, v)| *o = *v as usize);
+            written += count;
+        }
+        written
+    }
+
+    /// Like `decode_into` for `u64` values, decoded straight into `out`.
+    pub fn decode_into_u64(&self, start: usize, out: &mut [u64]) -> usize {
+        trace_span!("pef::decode", start = start, len = out.len());
+        self.cursor_at(start).next_chunk_u64(out)
+    }
+
+    /// Decodes every value into a `Vec` sized up front, in chunks. Also available as
+    /// `Vec::from(&ef)`.
+    pub fn to_vec(&self) -> Vec<usize> {
+        let mut out = vec![0; self.size];
+        let written = self.decode_into(0, &mut out);
+        out.truncate(written);
+        out
+    }
+
+    pub fn to_vec_u64(&self) -> Vec<u64> {
+        let mut out = vec![0; self.size];
+        let written = self.decode_into_u64(0, &mut out);
+        out.truncate(written);
+        out
+    }
+
+    /// Iterates the values as `u64`, whole on every target.
+    pub fn iter_u64(&self) -> impl Iterator<Item=u64> + '_ {
+        let mut cursor = self.cursor();
+        std::iter::from_fn(move || cursor.next_u64())
+    }
+
+    /// Picks up a cursor from `Cursor::checkpoint`, failing with `CorruptHeader` if the checkpoint
+    /// is malformed, `UnsupportedVersion` if a newer one, or `UnexpectedCount` if it was taken on a
+    /// sequence of another length.
+    pub fn resume(&self, checkpoint: &[u8]) -> Result<Cursor<'_, V>, Error> {
+        if checkpoint.len() < CHECKPOINT_LEN {
+            return Err(Error::truncated_data(checkpoint.len(), CHECKPOINT_LEN));
+        }
+        if checkpoint.len() != CHECKPOINT_LEN || !checkpoint.starts_with(&CHECKPOINT_MAGIC) {
+            return Err(Error::corrupt_header(0));
+        }
+        if checkpoint[4] != 1 {
+            return Err(Error::unsupported_version(checkpoint[4]));
+        }
+        let field = |i: usize| {
+            usize::try_from(u64::from_le_bytes(checkpoint[i * 8..i * 8 + 8].try_into().expect("8 bytes"))).map_err(|_| Error::corrupt_header(i * 8))
+        };
+        let (index, upper_position, size) = (field(1)?, field(2)?, field(3)?);
+        if size != self.size {
+            return Err(Error::unexpected_count(self.size, size));
+        }
+        if index > self.size {
+            return Err(Error::corrupt_header(8));
+        }
+        // The current element's bit must be a one, and exactly `index` ones must come before it
+        if index < self.size && self.select_index().select_1(index) != Some(upper_position) {
+            return Err(Error::corrupt_header(16));
+        }
+        Ok(Cursor { ef: self, index, upper_position })
+    }
+}
+
+impl<'a, V: AsRef<[u64]>> Cursor<'a, V> {
+    /// Rank of the element `peek` would return.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    pub fn peek(&self) -> Option<usize> {
+        self.peek_u64().map(|v| v as usize)
+    }
+
+    pub fn peek_u64(&self) -> Option<u64> {
+        if self.index >= self.ef.size {
+            return None;
+        }
+        let l = self.ef.num_lower_bits;
+        let start = self.index.checked_mul(l)?;
+        let lower = self.ef.lower_bits.slice(start, start.checked_add(l)?)?;
+        let upper = self.upper_position.checked_sub(self.index)? as u64;
+        Some(upper.checked_shl(l as u32)? | lower)
+    }
+
+    /// Moves to the first element at or after the current one that is `>= value` and returns it,
+    /// without consuming it.
+    pub fn advance_to(&mut self, value: usize) -> Option<usize> {
+        self.advance_to_u64(value as u64).map(|v| v as usize)
+    }
+
+    pub fn advance_to_u64(&mut self, value: u64) -> Option<u64> {
+        let current = self.peek_u64()?;
+        if current >= value {
+            return Some(current);
+        }
+        let Ok(bucket) = usize::try_from(value.checked_shr(self.ef.num_lower_bits as u32).unwrap_or(0)) else {
+            self.index = self.ef.size;
+            return None;
+        };
+        let current_bucket = self.upper_position.checked_sub(self.index)?;
+        if bucket > current_bucket {
+            // Jump straight past every bucket below the target's, galloping from where we are
+            let Some(position) = self.ef.select_index().select_0_at_or_after(self.upper_position, bucket - 1) else {
+                self.index = self.ef.size;
+                return None;
+            };
+            self.index = position + 1 - bucket;
+            self.upper_position = self.ef.upper_bits.next_1(position).unwrap_or(0);
+            debug_assert!(self.index >= self.ef.size || self.ef.select_index().select_1(self.index) == Some(self.upper_position));
+        }
+        while self.peek_u64()? < value {
+            self.step();
+        }
+        self.peek_u64()
+    }
+
+    /// Consumes the current element like `next`, as a `u64`.
+    pub fn next_u64(&mut self) -> Option<u64> {
+        let value = self.peek_u64()?;
+        self.step();
+        Some(value)
+    }
+
+    /// Consumes the current element and every repeat of it, returning it once. The same as
+    /// `next_u64` unless the sequence allows repeats.
+    pub fn next_distinct_u64(&mut self) -> Option<u64> {
+        let value = self.next_u64()?;
+        if self.ef.strictness == Strictness::NonDecreasing {
+            while self.peek_u64() == Some(value) {
+                self.step();
+            }
+        }
+        Some(value)
+    }
+
+    /// Decodes up to `out.len()` values into `out`, consuming them, and returns how many there were.
+    /// The lower bits of the whole chunk are unpacked in one go, which is much faster than `next`
+    /// for scans. For a non-empty `out` it returns 0 exactly when `next` would return `None`, so a
+    /// loop can stop at the first 0.
+    pub fn next_chunk_u64(&mut self, out: &mut [u64]) -> usize {
+        let l = self.ef.num_lower_bits;
+        let count = out.len().min(self.ef.size.saturating_sub(self.index));
+        let out = &mut out[..count];
+        let unpacked = l < 64 && self.index.checked_mul(l).is_some_and(|start| self.ef.lower_bits.unpack(start, l, out));
+        if !unpacked {
+            // Corrupt lower bits somewhere in the chunk; decode one at a time up to them
+            return out.iter_mut().map_while(|slot| self.next_u64().map(|value| *slot = value)).count();
+        }
+        for (decoded, value) in out.iter_mut().enumerate() {
+            let Some(upper) = self.upper_position.checked_sub(self.index) else {
+                return decoded;
+            };
+            *value |= (upper as u64) << l;
+            self.step();
+        }
+        count
+    }
+
+    /// Serializes the position so it can be resumed with `EliasFano::resume`, e.g. after a restart.
+    pub fn checkpoint(&self) -> [u8; CHECKPOINT_LEN] {
+        let mut out = [0; CHECKPOINT_LEN];
+        out[..4].copy_from_slice(&CHECKPOINT_MAGIC);
+        out[4] = 1;
+        for (i, field) in [self.index, self.upper_position, self.ef.size].iter().enumerate() {
+            out[8 + i * 8..16 + i * 8].copy_from_slice(&(*field as u64).to_le_bytes());
+        }
+        out
+    }
+
+    fn step(&mut self) {
+        self.index += 1;
+        if self.index < self.ef.size {
+            // Loading checks there is a one per value, so only a bad encoder can run out
+            let next = self.ef.upper_bits.next_1(self.upper_position + 1);
+            debug_assert!(next.is_some(), "upper bits hold fewer ones than values");
+            self.upper_position = next.unwrap_or(0);
+        }
+    }
+}
+
+impl<'a, V: AsRef<[u64]>> Iterator for Cursor<'a, V> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.next_u64().map(|v| v as usize)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.ef.size - self.index.min(self.ef.size);
+        (remaining, Some(remaining))
+    }
+
+    fn fold<B, F: FnMut(B, usize) -> B>(mut self, init: B, mut f: F) -> B {
+        // A 0 from `next_chunk_u64` is where `next` would have stopped too
+        let mut buffer = [0u64; 256];
+        let mut accumulator = init;
+        loop {
+            let count = self.next_chunk_u64(&mut buffer);
+            if count == 0 {
+                return accumulator;
+            }
+            accumulator = buffer[..count].iter().fold(accumulator, |acc, v| f(acc, *v as usize));
+        }
+    }
+}
+
+impl<'a, V: AsRef<[u64]>> ExactSizeIterator for Cursor<'a, V> {}
+
+impl<V: AsRef<[u64]>> From<&EliasFano<V>> for Vec<usize> {
+    fn from(ef: &EliasFano<V>) -> Self {
+        ef.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+
+    #[test]
+    fn advance_to_moves_forward_only() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut cursor = ef.cursor();
+        assert_eq!(cursor.advance_to(3500), ef.next_geq(3500));
+        assert_eq!(cursor.position(), ef.rank(3500));
+        assert_eq!(cursor.advance_to(10), ef.next_geq(3500));
+        assert_eq!(cursor.next(), ef.next_geq(3500));
+        assert_eq!(cursor.advance_to(100_000), None);
+        assert_eq!(cursor.next(), None);
+    }
+
+    #[test]
+    fn cursor_at_starts_mid_sequence() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        assert_eq!(ef.cursor_at(600).collect::<Vec<_>>(), data[600..]);
+        assert_eq!(ef.cursor_at(1000).next(), None);
+    }
+
+    #[test]
+    fn next_geq_many_matches_next_geq() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data).expect("elias fano encoding");
+        let sorted: Vec<usize> = (0..7100).step_by(13).collect();
+        let expected: Vec<_> = sorted.iter().map(|p| ef.next_geq(*p)).collect();
+        assert_eq!(ef.next_geq_many(&sorted), expected);
+
+        let unsorted = [500, 20, 6999, 7000, 3];
+        let expected: Vec<_> = unsorted.iter().map(|p| ef.next_geq(*p)).collect();
+        assert_eq!(ef.next_geq_many(&unsorted), expected);
+    }
+
+    #[test]
+    fn next_geq_from_resumes_at_hint() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut rank = 0;
+        for probe in (0..7000).step_by(97) {
+            let (found, value) = ef.next_geq_from(rank, probe).expect("probe below the last value");
+            assert_eq!((found, Some(value)), (ef.rank(probe), ef.next_geq(probe)));
+            rank = found;
+        }
+        assert_eq!(ef.next_geq_from(600, 10), Some((600, data[600])));
+        assert_eq!(ef.next_geq_from(0, 8000), None);
+    }
+
+    #[test]
+    fn contains_many_masks_present_queries() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(&data).expect("elias fano encoding");
+        let queries: Vec<usize> = (0..7100).step_by(5).chain([8, 3]).collect();
+        let mask = ef.contains_many(&queries);
+        let expected: Vec<usize> = (0..queries.len()).filter(|i| data.contains(&queries[*i])).collect();
+        let set: Vec<usize> = std::iter::successors(mask.next_1(0), |p| mask.next_1(p + 1)).collect();
+        assert_eq!(set, expected);
+        assert!(expected.contains(&(queries.len() - 2)));
+    }
+
+    #[test]
+    fn chunked_decoding_matches_next() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut cursor = ef.cursor_at(10);
+        let mut chunk = [0u64; 300];
+        assert_eq!(cursor.next_chunk_u64(&mut chunk), 300);
+        assert_eq!(chunk.iter().map(|v| *v as usize).collect::<Vec<_>>(), data[10..310]);
+        assert_eq!(cursor.next(), Some(data[310]));
+        assert_eq!(ef.cursor_at(3).fold(Vec::new(), |mut all, v| { all.push(v); all }), data[3..]);
+        assert_eq!(ef.iter().sum::<usize>(), data.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn next_chunk_returns_0_only_at_the_end() {
+        for len in [1, 255, 256, 257, 600] {
+            let ef = EliasFano::new(0..len).expect("elias fano encoding");
+            let mut cursor = ef.cursor();
+            let mut chunk = [0u64; 256];
+            let mut total = 0;
+            loop {
+                let at_end = cursor.peek().is_none();
+                let count = cursor.next_chunk_u64(&mut chunk);
+                assert_eq!(count == 0, at_end, "len {} after {}", len, total);
+                if count == 0 {
+                    break;
+                }
+                total += count;
+            }
+            assert_eq!(total, len);
+            assert_eq!((cursor.next_chunk_u64(&mut chunk), cursor.next()), (0, None));
+            assert_eq!(ef.iter().fold(0, |count, _| count + 1), len);
+        }
+    }
+
+    #[test]
+    fn iter_chunks_covers_sequence() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut chunks = ef.iter_chunks(300);
+        let mut all = Vec::new();
+        while let Some(chunk) = chunks.next_chunk() {
+            assert_eq!(chunk.len(), if all.len() == 900 { 100 } else { 300 });
+            all.extend_from_slice(chunk);
+        }
+        assert_eq!(all, data);
+        assert_eq!(chunks.next_chunk(), None);
+    }
+
+    #[test]
+    fn to_vec_decodes_everything() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        assert_eq!(ef.to_vec(), data);
+        assert_eq!(Vec::from(&ef), data);
+        assert_eq!(ef.to_vec_u64().len(), 1000);
+        assert_eq!(ef.cursor_at(990).len(), 10);
+        assert!(EliasFano::empty().to_vec().is_empty());
+    }
+
+    #[test]
+    fn decode_into_fills_rank_ranges() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut all = vec![0; 1000];
+        assert_eq!(ef.decode_into(0, &mut all), 1000);
+        assert_eq!(all, data);
+        let mut tail = vec![0; 50];
+        assert_eq!(ef.decode_into(980, &mut tail), 20);
+        assert_eq!(tail[..20], data[980..]);
+        let mut range = vec![0u64; 300];
+        assert_eq!(ef.decode_into_u64(100, &mut range), 300);
+        assert_eq!(range.iter().map(|v| *v as usize).collect::<Vec<_>>(), data[100..400]);
+    }
+
+    #[test]
+    fn checkpoint_and_resume() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut cursor = ef.cursor();
+        cursor.advance_to(2000);
+        let checkpoint = cursor.checkpoint();
+        let expected: Vec<usize> = cursor.collect();
+
+        let resumed = ef.resume(&checkpoint).expect("resumed");
+        assert_eq!(resumed.collect::<Vec<_>>(), expected);
+
+        let other = EliasFano::new(vec![1, 2, 3]).expect("elias fano encoding");
+        assert!(other.resume(&checkpoint).is_err());
+        let mut corrupt = checkpoint;
+        corrupt[16] ^= 1;
+        assert!(ef.resume(&corrupt).is_err());
+
+        let kind = |checkpoint: &[u8]| ef.resume(checkpoint).map(|_| ()).map_err(|e| e.kind().clone());
+        let mut bad_magic = checkpoint;
+        bad_magic[0] = b'X';
+        assert!(matches!(kind(&bad_magic), Err(ErrorKind::CorruptHeader { offset: 0 })));
+        let mut newer = checkpoint;
+        newer[4] = 2;
+        assert!(matches!(kind(&newer), Err(ErrorKind::UnsupportedVersion(2))));
+        assert!(matches!(kind(&checkpoint[..8]), Err(ErrorKind::TruncatedData { .. })));
+    }
+}
@@ -1,122 +1,1091 @@
 use crate::bits::Bits;
+use crate::cursor::Cursor;
 use crate::errors::Error;
-use std::borrow::Borrow;
-use std::convert::TryInto;
+use crate::checksum::{crc32, Crc32, Crc32Reader, Crc32Writer};
+use crate::select_index::{LazySelectIndex, Select, SelectIndex, DEFAULT_SAMPLE_RATE};
+use crate::metadata::{align_up, describe, header_len, parse_header, read_checksum, verify_checksum, write_default_header, write_header, Codec, HeaderFields, Metadata, CHECKSUM_LEN, DEFAULT_ALIGNMENT, HEADER_LEN, NUM_LOWER_BITS_OFFSET, PREFIX_LEN, SIZE_OFFSET, UNIVERSE_OFFSET};
+use crate::sequence::{CompressedSequence, Strictness};
+use crate::set_ops::merge_disjoint;
+use std::borrow::{Borrow, Cow};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
 
 
 // Logic taken from https://www.antoniomallia.it/sorted-integers-compression-with-elias-fano-encoding.html
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EliasFano<V: AsRef<[u64]>> {
-    upper_bits: Bits<V>,
-    lower_bits: Bits<V>,
-    num_lower_bits: usize,
-    num_upper_bits: usize,
-    size: usize,
+    pub(crate) upper_bits: Bits<V>,
+    pub(crate) lower_bits: Bits<V>,
+    pub(crate) num_lower_bits: usize,
+    pub(crate) num_upper_bits: usize,
+    pub(crate) size: usize,
+    /// The declared largest value, at least the last one.
+    pub(crate) universe: u64,
+    /// Whether values may repeat, which set operations have to allow for.
+    pub(crate) strictness: Strictness,
+    pub(crate) upper_index: Arc<LazySelectIndex>,
+}
+
+/// Checks that `ids` strictly increase, as `new` requires, so a large input can be checked up
+/// front and the culprit reported where it came from. The error names the first pair out of order.
+pub fn validate_sorted(ids: &[usize]) -> Result<(), Error> {
+    match ids.windows(2).position(|pair| pair[0] >= pair[1]) {
+        Some(position) => Err(Error::out_of_order(position + 1, ids[position] as u64, ids[position + 1] as u64)),
+        None => Ok(()),
+    }
 }
 
 impl EliasFano<Vec<u64>> {
     pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
-        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+        Self::from_u64s(ids.into_iter().map(|i| *i.borrow() as u64))
+    }
+
+    /// Like `new`, with the ordering `ids` must follow given by `strictness`.
+    pub fn with_strictness(ids: impl IntoIterator<Item=impl Borrow<usize>>, strictness: Strictness) -> Result<Self, Error> {
+        Self::from_u64s_with_strictness(ids.into_iter().map(|i| *i.borrow() as u64), strictness)
+    }
+
+    /// Like `new` for `u64` ids, which cover the same range on every target. Together with the
+    /// other `_u64` methods this is the API for ids that may not fit a 32-bit `usize`.
+    pub fn from_u64s(ids: impl IntoIterator<Item=impl Borrow<u64>>) -> Result<Self, Error> {
+        Self::from_u64s_with_strictness(ids, Strictness::StrictlyIncreasing)
+    }
 
-        let size = ids.len();
+    pub fn from_u64s_with_strictness(ids: impl IntoIterator<Item=impl Borrow<u64>>, strictness: Strictness) -> Result<Self, Error> {
+        let ids: Vec<u64> = ids.into_iter().map(|i| *i.borrow()).collect();
 
-        if !ids.iter().zip(ids.iter().skip(1)).all(|(a, b)| a < b) {
-            return Err(Error::unsorted_ids());
+        if let Some(position) = ids.windows(2).position(|pair| strictness.out_of_order(pair[0], pair[1])) {
+            return Err(Error::out_of_order(position + 1, ids[position], ids[position + 1]));
         }
 
         let m = *ids.last().ok_or(Error::no_ids())?;
-        let n = ids.len();
-        let num_lower_bits = ((m as f64) / (n as f64)).log2().ceil() as usize;
-        let num_upper_bits = (n as f64).log2().ceil() as usize;
-        let upper_mask = u64::MAX >> (64 - num_upper_bits);
+        Self::encode(ids.len(), m, ids, strictness)
+    }
+
+    /// Like `new`, with the parameters chosen for values up to `universe` rather than the last id,
+    /// e.g. a segment of a known id range that later segments are appended after. The universe is
+    /// kept through serialization; ids above it are an error.
+    pub fn with_universe(ids: impl IntoIterator<Item=impl Borrow<usize>>, universe: usize) -> Result<Self, Error> {
+        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+        validate_sorted(&ids)?;
+        if ids.is_empty() {
+            return Err(Error::no_ids());
+        }
+        Self::encode(ids.len(), universe as u64, ids.iter().map(|i| *i as u64), Strictness::StrictlyIncreasing)
+    }
+
+    /// Like `new`, but validates and encodes straight from the slice instead of first copying the
+    /// ids, so a build holds only the input and the output.
+    pub fn from_sorted_slice(ids: &[usize]) -> Result<Self, Error> {
+        validate_sorted(ids)?;
 
-        let mut all_lower_bits = Bits::new();
+        let m = *ids.last().ok_or(Error::no_ids())? as u64;
+        Self::encode(ids.len(), m, ids.iter().map(|i| *i as u64), Strictness::StrictlyIncreasing)
+    }
+
+    /// Encodes `n` ids ordered as `strictness` requires, none above `m`, in one pass. Ids breaking
+    /// either rule or a count other than `n` are errors rather than a corrupt result.
+    pub(crate) fn encode(n: usize, m: u64, ids: impl IntoIterator<Item=u64>, strictness: Strictness) -> Result<Self, Error> {
+        trace_span!("pef::encode", size = n, universe = m);
+        let (num_lower_bits, num_upper_bits) = parameters(n, m);
 
-        let mut upper_bit_buckets: Vec<_> = (0..upper_mask).map(|_| 0).collect();
+        // Reserved exactly: the upper bits hold a one per id and a zero closing each bucket
+        let mut all_lower_bits = Bits::with_capacity(n * num_lower_bits);
+        let mut all_upper_bits = Bits::with_capacity(n + (m >> num_lower_bits) as usize + 1);
 
+        // Each id closes the buckets between the previous id's and its own with a zero apiece, then
+        // sets its bit. m >> num_lower_bits is at most about 2n, so buckets fit a usize.
+        let mut bucket = 0;
+        let mut previous = None;
+        let mut count = 0;
         for id in ids {
-            all_lower_bits.append_from(id as u64, num_lower_bits);
-            let upper_bits = ((id as u64) >> num_lower_bits) & upper_mask;
-            upper_bit_buckets[upper_bits as usize] += 1;
+            if let Some(p) = previous.filter(|p| strictness.out_of_order(*p, id)) {
+                return Err(Error::out_of_order(count, p, id));
+            }
+            if id > m {
+                return Err(Error::value_above_universe(id as usize, m as usize));
+            }
+            previous = Some(id);
+            count += 1;
+            all_lower_bits.append_from(id, num_lower_bits);
+            let high = (id >> num_lower_bits) as usize;
+            all_upper_bits.append_zeros(high - bucket).append_ones(1);
+            bucket = high;
         }
-
-        let mut all_upper_bits = Bits::new();
-        for bucket_count in upper_bit_buckets {
-            all_upper_bits.append_ones(bucket_count);
-            all_upper_bits.append_zeros(1);
+        // Zeros close the buckets up to the universe's, so the upper bits cover every value it allows
+        all_upper_bits.append_zeros((m >> num_lower_bits) as usize + 1 - bucket);
+        if count != n {
+            return Err(Error::unexpected_count(n, count));
         }
 
-        Ok(Self {
+        let ef = Self {
+            upper_index: Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE)),
             lower_bits: all_lower_bits,
             upper_bits: all_upper_bits,
             num_lower_bits,
             num_upper_bits,
-            size,
-        })
+            size: n,
+            universe: m,
+            strictness,
+        };
+        debug_assert!(ef.invariants_hold());
+        Ok(ef)
+    }
+
+    /// A sequence with no values, which `new` refuses to build. Conversions from containers that
+    /// can be empty use it.
+    pub(crate) fn empty() -> Self {
+        let mut upper_bits = Bits::new();
+        upper_bits.append_zeros(1);
+        let upper_index = Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE));
+        Self {
+            upper_bits,
+            lower_bits: Bits::new(),
+            num_lower_bits: 0,
+            num_upper_bits: 0,
+            size: 0,
+            universe: 0,
+            strictness: Strictness::StrictlyIncreasing,
+            upper_index,
+        }
+    }
+
+    /// Exact length of `as_bytes()` for `n` ids whose largest value is `universe`, without building
+    /// anything. Lengths past `usize::MAX`, which couldn't be built either, come out as it.
+    pub fn estimate_size(n: usize, universe: usize) -> usize {
+        let n = n.max(1);
+        let universe = universe as u64;
+        let (num_lower_bits, num_upper_bits) = parameters(n, universe);
+        // In u64, where shifting by num_lower_bits is defined whatever the width of usize
+        let upper_words = (n as u64 + (universe >> num_lower_bits) + 1) / 64 + 1;
+        let lower_words = (n as u64).saturating_mul(num_lower_bits as u64) / 64 + 1;
+        let upper_bits_len = usize::try_from(8 * upper_words).unwrap_or(usize::MAX);
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        let fields = HeaderFields { codec: Codec::EliasFano, size: n, num_upper_bits, num_lower_bits, upper_bits_len, universe, last: universe, select_sample_rate: DEFAULT_SAMPLE_RATE, strictness: Strictness::StrictlyIncreasing };
+        write_default_header(&mut header, &fields);
+        let total = (header.len() + CHECKSUM_LEN) as u64 + 8 * (upper_words + lower_words);
+        usize::try_from(total).unwrap_or(usize::MAX)
     }
 }
 
+impl EliasFano<Vec<u64>> {
+    /// Copies a blob into owned storage, so the result doesn't borrow `data` and its alignment
+    /// doesn't matter.
+    pub fn from_bytes_owned(data: &[u8]) -> Result<Self, Error> {
+        Self::read_from(&mut &data[..])
+    }
+
+    /// Moves the words into another owning container. `EliasFano<Arc<[u64]>>` is cheap to clone
+    /// and can be shared by every thread of a query pool.
+    pub fn into_storage<S: AsRef<[u64]> + From<Vec<u64>>>(self) -> EliasFano<S> {
+        EliasFano {
+            upper_bits: self.upper_bits.into_storage(),
+            lower_bits: self.lower_bits.into_storage(),
+            num_lower_bits: self.num_lower_bits,
+            num_upper_bits: self.num_upper_bits,
+            size: self.size,
+            universe: self.universe,
+            strictness: self.strictness,
+            upper_index: self.upper_index,
+        }
+    }
+
+    /// Reads a blob as written by `write_to`, consuming exactly its bytes from `reader`.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Error> {
+        let mut reader = Crc32Reader::new(reader);
+        let mut header = vec![0; PREFIX_LEN];
+        reader.read_exact(&mut header)?;
+        header.resize(header_len(&header), 0);
+        reader.read_exact(&mut header[PREFIX_LEN..])?;
+        let metadata = parse_header(&header)?;
+        if metadata.codec != Codec::EliasFano {
+            return Err(Error::unknown_codec(metadata.codec.tag()));
+        }
+
+        skip(&mut reader, metadata.upper_bits_offset - metadata.header_len)?;
+        let upper_bits = Bits::read_from(&mut reader, metadata.upper_bits_len)?;
+        skip(&mut reader, metadata.lower_bits_offset - metadata.upper_bits_offset - metadata.upper_bits_len)?;
+        let lower_bits = Bits::read_from(&mut reader, metadata.lower_bits_len)?;
+
+        let (reader, actual) = reader.finish();
+        if metadata.version >= 2 {
+            let mut checksum = [0; CHECKSUM_LEN];
+            reader.read_exact(&mut checksum)?;
+            let expected = read_checksum(checksum, metadata.version);
+            if actual != expected {
+                return Err(Error::checksum_mismatch(expected, actual));
+            }
+        }
+
+        Self::from_parts(&metadata, upper_bits, lower_bits)
+    }
+}
+
+/// Lower and upper bit counts for `n` ids up to `m`: `ceil(log2(m / n))` and `ceil(log2(n))`.
+/// Only integer math goes into them, so every platform picks the same and encodes the same bytes.
+pub(crate) fn parameters(n: usize, m: u64) -> (usize, usize) {
+    // The smallest l with n << l >= m. Capped so shifting a u64 by it stays defined
+    let num_lower_bits = match n {
+        0 if m == 0 => 0,
+        0 => 63,
+        _ => ceil_log2(m.div_ceil(n as u64) as u128).min(63),
+    };
+    (num_lower_bits, ceil_log2(n as u128))
+}
+
+pub(crate) fn ceil_log2(x: u128) -> usize {
+    if x <= 1 { 0 } else { (x - 1).ilog2() as usize + 1 }
+}
+
 impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Assembles a deserialized sequence, rejecting bit arrays that disagree with the header
+    /// rather than answering queries from them wrongly.
+    pub(crate) fn from_parts(metadata: &Metadata, upper_bits: Bits<V>, lower_bits: Bits<V>) -> Result<Self, Error> {
+        trace_span!("pef::load", size = metadata.size);
+        let corrupt = |field: usize| Error::corrupt_header(metadata.field_offset(field));
+        // Construction never picks more than 63, and values are shifted by it
+        if metadata.num_lower_bits > 63 {
+            return Err(corrupt(NUM_LOWER_BITS_OFFSET));
+        }
+        let lower_bits_needed = metadata.size.checked_mul(metadata.num_lower_bits).ok_or(corrupt(NUM_LOWER_BITS_OFFSET))?;
+        if lower_bits.byte_len() * 8 < lower_bits_needed {
+            return Err(corrupt(NUM_LOWER_BITS_OFFSET));
+        }
+        // One set bit per value, and a zero closing the last bucket after them
+        let ones: usize = upper_bits.bits.as_ref().iter().map(|w| w.count_ones() as usize).sum();
+        if ones != metadata.size || upper_bits.byte_len() * 8 == ones {
+            return Err(corrupt(SIZE_OFFSET));
+        }
+        let mut ef = Self {
+            upper_index: Arc::new(LazySelectIndex::new(metadata.select_sample_rate)),
+            upper_bits,
+            lower_bits,
+            num_lower_bits: metadata.num_lower_bits,
+            num_upper_bits: metadata.num_upper_bits,
+            size: metadata.size,
+            universe: 0,
+            strictness: metadata.strictness,
+        };
+        let last = ef.last_u64().unwrap_or(0);
+        ef.universe = metadata.universe.unwrap_or(last);
+        if ef.universe < last {
+            return Err(corrupt(UNIVERSE_OFFSET));
+        }
+        Ok(ef)
+    }
+
+    /// Checks what every encoder must leave true, for `debug_assert!`s after construction. Loaded
+    /// blobs aren't held to it: their lower bits aren't validated, so decoded values may not
+    /// increase. Values only have to increase strictly unless the sequence allows repeats.
+    pub(crate) fn invariants_hold(&self) -> bool {
+        let ones: usize = self.upper_bits.bits.as_ref().iter().map(|w| w.count_ones() as usize).sum();
+        let lower_bits_needed = self.size.checked_mul(self.num_lower_bits);
+        self.num_lower_bits <= 63
+            && ones == self.size
+            && lower_bits_needed.is_some_and(|needed| self.lower_bits.byte_len() * 8 >= needed)
+            && self.iter_u64().zip(self.iter_u64().skip(1)).all(|(a, b)| !self.strictness.out_of_order(a, b))
+    }
+
+    /// Value at `index`. Values above `usize::MAX`, only possible on 32-bit targets, are truncated;
+    /// `get_u64` returns them whole.
+    ///
+    /// There is deliberately no `Index<usize>` impl: `Index` must hand out a reference, and values
+    /// are decoded on each access rather than stored. Use `try_get` where `ef[i]` would be used.
     pub fn get(&self, index: usize) -> Option<usize> {
-        let lower = self.lower_bits.slice(
-            index * self.num_lower_bits,
-            (index + 1) * self.num_lower_bits,
-        )?;
-        let upper = self.upper_bits.select_1(index)? - index;
-        Some((((upper as u64) << self.num_lower_bits) | lower) as usize)
+        self.get_u64(index).map(|v| v as usize)
+    }
+
+    pub fn get_u64(&self, index: usize) -> Option<u64> {
+        if index >= self.size {
+            return None;
+        }
+        let start = index.checked_mul(self.num_lower_bits)?;
+        let lower = self.lower_bits.slice(start, start.checked_add(self.num_lower_bits)?)?;
+        let upper = self.select_index().select_1(index)?.checked_sub(index)?;
+        Some((upper as u64).checked_shl(self.num_lower_bits as u32)? | lower)
+    }
+
+    /// Like `get`, but tells an `index` past the end, `IndexOutOfRange`, from bit arrays that don't
+    /// decode, `CorruptStructure`, which matters for sequences loaded from untrusted data.
+    pub fn try_get(&self, index: usize) -> Result<usize, Error> {
+        self.try_get_u64(index).map(|v| v as usize)
+    }
+
+    pub fn try_get_u64(&self, index: usize) -> Result<u64, Error> {
+        if index >= self.size {
+            return Err(Error::index_out_of_range(index, self.size));
+        }
+        self.get_u64(index).ok_or_else(|| Error::corrupt_structure(index))
+    }
+
+    /// The `k`th smallest value, counting from 0. The same as `get`, named for quantile code.
+    pub fn kth(&self, k: usize) -> Option<usize> {
+        self.get(k)
+    }
+
+    /// Rank of the nearest-rank `p` quantile: the smallest rank with at least a `p` share of the
+    /// values at or below it. `None` when empty or `p` is outside `0.0..=1.0`.
+    pub fn rank_of_quantile(&self, p: f64) -> Option<usize> {
+        if self.is_empty() || !(0.0..=1.0).contains(&p) {
+            return None;
+        }
+        let rank = (p * self.len() as f64).ceil() as usize;
+        Some(rank.saturating_sub(1).min(self.len() - 1))
+    }
+
+    /// The `p` quantile of the stored values, e.g. `quantile(0.99)` for p99 of timestamps.
+    pub fn quantile(&self, p: f64) -> Option<usize> {
+        self.get(self.rank_of_quantile(p)?)
+    }
+
+    /// The declared largest value: the last one, unless a larger one was given with
+    /// `with_universe` or to `OutOfCoreBuilder`. Deserialized sequences keep it.
+    pub fn universe(&self) -> usize {
+        self.universe as usize
+    }
+
+    pub fn universe_u64(&self) -> u64 {
+        self.universe
+    }
+
+    /// Samples the select index every `rate` ones and zeros, rounded up to a power of two from 8 to
+    /// 65536. The default is 512; lower rates make `get` and `rank` faster for a bigger index. The
+    /// rate is serialized, so loading builds the same index. Sequences derived from
+    /// this one, e.g. by `rebase` or set operations, start again from the default.
+    pub fn with_select_sample_rate(mut self, rate: usize) -> Self {
+        self.upper_index = Arc::new(LazySelectIndex::new(rate));
+        self
+    }
+
+    /// Whether values may repeat, as chosen at construction and recorded in the header.
+    pub fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    pub fn select_sample_rate(&self) -> usize {
+        self.upper_index.sample_rate()
+    }
+
+    /// Builds the select index now. Otherwise the first `get`, `rank` or similar query builds it,
+    /// so sequences that are only loaded and written out again never do; warming up keeps that
+    /// cost out of the first query's latency.
+    pub fn build_select_index(&self) {
+        self.select_index();
+    }
+
+    /// The select index as a blob of its own, e.g. to keep next to a large immutable sequence so
+    /// that loading it skips the build. The blob records a CRC-32 of the upper bits it indexes, so
+    /// it only loads back onto the same sequence, and a new one can always be made from the
+    /// sequence alone, e.g. after the index format changes.
+    pub fn select_index_bytes(&self) -> Vec<u8> {
+        self.upper_index.get_or_build(self.upper_bits.bits.as_ref()).to_bytes(crc32(&self.upper_bits.byte_view()))
+    }
+
+    /// Installs an index from `select_index_bytes` instead of building one. It fails with
+    /// `ChecksumMismatch` if the blob is damaged or was made for other upper bits, and with
+    /// `UnsupportedVersion` for another index format version, in which case the index can just be
+    /// rebuilt. Counts are checked to be consistent, not recounted, so a crafted blob can make
+    /// queries wrong but can't make them panic.
+    pub fn load_select_index(&mut self, blob: &[u8]) -> Result<(), Error> {
+        let words = self.upper_bits.bits.as_ref();
+        let index = SelectIndex::from_bytes(words, crc32(&self.upper_bits.byte_view()), blob)?;
+        self.upper_index = Arc::new(LazySelectIndex::built_from(index));
+        Ok(())
+    }
+
+    pub(crate) fn select_index(&self) -> Select<'_> {
+        let words = self.upper_bits.bits.as_ref();
+        self.upper_index.get_or_build(words).over(words)
+    }
+
+    /// Smallest value, `get(0)` under another name.
+    pub fn first(&self) -> Option<usize> {
+        self.first_u64().map(|v| v as usize)
+    }
+
+    pub fn first_u64(&self) -> Option<u64> {
+        self.get_u64(0)
+    }
+
+    /// Largest value, found from the highest set upper bit rather than a select. Only the zero
+    /// words after that bit are scanned, which are more than a word or two when the universe is
+    /// well above the last value.
+    pub fn last(&self) -> Option<usize> {
+        self.last_u64().map(|v| v as usize)
+    }
+
+    pub fn last_u64(&self) -> Option<u64> {
+        let index = self.size.checked_sub(1)?;
+        let words = self.upper_bits.bits.as_ref();
+        let word_index = words.iter().rposition(|word| *word != 0)?;
+        let position = 64 * word_index + 63 - words[word_index].leading_zeros() as usize;
+        let upper = position.checked_sub(index)?;
+        let start = index.checked_mul(self.num_lower_bits)?;
+        let lower = self.lower_bits.slice(start, start.checked_add(self.num_lower_bits)?)?;
+        Some((upper as u64).checked_shl(self.num_lower_bits as u32)? | lower)
     }
 
     pub fn next_geq(&self, value: usize) -> Option<usize> {
-        let value = value as u64;
-        let upper_bits_bucket = (value >> self.num_lower_bits) & (u64::MAX >> (64 - self.num_upper_bits));
+        self.next_geq_u64(value as u64).map(|v| v as usize)
+    }
+
+    pub fn next_geq_u64(&self, value: u64) -> Option<u64> {
+        self.get_u64(self.rank_u64(value))
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.contains_u64(value as u64)
+    }
+
+    pub fn contains_u64(&self, value: u64) -> bool {
+        self.next_geq_u64(value) == Some(value)
+    }
+
+    /// `slice::binary_search` for `value`: `Ok` with its rank if stored, else `Err` with the rank it
+    /// would be inserted at. Answered with one `rank`, not a search over `get`.
+    pub fn binary_search(&self, value: usize) -> Result<usize, usize> {
+        let rank = self.rank(value);
+        if self.get(rank) == Some(value) { Ok(rank) } else { Err(rank) }
+    }
 
-        let start = if upper_bits_bucket == 0 {
+    /// `slice::partition_point`: the rank of the first value for which `pred` is false, given that
+    /// it holds for a prefix of the values.
+    pub fn partition_point(&self, pred: impl FnMut(usize) -> bool) -> usize {
+        self.as_view().partition_point(pred)
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.rank_u64(value as u64)
+    }
+
+    pub fn rank_u64(&self, value: u64) -> usize {
+        // A bucket past what a usize can count is past every stored value
+        let Ok(bucket) = usize::try_from(value.checked_shr(self.num_lower_bits as u32).unwrap_or(0)) else {
+            return self.size;
+        };
+
+        // Every value in an earlier bucket is smaller, so skip straight past them
+        let start = if bucket == 0 {
             0
         } else {
-            self.upper_bits.select_0(upper_bits_bucket as usize - 1)? - upper_bits_bucket as usize
+            match self.select_index().select_0(bucket - 1) {
+                Some(position) => position + 1 - bucket,
+                None => return self.size,
+            }
         };
 
-        for i in start..self.size {
-            let to_check = self.get(i);
-            if to_check >= Some(value as usize) {
-                return to_check;
-            }
-        }
-        None
+        (start..self.size)
+            .find(|i| self.get_u64(*i).map_or(true, |v| v >= value))
+            .unwrap_or(self.size)
+    }
+
+    /// How many times `value` is stored, at most once unless values may repeat.
+    pub(crate) fn occurrences(&self, value: u64) -> usize {
+        let end = value.checked_add(1).map_or(self.size, |next| self.rank_u64(next));
+        end.saturating_sub(self.rank_u64(value))
     }
 
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The bytes of `as_bytes` without assembling them, for hashing, vectored writes or sending.
+    pub fn as_byte_view(&self) -> ByteView<'_> {
+        let header = self.default_header();
+        debug_assert_eq!(align_up(header.len(), DEFAULT_ALIGNMENT), header.len(), "no padding after the header");
+        let upper_bits = self.upper_bits.byte_view();
+        let lower_bits = self.lower_bits.byte_view();
+        let mut crc = Crc32::new();
+        [&header[..], &upper_bits, &lower_bits].iter().for_each(|part| crc.update(part));
+        ByteView { header, upper_bits, lower_bits, checksum: crc.finish().to_le_bytes() }
+    }
+
+    /// The serialized sequence. The same ids give the same bytes on every platform: parameters
+    /// are chosen with integer math and every field has a fixed byte order.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut vec = (self.size as u64).to_be_bytes().to_vec();
-        vec.append(&mut (self.num_upper_bits as u64).to_be_bytes().to_vec());
-        vec.append(&mut (self.num_lower_bits as u64).to_be_bytes().to_vec());
-        let mut upper_bits_data = self.upper_bits.as_bytes();
-        vec.append(&mut (upper_bits_data.len() as u64).to_be_bytes().to_vec());
-        vec.append(&mut upper_bits_data);
-        vec.append(&mut self.lower_bits.as_bytes());
+        let mut vec = Vec::with_capacity(self.serialized_len());
+        self.write_to(&mut vec).expect("writing to a Vec can't fail");
         vec
     }
 
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
-        (0..self.size).filter_map(move |i| self.get(i))
+    /// Copies into owned storage, so a structure loaded zero-copy can outlive its buffer.
+    pub fn to_owned(&self) -> EliasFano<Vec<u64>> {
+        EliasFano {
+            upper_bits: self.upper_bits.to_owned(),
+            lower_bits: self.lower_bits.to_owned(),
+            num_lower_bits: self.num_lower_bits,
+            num_upper_bits: self.num_upper_bits,
+            size: self.size,
+            universe: self.universe,
+            strictness: self.strictness,
+            upper_index: self.upper_index.clone(),
+        }
+    }
+
+    /// Re-encodes the values mapped through `f`, which must be strictly increasing, e.g. to scale
+    /// record numbers to byte offsets. Values stream from one to the other without being collected
+    /// first. `f` is called once per value, on the last one first since the mapped last value is
+    /// the new universe. If `f` turns out not to be increasing, the result is an `UnsortedIds` or
+    /// `ValueAboveUniverse` error.
+    pub fn map_monotone(&self, mut f: impl FnMut(u64) -> u64) -> Result<EliasFano<Vec<u64>>, Error> {
+        let Some(last) = self.last_u64() else {
+            return Ok(EliasFano::empty());
+        };
+        let m = f(last);
+        let mapped = self.iter_u64().take(self.size - 1).map(&mut f).chain(std::iter::once(m));
+        EliasFano::encode(self.size, m, mapped, self.strictness)
+    }
+
+    /// The same sequence with `offset` added to every value, e.g. to turn a segment's local ids into
+    /// global ones. When `offset` is a whole number of buckets, at most as many as there are upper
+    /// bits, the lower bits are copied as they are and the upper bits just start later; otherwise
+    /// the values are re-encoded. The universe moves with them; `None` if it would overflow a `u64`.
+    pub fn rebase(&self, offset: u64) -> Option<EliasFano<Vec<u64>>> {
+        if self.is_empty() {
+            return Some(EliasFano::empty());
+        }
+        let universe = self.universe.checked_add(offset)?;
+        let l = self.num_lower_bits as u32;
+        let buckets = (offset >> l) as usize;
+        if offset & !(u64::MAX << l) != 0 || buckets > self.upper_bits.byte_len() * 8 {
+            return EliasFano::encode(self.size, universe, self.iter_u64().map(|v| v + offset), self.strictness).ok();
+        }
+        let mut upper_bits = Bits::with_capacity(buckets + self.upper_bits.byte_len() * 8);
+        upper_bits.append_zeros(buckets);
+        for word in self.upper_bits.bits.as_ref() {
+            upper_bits.append_from(word.reverse_bits(), 64);
+        }
+        Some(EliasFano {
+            upper_index: Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE)),
+            upper_bits,
+            lower_bits: self.lower_bits.to_owned(),
+            num_lower_bits: self.num_lower_bits,
+            num_upper_bits: self.num_upper_bits,
+            size: self.size,
+            universe,
+            strictness: self.strictness,
+        })
+    }
+
+    /// A new sequence with `insertions` added and `deletions` removed, e.g. to apply a batch of
+    /// index updates to a memory-mapped sequence. Values stream from this one into the new one
+    /// merged with the changes, so neither side is decoded into a `Vec`. The changes may come in
+    /// any order; inserting a present value or deleting an absent one does nothing, and a value in
+    /// both ends up deleted. Deleting a repeated value removes every repeat. The universe grows to
+    /// the largest insertion if it is above it. A loaded sequence whose values turn out not to be
+    /// ordered or counted as its header says fails with the encoding error instead of panicking.
+    pub fn patched(&self, insertions: &[u64], deletions: &[u64]) -> Result<EliasFano<Vec<u64>>, Error> {
+        let mut deletions = deletions.to_vec();
+        deletions.sort_unstable();
+        deletions.dedup();
+        let deleted = |value: &u64| deletions.binary_search(value).is_ok();
+        let mut added: Vec<u64> = insertions.iter().copied().filter(|v| !deleted(v) && !self.contains_u64(*v)).collect();
+        added.sort_unstable();
+        added.dedup();
+
+        let removed: usize = deletions.iter().map(|v| self.occurrences(*v)).sum();
+        let n = (self.size + added.len()).checked_sub(removed).ok_or_else(|| Error::unexpected_count(self.size + added.len(), removed))?;
+        if n == 0 {
+            return Ok(EliasFano::empty());
+        }
+        let m = added.last().map_or(self.universe, |last| self.universe.max(*last));
+
+        let merged = merge_disjoint(self.iter_u64().filter(|v| !deleted(v)), added.into_iter());
+        EliasFano::encode(n, m, merged, self.strictness)
+    }
+
+    /// Exact length of `as_bytes()`, without building it.
+    pub fn serialized_len(&self) -> usize {
+        self.default_header().len() + self.upper_bits.byte_len() + self.lower_bits.byte_len() + CHECKSUM_LEN
+    }
+
+    /// Streams the same bytes as `as_bytes` into `writer`, returning how many were written. Small
+    /// sequences get the compact header.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<usize> {
+        self.write_with_header(writer, &self.default_header(), DEFAULT_ALIGNMENT)
+    }
+
+    /// Like `write_to` but starts both bit arrays at a multiple of `alignment` bytes, e.g. 4096 so
+    /// a memory-mapped file has each array on its own pages. `alignment` must be a power of two
+    /// of at least 8. The full header is always written, since it's what records the alignment.
+    pub fn write_to_aligned(&self, writer: &mut impl Write, alignment: usize) -> io::Result<usize> {
+        if !alignment.is_power_of_two() || !(DEFAULT_ALIGNMENT..1 << 32).contains(&alignment) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "alignment must be a power of two of at least 8"));
+        }
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        write_header(&mut header, &self.header_fields(), alignment);
+        self.write_with_header(writer, &header, alignment)
+    }
+
+    fn default_header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        write_default_header(&mut header, &self.header_fields());
+        header
+    }
+
+    pub(crate) fn header_fields(&self) -> HeaderFields {
+        HeaderFields {
+            codec: Codec::EliasFano,
+            size: self.size,
+            num_upper_bits: self.num_upper_bits,
+            num_lower_bits: self.num_lower_bits,
+            upper_bits_len: self.upper_bits.byte_len(),
+            universe: self.universe,
+            last: self.last_u64().unwrap_or(0),
+            select_sample_rate: self.select_sample_rate(),
+            strictness: self.strictness,
+        }
+    }
+
+    fn write_with_header(&self, writer: &mut impl Write, header: &[u8], alignment: usize) -> io::Result<usize> {
+        let mut writer = Crc32Writer::new(writer);
+        writer.write_all(header)?;
+        write_padding(&mut writer, header.len(), alignment)?;
+        self.upper_bits.write_to(&mut writer)?;
+        write_padding(&mut writer, align_up(header.len(), alignment) + self.upper_bits.byte_len(), alignment)?;
+        self.lower_bits.write_to(&mut writer)?;
+        let (writer, checksum, written) = writer.finish();
+        writer.write_all(&checksum.to_le_bytes())?;
+        Ok(written + CHECKSUM_LEN)
+    }
+
+    pub fn iter(&self) -> Cursor<'_, V> {
+        self.cursor()
+    }
+}
+
+/// The serialized form of an `EliasFano` in pieces, see `EliasFano::as_byte_view`. Only the header
+/// and checksum are built, the bit arrays are borrowed on little-endian targets.
+#[derive(Debug, Clone)]
+pub struct ByteView<'a> {
+    header: Vec<u8>,
+    upper_bits: Cow<'a, [u8]>,
+    lower_bits: Cow<'a, [u8]>,
+    checksum: [u8; CHECKSUM_LEN],
+}
+
+impl ByteView<'_> {
+    /// Concatenated, these are exactly `as_bytes()`.
+    pub fn slices(&self) -> [&[u8]; 4] {
+        [&self.header, &self.upper_bits, &self.lower_bits, &self.checksum]
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.slices().concat()
+    }
+}
+
+impl<V: AsRef<[u64]>> CompressedSequence for EliasFano<V> {
+    fn len(&self) -> usize {
+        EliasFano::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<usize> {
+        EliasFano::get(self, index)
+    }
+
+    fn next_geq(&self, value: usize) -> Option<usize> {
+        EliasFano::next_geq(self, value)
+    }
+
+    fn rank(&self, value: usize) -> usize {
+        EliasFano::rank(self, value)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_> {
+        Box::new(EliasFano::iter(self))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.as_bytes()
+    }
+
+    fn estimate_size(n: usize, universe: usize) -> usize {
+        EliasFano::estimate_size(n, universe)
     }
 }
 
 impl <'a> EliasFano<&'a [u64]> {
     pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
-        let upper_bits_len = u64::from_be_bytes(data[24..32].try_into().map_err(|_| Error::invalid_bits_data(0))?) as usize;
-        Ok(Self {
-            size: u64::from_be_bytes(data[0..8].try_into().map_err(|_| Error::invalid_bits_data(0))?) as usize,
-            num_upper_bits: u64::from_be_bytes(data[8..16].try_into().map_err(|_| Error::invalid_bits_data(0))?) as usize,
-            num_lower_bits: u64::from_be_bytes(data[16..24].try_into().map_err(|_| Error::invalid_bits_data(0))?) as usize,
-            upper_bits: Bits::from_bytes(&data[32..32 + upper_bits_len])?,
-            lower_bits: Bits::from_bytes(&data[32 + upper_bits_len..data.len()])?,
-        })
+        let metadata = describe(data)?;
+        verify_checksum(data, &metadata)?;
+        Self::from_metadata(data, metadata)
+    }
+
+    /// Like `from_bytes` but skips checksum verification, for callers that already trust the data
+    /// and don't want to hash it on every load.
+    pub fn from_bytes_unverified(data: &'a [u8]) -> Result<Self, Error> {
+        Self::from_metadata(data, describe(data)?)
+    }
+
+    fn from_metadata(data: &'a [u8], metadata: Metadata) -> Result<Self, Error> {
+        let (upper, lower) = payload(data, &metadata)?;
+        Self::from_parts(&metadata, Bits::from_bytes(upper)?, Bits::from_bytes(lower)?)
+    }
+}
+
+impl<'a> EliasFano<Cow<'a, [u64]>> {
+    /// Loads a blob zero-copy when `data` is suitably aligned and copies it otherwise, so callers
+    /// get one type either way.
+    pub fn load(data: &'a [u8]) -> Result<Self, Error> {
+        let metadata = describe(data)?;
+        verify_checksum(data, &metadata)?;
+        let (upper, lower) = payload(data, &metadata)?;
+        Self::from_parts(&metadata, Bits::load(upper)?, Bits::load(lower)?)
+    }
+
+    pub fn into_owned(self) -> EliasFano<Vec<u64>> {
+        EliasFano {
+            upper_bits: self.upper_bits.into_owned(),
+            lower_bits: self.lower_bits.into_owned(),
+            num_lower_bits: self.num_lower_bits,
+            num_upper_bits: self.num_upper_bits,
+            size: self.size,
+            universe: self.universe,
+            strictness: self.strictness,
+            upper_index: self.upper_index,
+        }
+    }
+}
+
+impl<'a> From<EliasFano<&'a [u64]>> for EliasFano<Cow<'a, [u64]>> {
+    fn from(ef: EliasFano<&'a [u64]>) -> Self {
+        EliasFano {
+            upper_bits: ef.upper_bits.into(),
+            lower_bits: ef.lower_bits.into(),
+            num_lower_bits: ef.num_lower_bits,
+            num_upper_bits: ef.num_upper_bits,
+            size: ef.size,
+            universe: ef.universe,
+            strictness: ef.strictness,
+            upper_index: ef.upper_index,
+        }
+    }
+}
+
+impl From<EliasFano<Vec<u64>>> for EliasFano<Cow<'static, [u64]>> {
+    fn from(ef: EliasFano<Vec<u64>>) -> Self {
+        EliasFano {
+            upper_bits: ef.upper_bits.into(),
+            lower_bits: ef.lower_bits.into(),
+            num_lower_bits: ef.num_lower_bits,
+            num_upper_bits: ef.num_upper_bits,
+            size: ef.size,
+            universe: ef.universe,
+            strictness: ef.strictness,
+            upper_index: ef.upper_index,
+        }
+    }
+}
+
+impl From<EliasFano<Vec<u64>>> for EliasFano<Arc<[u64]>> {
+    fn from(ef: EliasFano<Vec<u64>>) -> Self {
+        ef.into_storage()
+    }
+}
+
+// Every storage this crate produces can be queried from any thread
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[allow(dead_code)]
+    fn assertions() {
+        assert_send_sync::<EliasFano<Vec<u64>>>();
+        assert_send_sync::<EliasFano<&'static [u64]>>();
+        assert_send_sync::<EliasFano<Cow<'static, [u64]>>>();
+        assert_send_sync::<EliasFano<Arc<[u64]>>>();
+    }
+};
+
+/// Splits the upper and lower bit regions out of a blob `metadata` was parsed from.
+pub(crate) fn payload<'a>(data: &'a [u8], metadata: &Metadata) -> Result<(&'a [u8], &'a [u8]), Error> {
+    if metadata.codec != Codec::EliasFano {
+        return Err(Error::unknown_codec(metadata.codec.tag()));
+    }
+    let region = |offset: usize, len: usize| offset.checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::truncated_data(data.len(), offset.saturating_add(len)));
+    Ok((
+        region(metadata.upper_bits_offset, metadata.upper_bits_len)?,
+        region(metadata.lower_bits_offset, metadata.lower_bits_len)?,
+    ))
+}
+
+/// Writes zeros from `offset` up to the next multiple of `alignment`.
+fn write_padding(writer: &mut impl Write, offset: usize, alignment: usize) -> io::Result<()> {
+    let mut padding = align_up(offset, alignment) - offset;
+    while padding > 0 {
+        let chunk = padding.min(512);
+        writer.write_all(&[0; 512][..chunk])?;
+        padding -= chunk;
+    }
+    Ok(())
+}
+
+fn skip(reader: &mut impl Read, len: usize) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+    if copied < len as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::errors::ErrorKind;
+    use crate::metadata::SAMPLE_RATE_OFFSET;
+
+    #[test]
+    fn byte_view_matches_as_bytes() {
+        let ef = EliasFano::new((0..1000).map(|i| i * 7 + i % 3)).expect("elias fano encoding");
+        let view = ef.as_byte_view();
+        assert_eq!(view.to_vec(), ef.as_bytes());
+        if cfg!(target_endian = "little") {
+            assert_eq!(view.slices()[1].as_ptr(), ef.upper_bits.bits.as_ptr() as *const u8);
+        }
+    }
+
+    #[test]
+    fn map_monotone_streams_through_f() {
+        let data: Vec<u64> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::from_u64s(&data).expect("elias fano encoding");
+        let mapped = ef.map_monotone(|v| v * 4096 + 100).expect("mapped");
+        assert_eq!(mapped.iter_u64().collect::<Vec<_>>(), data.iter().map(|v| v * 4096 + 100).collect::<Vec<_>>());
+        assert_eq!(ef.map_monotone(|v| v / 8).unwrap_err().kind(), &ErrorKind::UnsortedIds { position: 3, pair: Some((2, 2)) });
+        assert!(ef.map_monotone(|v| if v == 0 { u64::MAX } else { v }).is_err());
+        let mut calls = 0;
+        ef.map_monotone(|v| { calls += 1; v + 1 }).expect("mapped");
+        assert_eq!(calls, 1000);
+    }
+
+    #[test]
+    fn rebase_shifts_every_value() {
+        let data: Vec<u64> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::from_u64s(&data).expect("elias fano encoding");
+        let whole_buckets = 5 << ef.num_lower_bits;
+        for offset in [0, whole_buckets, 12345, 1 << 50] {
+            let rebased = ef.rebase(offset).expect("rebased");
+            assert_eq!(rebased.iter_u64().collect::<Vec<_>>(), data.iter().map(|v| v + offset).collect::<Vec<_>>());
+            assert_eq!(rebased.next_geq_u64(offset + 8), Some(offset + 8));
+        }
+        assert_eq!(ef.rebase(whole_buckets).expect("rebased").lower_bits, ef.lower_bits);
+        assert!(ef.rebase(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn from_sorted_slice_matches_new() {
+        let ids: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::from_sorted_slice(&ids).expect("elias fano encoding");
+        assert_eq!(ef.as_bytes(), EliasFano::new(&ids).expect("elias fano encoding").as_bytes());
+        assert_eq!(EliasFano::from_sorted_slice(&[1, 5, 5]).unwrap_err().kind(), &ErrorKind::UnsortedIds { position: 2, pair: Some((5, 5)) });
+        assert_eq!(EliasFano::from_sorted_slice(&[]).unwrap_err(), Error::no_ids());
+    }
+
+    #[test]
+    fn non_decreasing_keeps_repeats() {
+        let prefix_sums = vec![0, 3, 3, 3, 7, 7, 20, 20, 20];
+        assert!(EliasFano::new(&prefix_sums).is_err());
+        let ef = EliasFano::with_strictness(&prefix_sums, Strictness::NonDecreasing).expect("elias fano encoding");
+        assert!(ef.invariants_hold());
+        assert_eq!(ef.iter().collect::<Vec<_>>(), prefix_sums);
+        assert_eq!(ef.get(5), Some(7));
+        assert_eq!((ef.rank(3), ef.rank(4), ef.rank(20), ef.rank(21)), (1, 4, 6, 9));
+        assert_eq!(ef.next_geq(4), Some(7));
+        assert!(ef.contains(20) && !ef.contains(19));
+        assert_eq!(
+            EliasFano::with_strictness([2, 1], Strictness::NonDecreasing).unwrap_err().kind(),
+            &ErrorKind::UnsortedIds { position: 1, pair: Some((2, 1)) }
+        );
+    }
+
+    #[test]
+    fn binary_search_and_partition_point_match_slice() {
+        let data: Vec<usize> = (0..3000).map(|i| i * 11 + i % 4).collect();
+        let ef = EliasFano::new(&data).expect("elias fano encoding");
+        for probe in (0..34_000).step_by(7) {
+            assert_eq!(ef.binary_search(probe), data.binary_search(&probe));
+            assert_eq!(ef.partition_point(|v| v < probe), data.partition_point(|v| *v < probe));
+        }
+        assert_eq!(EliasFano::empty().binary_search(5), Err(0));
+    }
+
+    #[test]
+    fn select_sample_rate_survives_serialization() {
+        let data: Vec<usize> = (0..5000).map(|i| i * 37 + i % 5).collect();
+        let ef = EliasFano::new(&data).expect("elias fano encoding").with_select_sample_rate(100);
+        assert_eq!(ef.select_sample_rate(), 128);
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+        let bytes = ef.as_bytes();
+        assert_eq!(bytes[SAMPLE_RATE_OFFSET], 7);
+        let loaded = EliasFano::from_bytes_owned(&bytes).expect("round trip");
+        assert_eq!((loaded.select_sample_rate(), loaded.get(4321)), (128, Some(data[4321])));
+
+        // The default stays out of the header, and small sequences keep the compact one
+        let small = EliasFano::new([3, 8, 20]).expect("elias fano encoding");
+        assert_eq!(small.clone().with_select_sample_rate(512).as_bytes(), small.as_bytes());
+        let loaded = EliasFano::from_bytes_owned(&small.with_select_sample_rate(1).as_bytes()).expect("round trip");
+        assert_eq!(loaded.select_sample_rate(), 8);
+
+        let mut corrupt = bytes;
+        corrupt[SAMPLE_RATE_OFFSET] = 40;
+        assert_eq!(describe(&corrupt).map(|_| ()), Err(Error::corrupt_header(SAMPLE_RATE_OFFSET)));
+    }
+
+    #[test]
+    fn select_index_loads_from_its_own_blob() {
+        let data: Vec<usize> = (0..20_000).map(|i| i * 37 + i % 5).collect();
+        let ef = EliasFano::new(&data).expect("elias fano encoding").with_select_sample_rate(64);
+        let blob = ef.select_index_bytes();
+        let mut loaded = EliasFano::from_bytes_owned(&ef.as_bytes()).expect("round trip");
+        loaded.load_select_index(&blob).expect("matching index");
+        assert!(loaded.upper_index.built().is_some());
+        assert_eq!(loaded.select_sample_rate(), 64);
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), data);
+        assert!((0..20_000).step_by(7).all(|i| loaded.get(i) == Some(data[i]) && loaded.rank(data[i]) == i));
+
+        let mut other = EliasFano::new(data.iter().map(|v| v + 1)).expect("elias fano encoding");
+        assert!(matches!(other.load_select_index(&blob).map_err(|e| e.kind().clone()), Err(ErrorKind::ChecksumMismatch { .. })));
+        let mut future = blob.clone();
+        future[4] = 3;
+        assert_eq!(loaded.load_select_index(&future), Err(Error::unsupported_version(3)));
+        assert!(loaded.load_select_index(&blob[..blob.len() - 1]).is_err());
+        let mut empty = EliasFano::empty();
+        assert_eq!(empty.load_select_index(&EliasFano::empty().select_index_bytes()), Ok(()));
+    }
+
+    #[test]
+    fn first_and_last() {
+        let data: Vec<usize> = (0..5000).map(|i| 1_000_000 + i * 37 + i % 5).collect();
+        let ef = EliasFano::new(&data).expect("elias fano encoding");
+        assert_eq!((ef.first(), ef.last()), (Some(data[0]), Some(data[4999])));
+        assert_eq!(ef.last(), ef.get(4999));
+        let single = EliasFano::new([7]).expect("elias fano encoding");
+        assert_eq!((single.first(), single.last()), (Some(7), Some(7)));
+        let empty = EliasFano::empty();
+        assert_eq!((empty.first(), empty.last()), (None, None));
+    }
+
+    #[test]
+    fn try_get_tells_range_from_corruption() {
+        let mut ef = EliasFano::new([3, 8, 20]).expect("elias fano encoding");
+        assert_eq!(ef.try_get(1), Ok(8));
+        assert_eq!(ef.try_get(3), Err(Error::index_out_of_range(3, 3)));
+        ef.upper_bits = Bits::new();
+        ef.upper_index = Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE));
+        assert_eq!(ef.get(1), None);
+        assert_eq!(ef.try_get(1), Err(Error::corrupt_structure(1)));
+    }
+
+    // Encodings must not change across platforms or releases; a change here is a format break
+    #[test]
+    fn golden_bytes() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let ef = EliasFano::new([2, 3, 5, 7, 11, 13, 24, 100, 1000]).expect("elias fano encoding");
+        assert_eq!(hex(&ef.as_bytes()), "e009040708000000ff800000000000002030148ec632260b03cb92a1");
+        let mut full = vec![];
+        ef.write_to_aligned(&mut full, DEFAULT_ALIGNMENT).expect("written");
+        assert_eq!(
+            hex(&full),
+            concat!(
+                "50454600050003000900000000000000040000000000000007000000000000000800000000000000",
+                "e803000000000000ff800000000000002030148ec632260b1091e39e",
+            )
+        );
+    }
+
+    #[test]
+    fn universe_survives_serialization() {
+        let ef = EliasFano::with_universe([2, 3, 5, 7], 1 << 20).expect("elias fano encoding");
+        assert_eq!((ef.universe(), ef.last()), (1 << 20, Some(7)));
+        assert!(ef.invariants_hold());
+        assert_eq!(ef.upper_bits.bits.capacity(), ef.upper_bits.bits.len());
+        let data = ef.as_bytes();
+        assert_eq!(describe(&data).expect("metadata").universe, Some(1 << 20));
+        assert_eq!(EliasFano::from_bytes_owned(&data).expect("loaded").universe(), 1 << 20);
+        assert_eq!(ef.rebase(10).expect("rebased").universe(), (1 << 20) + 10);
+        assert!(EliasFano::with_universe([2, 30], 29).is_err());
+
+        let reseal = |data: &mut Vec<u8>| {
+            let body_len = data.len() - CHECKSUM_LEN;
+            let checksum = crate::checksum::crc32(&data[..body_len]);
+            data[body_len..].copy_from_slice(&checksum.to_le_bytes());
+        };
+
+        // Compact headers, and full ones before version 5, leave it at the last value
+        let ef = EliasFano::new([2, 3, 5, 7]).expect("elias fano encoding");
+        assert_eq!(describe(&ef.as_bytes()).expect("metadata").universe, None);
+        assert_eq!(EliasFano::from_bytes_owned(&ef.as_bytes()).expect("loaded").universe(), 7);
+        let mut v4 = vec![];
+        ef.write_to_aligned(&mut v4, DEFAULT_ALIGNMENT).expect("written");
+        v4.drain(UNIVERSE_OFFSET..HEADER_LEN);
+        v4[4] = 4;
+        reseal(&mut v4);
+        assert_eq!(EliasFano::from_bytes_owned(&v4).expect("loaded").universe(), 7);
+
+        let mut below_last = vec![];
+        ef.write_to_aligned(&mut below_last, DEFAULT_ALIGNMENT).expect("written");
+        below_last[UNIVERSE_OFFSET] = 6;
+        reseal(&mut below_last);
+        assert_eq!(EliasFano::from_bytes_owned(&below_last).unwrap_err(), Error::corrupt_header(UNIVERSE_OFFSET));
+    }
+
+    #[test]
+    fn patched_applies_changes() {
+        let data: Vec<u64> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let bytes = EliasFano::from_u64s(&data).expect("elias fano encoding").as_bytes();
+        let loaded = EliasFano::<Cow<'_, [u64]>>::load(&bytes).expect("loaded");
+
+        let insertions = [9000, 1, 7, 9000, 4];
+        let deletions = [data[10], data[999], 1, 5];
+        let mut expected: Vec<u64> = data.iter().copied().chain([4, 7, 9000]).filter(|v| !deletions.contains(v)).collect();
+        expected.sort_unstable();
+        let patched = loaded.patched(&insertions, &deletions).expect("patched");
+        assert_eq!(patched.iter_u64().collect::<Vec<_>>(), expected);
+        assert_eq!(patched.universe_u64(), 9000);
+
+        assert!(loaded.patched(&[], &data).expect("patched").is_empty());
+        assert_eq!(loaded.patched(&[], &[]).expect("patched").iter_u64().collect::<Vec<_>>(), data);
+
+        let repeated = EliasFano::from_u64s_with_strictness([1u64, 5, 5, 9], Strictness::NonDecreasing).expect("elias fano encoding");
+        assert_eq!(repeated.patched(&[], &[5]).expect("patched").iter_u64().collect::<Vec<_>>(), [1, 9]);
+        let patched = repeated.patched(&[7, 5], &[]).expect("patched");
+        assert_eq!((patched.strictness(), patched.iter_u64().collect::<Vec<_>>()), (Strictness::NonDecreasing, vec![1, 5, 5, 7, 9]));
+
+        // Raising the first value's lower bits puts it above the second
+        let mut corrupt = EliasFano::from_u64s([0u64, 1, 64]).expect("elias fano encoding").as_bytes();
+        let lower_bits_offset = describe(&corrupt).expect("metadata").lower_bits_offset;
+        corrupt[lower_bits_offset] ^= 0xff;
+        let loaded = EliasFano::from_bytes_unverified(corrupt.as_slice()).expect("loaded");
+        assert!(loaded.patched(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn parameters_are_exact() {
+        let smallest = |n: u64, m: u64| (0..63).find(|l| (n as u128) << l >= m as u128).unwrap_or(63);
+        let near_2_53 = (1u64 << 53) - 2..(1u64 << 53) + 3;
+        for m in (0..300).chain(near_2_53.clone()).chain([u64::MAX - 1, u64::MAX]) {
+            for n in (1..40).chain(near_2_53.clone()) {
+                assert_eq!(parameters(n as usize, m).0, smallest(n, m), "n={} m={}", n, m);
+            }
+        }
+        assert_eq!(parameters(1 << 20, 0).1, 20);
+        assert_eq!(parameters((1 << 20) + 1, 0).1, 21);
+        assert_eq!(parameters(0, 0), (0, 0));
+    }
+
+    #[test]
+    fn construction_reserves_exactly() {
+        let ef = EliasFano::new((0..10_000).map(|i| i * 37 + i % 5)).expect("elias fano encoding");
+        assert_eq!(ef.upper_bits.bits.capacity(), ef.upper_bits.bits.len());
+        assert_eq!(ef.lower_bits.bits.capacity(), ef.lower_bits.bits.len());
+    }
 
     #[test]
     fn ef_get() {
@@ -136,6 +1105,33 @@ mod tests {
         assert_eq!(ef.next_geq(14), Some(24));
     }
 
+    #[test]
+    fn ef_rank() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        assert_eq!(ef.rank(0), 0);
+        assert_eq!(ef.rank(3), 1);
+        assert_eq!(ef.rank(4), 2);
+        assert_eq!(ef.rank(24), 6);
+        assert_eq!(ef.rank(25), 7);
+        assert_eq!(ef.next_geq(25), None);
+    }
+
+    #[test]
+    fn ef_get_across_word_boundaries() {
+        let data: Vec<usize> = (0..500).map(|i| i * 37 + i % 5).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+        assert_eq!(ef.next_geq(37 * 300 + 1), Some(37 * 301 + 1));
+    }
+
+    #[test]
+    fn estimate_size_matches_serialized_len() {
+        for data in [vec![2, 3, 5, 7, 11, 13, 24], (0..1000).map(|i| i * 7).collect(), vec![0], vec![1 << 40]] {
+            let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+            assert_eq!(EliasFano::estimate_size(data.len(), *data.last().unwrap()), ef.as_bytes().len());
+        }
+    }
+
     #[test]
     fn can_iterate() {
         let data = vec![2, 3, 5, 7, 11, 13, 24];
@@ -143,6 +1139,31 @@ mod tests {
         assert_eq!(ef.iter().collect::<Vec<_>>(), data);
     }
 
+    #[test]
+    fn values_above_u32_max() {
+        let data: Vec<u64> = (0..1000).map(|i| (1 << 40) + i * 12_345).chain([u64::MAX]).collect();
+        let ef = EliasFano::from_u64s(&data).expect("elias fano encoding");
+        assert_eq!(ef.get_u64(0), Some(1 << 40));
+        assert_eq!(ef.get_u64(1000), Some(u64::MAX));
+        assert_eq!(ef.rank_u64(1 << 40), 0);
+        assert_eq!(ef.rank_u64((1 << 40) + 12_346), 2);
+        assert_eq!(ef.next_geq_u64((1 << 40) + 1), Some((1 << 40) + 12_345));
+        assert_eq!(ef.next_geq_u64((1 << 41) + 1), Some(u64::MAX));
+        assert_eq!(ef.iter_u64().collect::<Vec<_>>(), data);
+
+        let loaded = EliasFano::from_bytes_owned(&ef.as_bytes()).expect("deserialized");
+        assert_eq!(loaded.iter_u64().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn single_huge_value() {
+        let ef = EliasFano::from_u64s([u64::MAX]).expect("elias fano encoding");
+        assert_eq!(ef.get_u64(0), Some(u64::MAX));
+        assert_eq!(ef.rank_u64(u64::MAX), 0);
+        let mut cursor = ef.cursor();
+        assert_eq!(cursor.advance_to_u64(u64::MAX - 1), Some(u64::MAX));
+    }
+
     #[test]
     fn serialize_and_deserialize() {
         let data = vec![2, 3, 5, 7, 11, 13, 24];
@@ -151,4 +1172,214 @@ mod tests {
         let ef = EliasFano::from_bytes(ef_serialized.as_slice()).expect("deserialized");
         assert_eq!(ef.iter().collect::<Vec<_>>(), data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn write_to_matches_as_bytes() {
+        let ef = EliasFano::new((0..1000).map(|i| i * 3)).expect("elias fano encoding");
+        let mut out = vec![];
+        let written = ef.write_to(&mut out).expect("written");
+        assert_eq!(written, out.len());
+        assert_eq!(ef.serialized_len(), out.len());
+        assert_eq!(out, ef.as_bytes());
+    }
+
+    #[test]
+    fn read_from_stream() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut stream = ef.as_bytes();
+        stream.extend_from_slice(b"next");
+        let mut reader = stream.as_slice();
+        let ef = EliasFano::read_from(&mut reader).expect("read");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+        assert_eq!(reader, b"next");
+
+        let legacy = crate::metadata::to_legacy(&ef.as_bytes());
+        let ef = EliasFano::read_from(&mut legacy.as_slice()).expect("read");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn read_from_truncated_stream() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let stream = ef.as_bytes();
+        assert!(EliasFano::read_from(&mut &stream[..stream.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn new_reports_unsorted_position() {
+        let err = EliasFano::new(vec![2, 3, 5, 5, 11]).expect_err("unsorted");
+        assert_eq!(err.kind(), &ErrorKind::UnsortedIds { position: 3, pair: Some((5, 5)) });
+        assert!(err.to_string().contains("position=3 previous=5 id=5"));
+        assert_eq!(validate_sorted(&[2, 3, 9, 4]), Err(Error::out_of_order(3, 9, 4)));
+        assert_eq!(validate_sorted(&[2, 3, 9]), Ok(()));
+        assert_eq!(validate_sorted(&[]), Ok(()));
+    }
+
+    #[test]
+    fn from_bytes_truncated() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let current = ef.as_bytes();
+        let legacy = crate::metadata::to_legacy(&current);
+        for data in [current, legacy] {
+            for len in 0..data.len() {
+                let err = EliasFano::from_bytes_unverified(&data[..len]).expect_err("truncated");
+                assert!(matches!(err.kind(), ErrorKind::TruncatedData { offset, needed } if *offset == len && *needed > len), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn from_bytes_rejects_inconsistent_header() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let mut full_header = vec![];
+        ef.write_to_aligned(&mut full_header, DEFAULT_ALIGNMENT).expect("written");
+        // Compact header fields have no fixed offsets, so errors point at where they start
+        for (data, size_offset) in [(full_header, SIZE_OFFSET), (ef.as_bytes(), 1)] {
+            let upper_bits_offset = describe(&data).expect("metadata").upper_bits_offset;
+
+            // The upper bits encode one value fewer than the header declares
+            let mut missing_value = data.clone();
+            missing_value[upper_bits_offset] &= 0b1111_1110;
+            let err = EliasFano::from_bytes_unverified(&missing_value).expect_err("inconsistent");
+            assert!(matches!(err.kind(), ErrorKind::CorruptHeader { offset } if *offset == size_offset), "{}", err);
+
+            assert!(EliasFano::from_bytes_unverified(&data).is_ok());
+        }
+    }
+
+    #[test]
+    fn queries_on_corrupt_blobs_dont_panic() {
+        let ef = EliasFano::new((0..200).map(|i| i * 37 + i % 5)).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        for position in 0..data.len() {
+            for bit in 0..8 {
+                let mut corrupt = data.clone();
+                corrupt[position] ^= 1 << bit;
+                if let Ok(ef) = EliasFano::from_bytes_unverified(&corrupt) {
+                    for value in [0, 1, 500, 7400, usize::MAX] {
+                        ef.get(value);
+                        ef.next_geq(value);
+                        ef.rank(value);
+                        ef.cursor().advance_to(value);
+                    }
+                    ef.iter().count();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_owned() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        // Shift by one byte so the words can't be borrowed in place
+        let mut unaligned = vec![0];
+        unaligned.extend(ef.as_bytes());
+        let ef = EliasFano::from_bytes_owned(&unaligned[1..]).expect("deserialized");
+        let handle = std::thread::spawn(move || ef.iter().collect::<Vec<_>>());
+        assert_eq!(handle.join().expect("joined"), data);
+    }
+
+    #[test]
+    fn to_owned_outlives_buffer() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let owned = {
+            let ef_serialized = EliasFano::new(data.clone()).expect("elias fano encoding").as_bytes();
+            EliasFano::from_bytes(ef_serialized.as_slice()).expect("deserialized").to_owned()
+        };
+        assert_eq!(owned.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn load_into_one_type() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let ef_serialized = ef.as_bytes();
+        let mut unaligned = vec![0];
+        unaligned.extend(ef_serialized.iter());
+
+        let loaded: Vec<EliasFano<Cow<[u64]>>> = vec![
+            EliasFano::load(ef_serialized.as_slice()).expect("aligned"),
+            EliasFano::load(&unaligned[1..]).expect("unaligned"),
+            EliasFano::from_bytes(ef_serialized.as_slice()).expect("borrowed").into(),
+            ef.into(),
+        ];
+        for ef in loaded {
+            assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+        }
+    }
+
+    #[test]
+    fn share_across_threads() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 3).collect();
+        let ef: EliasFano<Arc<[u64]>> = EliasFano::from_bytes_owned(&EliasFano::new(data.clone()).expect("elias fano encoding").as_bytes())
+            .expect("deserialized")
+            .into();
+        let handles: Vec<_> = (0..4).map(|t| {
+            let ef = ef.clone();
+            std::thread::spawn(move || ef.get(t * 100))
+        }).collect();
+        for (t, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().expect("joined"), Some(t * 300));
+        }
+    }
+
+    #[test]
+    fn page_aligned_layout() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut aligned = vec![];
+        let written = ef.write_to_aligned(&mut aligned, 4096).expect("written");
+        assert_eq!(written, aligned.len());
+
+        let metadata = describe(&aligned).expect("metadata");
+        assert_eq!(metadata.alignment, 4096);
+        assert_eq!(metadata.upper_bits_offset, 4096);
+        assert_eq!(metadata.lower_bits_offset % 4096, 0);
+        assert_eq!(metadata.total_len(), aligned.len());
+
+        let borrowed = EliasFano::from_bytes(&aligned).expect("deserialized");
+        assert_eq!(borrowed.iter().collect::<Vec<_>>(), data);
+        let read = EliasFano::read_from(&mut aligned.as_slice()).expect("read");
+        assert_eq!(read.iter().collect::<Vec<_>>(), data);
+        assert_eq!(crate::metadata::migrate(&aligned).expect("migrated"), ef.as_bytes());
+        assert!(ef.write_to_aligned(&mut vec![], 12).is_err());
+    }
+
+    #[test]
+    fn deserialize_legacy_layout() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let ef_serialized = ef.as_bytes();
+        let legacy = crate::metadata::to_legacy(&ef_serialized);
+        let ef = EliasFano::from_bytes(legacy.as_slice()).expect("deserialized");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn deserialize_detects_corruption() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut ef_serialized = ef.as_bytes();
+        // Flip a lower bit, which unlike the upper bits isn't checked against the header
+        let lower_bits_offset = describe(&ef_serialized).expect("metadata").lower_bits_offset;
+        ef_serialized[lower_bits_offset] ^= 0b100;
+        assert!(EliasFano::from_bytes(ef_serialized.as_slice()).is_err());
+        assert!(EliasFano::from_bytes_unverified(ef_serialized.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn quantiles() {
+        let ef = EliasFano::new((1..=100).map(|i| i * 10)).expect("elias fano encoding");
+        assert_eq!(ef.quantile(0.0), Some(10));
+        assert_eq!(ef.quantile(0.5), Some(500));
+        assert_eq!(ef.quantile(0.99), Some(990));
+        assert_eq!(ef.quantile(1.0), Some(1000));
+        assert_eq!(ef.rank_of_quantile(0.5), Some(49));
+        assert_eq!(ef.kth(49), Some(500));
+        assert_eq!(ef.quantile(1.5), None);
+        assert_eq!(ef.quantile(f64::NAN), None);
+        assert_eq!(EliasFano::empty().quantile(0.5), None);
+    }
+}
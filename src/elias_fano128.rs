@@ -0,0 +1,189 @@
+use crate::bits::Bits;
+use crate::elias_fano::ceil_log2;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::select_index::SelectIndex;
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+
+/// Elias-Fano over `u128` values, for keys such as IPv6 addresses or UUIDs. It is laid out like
+/// `EliasFano`, except a value's lower bits can be wider than a word, in which case they are
+/// appended as two fields. The serialized form is the value count, the number of lower bits and the
+/// upper bits' byte length, big-endian, then the upper and lower bits.
+#[derive(Debug, Clone)]
+pub struct EliasFano128 {
+    upper_bits: Bits<Vec<u64>>,
+    lower_bits: Bits<Vec<u64>>,
+    upper_index: SelectIndex,
+    num_lower_bits: usize,
+    size: usize,
+}
+
+impl EliasFano128 {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<u128>>) -> Result<Self, Error> {
+        let ids: Vec<u128> = ids.into_iter().map(|i| *i.borrow()).collect();
+
+        if let Some(position) = ids.windows(2).position(|pair| pair[0] >= pair[1]) {
+            return Err(Error::unsorted_ids(position + 1));
+        }
+
+        let m = *ids.last().ok_or(Error::no_ids())?;
+        let n = ids.len();
+        // Capped so shifting a u128 by it stays defined
+        let num_lower_bits = ceil_log2(m.div_ceil(n as u128)).min(127);
+
+        let mut lower_bits = Bits::with_capacity(n * num_lower_bits);
+        let mut upper_bits = Bits::with_capacity(n + (m >> num_lower_bits) as usize + 1);
+        // m >> num_lower_bits is at most about 2n, so buckets fit a usize
+        let mut bucket = 0;
+        for id in ids {
+            if num_lower_bits > 64 {
+                lower_bits.append_from((id >> 64) as u64, num_lower_bits - 64).append_from(id as u64, 64);
+            } else {
+                lower_bits.append_from(id as u64, num_lower_bits);
+            }
+            let high = (id >> num_lower_bits) as usize;
+            upper_bits.append_zeros(high - bucket).append_ones(1);
+            bucket = high;
+        }
+        upper_bits.append_zeros(1);
+
+        let upper_index = SelectIndex::build(upper_bits.bits.as_ref());
+        Ok(Self { upper_bits, lower_bits, upper_index, num_lower_bits, size: n })
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u128> {
+        if index >= self.size {
+            return None;
+        }
+        let upper = self.upper_index.over(self.upper_bits.bits.as_ref()).select_1(index)? - index;
+        Some(self.value(index, upper))
+    }
+
+    pub fn next_geq(&self, value: u128) -> Option<u128> {
+        self.get(self.rank(value))
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: u128) -> usize {
+        let Ok(bucket) = usize::try_from(value >> self.num_lower_bits) else {
+            return self.size;
+        };
+        let start = if bucket == 0 {
+            0
+        } else {
+            match self.upper_index.over(self.upper_bits.bits.as_ref()).select_0(bucket - 1) {
+                Some(position) => position + 1 - bucket,
+                None => return self.size,
+            }
+        };
+        (start..self.size)
+            .find(|i| self.get(*i).map_or(true, |v| v >= value))
+            .unwrap_or(self.size)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u128> + '_ {
+        let mut position = 0;
+        (0..self.size).map(move |index| {
+            // Construction leaves a one per value, so there is always a next one
+            position = self.upper_bits.next_1(position).unwrap_or(0);
+            let value = self.value(index, position - index);
+            position += 1;
+            value
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.size as u64).to_be_bytes().to_vec();
+        vec.extend_from_slice(&(self.num_lower_bits as u64).to_be_bytes());
+        vec.extend_from_slice(&(self.upper_bits.byte_len() as u64).to_be_bytes());
+        vec.append(&mut self.upper_bits.as_bytes());
+        vec.append(&mut self.lower_bits.as_bytes());
+        vec
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let size = usize::try_from(be_u64_at(data, 0)?).map_err(|_| Error::corrupt_header(0))?;
+        let num_lower_bits = be_u64_at(data, 8)? as usize;
+        // Values are shifted by it
+        if num_lower_bits > 127 {
+            return Err(Error::corrupt_header(8));
+        }
+        let upper_len = usize::try_from(be_u64_at(data, 16)?).map_err(|_| Error::corrupt_header(16))?;
+        let rest = rest_from(data, 24)?;
+        let upper = rest.get(..upper_len).ok_or(Error::truncated_data(data.len(), 24usize.saturating_add(upper_len)))?;
+        let upper_bits = Bits::load(upper)?.into_owned();
+        let lower_bits = Bits::load(&rest[upper_len..])?.into_owned();
+
+        // One set bit per value, and every value's lower bits present
+        let ones: usize = upper_bits.bits.iter().map(|w| w.count_ones() as usize).sum();
+        if ones != size {
+            return Err(Error::corrupt_header(0));
+        }
+        if size.checked_mul(num_lower_bits).map_or(true, |bits| bits > lower_bits.byte_len() * 8) {
+            return Err(Error::corrupt_header(8));
+        }
+        let upper_index = SelectIndex::build(upper_bits.bits.as_ref());
+        Ok(Self { upper_bits, lower_bits, upper_index, num_lower_bits, size })
+    }
+
+    fn value(&self, index: usize, upper: usize) -> u128 {
+        let l = self.num_lower_bits;
+        let start = index * l;
+        let lower = if l > 64 {
+            let high = self.lower_bits.slice(start, start + l - 64).unwrap_or(0);
+            let low = self.lower_bits.slice(start + l - 64, start + l).unwrap_or(0);
+            (high as u128) << 64 | low as u128
+        } else {
+            self.lower_bits.slice(start, start + l).unwrap_or(0) as u128
+        };
+        (upper as u128) << l | lower
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_values() {
+        let ids: Vec<u128> = (0..1000u128).map(|i| (i << 100) | (i * 0x1234_5678_9abc_def1)).collect();
+        let ef = EliasFano128::new(&ids).expect("elias fano encoding");
+        assert!(ef.num_lower_bits > 64);
+        assert_eq!(ef.iter().collect::<Vec<_>>(), ids);
+        assert_eq!(ef.get(500), Some(ids[500]));
+        assert_eq!(ef.rank(ids[500]), 500);
+        assert_eq!(ef.next_geq(ids[500] + 1), Some(ids[501]));
+        assert_eq!(ef.next_geq(u128::MAX), None);
+
+        let narrow = EliasFano128::new([3u128, 9, 27]).expect("elias fano encoding");
+        assert_eq!(narrow.iter().collect::<Vec<_>>(), vec![3, 9, 27]);
+        assert_eq!(narrow.rank(10), 2);
+
+        // 2^64 + 1 over 2 ids needs 64 lower bits; rounded to an f64 it looked like 63
+        let exact = EliasFano128::new([1u128, (1 << 64) + 1]).expect("elias fano encoding");
+        assert_eq!(exact.num_lower_bits, 64);
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let ids: Vec<u128> = (0..1000u128).map(|i| (i << 100) | (i * 0x1234_5678_9abc_def1)).collect();
+        let bytes = EliasFano128::new(&ids).expect("elias fano encoding").as_bytes();
+        let loaded = EliasFano128::from_bytes(&bytes).expect("deserialized");
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), ids);
+        assert_eq!(loaded.rank(ids[500]), 500);
+
+        assert!(EliasFano128::from_bytes(&bytes[..bytes.len() - 8]).is_err());
+        let mut wrong_size = bytes.clone();
+        wrong_size[7] ^= 1;
+        assert!(EliasFano128::from_bytes(&wrong_size).is_err());
+    }
+}
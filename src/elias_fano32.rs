@@ -0,0 +1,79 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+
+/// Sorted `u32` values, e.g. 32-bit document ids. Values go in and come out as `u32` on every
+/// target, and loading refuses a blob holding anything wider. The serialized form is the plain
+/// Elias-Fano blob, so small sequences get its compact header.
+#[derive(Debug, Clone)]
+pub struct EliasFano32 {
+    inner: EliasFano<Vec<u64>>,
+}
+
+impl EliasFano32 {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<u32>>) -> Result<Self, Error> {
+        Ok(Self { inner: EliasFano::from_u64s(ids.into_iter().map(|id| *id.borrow() as u64))? })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    // Every stored value fits, checked when encoding or loading
+    pub fn get(&self, index: usize) -> Option<u32> {
+        self.inner.get_u64(index).map(|v| v as u32)
+    }
+
+    pub fn next_geq(&self, value: u32) -> Option<u32> {
+        self.inner.next_geq_u64(value as u64).map(|v| v as u32)
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: u32) -> usize {
+        self.inner.rank_u64(value as u64)
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        self.inner.contains_u64(value as u64)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u32> + '_ {
+        self.inner.iter_u64().map(|v| v as u32)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.inner.as_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let inner = EliasFano::from_bytes_owned(data)?;
+        if let Some(last) = inner.last_u64().filter(|last| u32::try_from(*last).is_err()) {
+            return Err(Error::universe_too_large(usize::try_from(last).unwrap_or(usize::MAX), u32::MAX as usize));
+        }
+        Ok(Self { inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u32_values_round_trip() {
+        let ids: Vec<u32> = (0..5000).map(|i| i * 800_000 + i % 7).collect();
+        let ef = EliasFano32::new(&ids).expect("elias fano encoding");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), ids);
+        assert_eq!((ef.get(4999), ef.rank(ids[10]), ef.next_geq(ids[10] + 1)), (Some(ids[4999]), 10, Some(ids[11])));
+        assert!(ef.contains(ids[7]) && !ef.contains(ids[7] + 1));
+
+        let loaded = EliasFano32::from_bytes(&ef.as_bytes()).expect("loaded");
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), ids);
+        let wide = EliasFano::from_u64s([1, 1 << 32]).expect("elias fano encoding");
+        assert!(EliasFano32::from_bytes(&wide.as_bytes()).is_err());
+    }
+}
@@ -1,35 +1,131 @@
 use std::fmt::{Display, Formatter, Debug};
+use std::io;
+use std::sync::Arc;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ErrorKind {
-    UnsortedIds,
+    /// The id at `position` is not greater than the one before it. `pair` holds the two, previous
+    /// first, for codecs whose ids fit a `u64`.
+    UnsortedIds { position: usize, pair: Option<(u64, u64)> },
     NoIds,
-    InvalidSourceData(usize)
+    InvalidSourceData(usize),
+    GapTooLarge(usize),
+    UnsupportedVersion(u8),
+    UnknownCodec(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    Io(io::ErrorKind, String),
+    ValueAboveUniverse { value: usize, universe: usize },
+    UnexpectedCount { expected: usize, actual: usize },
+    IndexOutOfRange { index: usize, len: usize },
+    /// Input ended at `offset` but `needed` bytes were required.
+    TruncatedData { offset: usize, needed: usize },
+    /// Values go up to `universe`, more than the target can represent.
+    UniverseTooLarge { universe: usize, max: usize },
+    /// The header field at byte `offset` is invalid or disagrees with the payload.
+    CorruptHeader { offset: usize },
+    /// The bit arrays hold no valid value for the in-range `index`.
+    CorruptStructure { index: usize },
 }
 
+#[derive(Clone)]
 pub struct Error {
     error: ErrorKind,
+    // Kept so `source()` can hand back the original error; shared because io::Error isn't Clone
+    source: Option<Arc<io::Error>>,
 }
 
 impl Error {
-    pub fn unsorted_ids() -> Self {
-        Self { error: ErrorKind::UnsortedIds }
+    fn from_kind(error: ErrorKind) -> Self {
+        Self { error, source: None }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.error
+    }
+
+    pub fn unsorted_ids(position: usize) -> Self {
+        Self::from_kind(ErrorKind::UnsortedIds { position, pair: None })
+    }
+
+    /// Like `unsorted_ids`, naming the id at `position` and the one before it.
+    pub fn out_of_order(position: usize, previous: u64, id: u64) -> Self {
+        Self::from_kind(ErrorKind::UnsortedIds { position, pair: Some((previous, id)) })
     }
 
     pub fn no_ids() -> Self {
-        Self { error: ErrorKind::NoIds }
+        Self::from_kind(ErrorKind::NoIds)
     }
 
     pub fn invalid_bits_data(l: usize) -> Self {
-        Self { error: ErrorKind::InvalidSourceData(l) }
+        Self::from_kind(ErrorKind::InvalidSourceData(l))
+    }
+
+    pub fn gap_too_large(gap: usize) -> Self {
+        Self::from_kind(ErrorKind::GapTooLarge(gap))
+    }
+
+    pub fn unsupported_version(version: u8) -> Self {
+        Self::from_kind(ErrorKind::UnsupportedVersion(version))
+    }
+
+    pub fn unknown_codec(tag: u8) -> Self {
+        Self::from_kind(ErrorKind::UnknownCodec(tag))
+    }
+
+    pub fn value_above_universe(value: usize, universe: usize) -> Self {
+        Self::from_kind(ErrorKind::ValueAboveUniverse { value, universe })
+    }
+
+    pub fn unexpected_count(expected: usize, actual: usize) -> Self {
+        Self::from_kind(ErrorKind::UnexpectedCount { expected, actual })
+    }
+
+    pub fn index_out_of_range(index: usize, len: usize) -> Self {
+        Self::from_kind(ErrorKind::IndexOutOfRange { index, len })
+    }
+
+    pub fn truncated_data(offset: usize, needed: usize) -> Self {
+        Self::from_kind(ErrorKind::TruncatedData { offset, needed })
+    }
+
+    pub fn universe_too_large(universe: usize, max: usize) -> Self {
+        Self::from_kind(ErrorKind::UniverseTooLarge { universe, max })
+    }
+
+    pub fn corrupt_header(offset: usize) -> Self {
+        Self::from_kind(ErrorKind::CorruptHeader { offset })
+    }
+
+    pub fn corrupt_structure(index: usize) -> Self {
+        Self::from_kind(ErrorKind::CorruptStructure { index })
+    }
+
+    pub fn checksum_mismatch(expected: u32, actual: u32) -> Self {
+        Self::from_kind(ErrorKind::ChecksumMismatch { expected, actual })
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self.error {
-            ErrorKind::UnsortedIds => write!(f, "Unsorted ids cannot be compressed. Please sort."),
+        match &self.error {
+            ErrorKind::UnsortedIds { position, pair: None } => write!(f, "Unsorted ids cannot be compressed. Please sort. position={}", position),
+            ErrorKind::UnsortedIds { position, pair: Some((previous, id)) } => {
+                write!(f, "Unsorted ids cannot be compressed. Please sort. position={} previous={} id={}", position, previous, id)
+            }
             ErrorKind::NoIds => write!(f, "Emptys ids cannot be compressed."),
-            ErrorKind::InvalidSourceData(l) => write!(f, "Input data for Bits is not correct. length={}", l)
+            ErrorKind::InvalidSourceData(l) => write!(f, "Input data for Bits is not correct. length={}", l),
+            ErrorKind::GapTooLarge(gap) => write!(f, "Gap is too large for this codec. gap={}", gap),
+            ErrorKind::UnsupportedVersion(version) => write!(f, "Unsupported format version {}.", version),
+            ErrorKind::UnknownCodec(tag) => write!(f, "Unknown codec tag {}.", tag),
+            ErrorKind::ValueAboveUniverse { value, universe } => write!(f, "Value is above the declared universe. value={} universe={}", value, universe),
+            ErrorKind::UnexpectedCount { expected, actual } => write!(f, "Number of ids differs from the declared size. expected={} actual={}", expected, actual),
+            ErrorKind::IndexOutOfRange { index, len } => write!(f, "Index is out of range. index={} len={}", index, len),
+            ErrorKind::TruncatedData { offset, needed } => write!(f, "Data ends early. offset={} needed={}", offset, needed),
+            ErrorKind::UniverseTooLarge { universe, max } => write!(f, "Universe is too large. universe={} max={}", universe, max),
+            ErrorKind::CorruptHeader { offset } => write!(f, "Header field is corrupt. offset={}", offset),
+            ErrorKind::CorruptStructure { index } => write!(f, "Encoded data is corrupt. index={}", index),
+            ErrorKind::Io(_, message) => write!(f, "I/O error: {}", message),
+            ErrorKind::ChecksumMismatch { expected, actual } => write!(f, "Checksum mismatch, data is corrupt. expected={:08x} actual={:08x}", expected, actual),
         }
     }
 }
@@ -41,4 +137,62 @@ impl Debug for Error {
     }
 }
 
-impl std::error::Error for Error {}
\ No newline at end of file
+/// Errors are equal when their kinds are; the wrapped I/O error, if any, isn't compared.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.error == other.error
+    }
+}
+
+impl Eq for Error {}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self { error: ErrorKind::Io(e.kind(), e.to_string()), source: Some(Arc::new(e)) }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e.error {
+            ErrorKind::Io(kind, _) => kind,
+            ErrorKind::TruncatedData { .. } => io::ErrorKind::UnexpectedEof,
+            ErrorKind::UnsortedIds { .. } | ErrorKind::NoIds | ErrorKind::GapTooLarge(_) | ErrorKind::ValueAboveUniverse { .. }
+            | ErrorKind::UnexpectedCount { .. } | ErrorKind::IndexOutOfRange { .. } | ErrorKind::UniverseTooLarge { .. } => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn io_errors_round_trip() {
+        let error = Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "short read"));
+        assert_eq!(error.clone(), error);
+        assert_eq!(error.source().expect("source").to_string(), "short read");
+
+        let io_error = io::Error::from(error);
+        assert_eq!(io_error.kind(), io::ErrorKind::UnexpectedEof);
+        let inner = io_error.get_ref().and_then(|e| e.downcast_ref::<Error>()).expect("wrapped");
+        assert!(matches!(inner.kind(), ErrorKind::Io(io::ErrorKind::UnexpectedEof, _)));
+    }
+
+    #[test]
+    fn into_io_error_kind() {
+        assert_eq!(io::Error::from(Error::truncated_data(3, 8)).kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(io::Error::from(Error::unsorted_ids(1)).kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(io::Error::from(Error::corrupt_header(8)).kind(), io::ErrorKind::InvalidData);
+        assert_eq!(io::Error::from(Error::corrupt_structure(8)).kind(), io::ErrorKind::InvalidData);
+        assert_ne!(Error::corrupt_header(8), Error::corrupt_header(16));
+    }
+}
@@ -0,0 +1,178 @@
+//! C interface. Every function takes and returns plain C types; an `EliasFano` lives behind an
+//! opaque `PefEliasFano` pointer that the caller releases with `pef_free`. Functions that can fail
+//! return a null pointer or `false` instead of an error. The `pef-ffi` crate builds them into a
+//! shared library.
+
+use crate::elias_fano::EliasFano;
+use std::ptr;
+use std::slice;
+
+/// Opaque handle to an owned `EliasFano`.
+pub struct PefEliasFano(EliasFano<Vec<u64>>);
+
+/// Bytes allocated by this crate, released with `pef_bytes_free`.
+#[repr(C)]
+pub struct PefBytes {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Encodes `len` strictly increasing ids. Returns null if they are unsorted or `len` is 0.
+///
+/// # Safety
+/// `ids` must point to `len` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn pef_create(ids: *const u64, len: usize) -> *mut PefEliasFano {
+    if ids.is_null() {
+        return ptr::null_mut();
+    }
+    match EliasFano::from_u64s(slice::from_raw_parts(ids, len)) {
+        Ok(ef) => Box::into_raw(Box::new(PefEliasFano(ef))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Loads a blob written by `pef_serialize` or `as_bytes`, copying it so `data` can be released
+/// afterwards. Returns null if it is corrupt.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pef_from_bytes(data: *const u8, len: usize) -> *mut PefEliasFano {
+    if data.is_null() {
+        return ptr::null_mut();
+    }
+    match EliasFano::from_bytes_owned(slice::from_raw_parts(data, len)) {
+        Ok(ef) => Box::into_raw(Box::new(PefEliasFano(ef))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `ef` must come from `pef_create` or `pef_from_bytes` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn pef_len(ef: *const PefEliasFano) -> usize {
+    ef.as_ref().map_or(0, |ef| ef.0.len())
+}
+
+/// Writes the value at `index` to `out`, returning false if `index` is out of range.
+///
+/// # Safety
+/// `ef` as for `pef_len`, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pef_get(ef: *const PefEliasFano, index: usize, out: *mut u64) -> bool {
+    write_value(ef.as_ref().and_then(|ef| ef.0.get_u64(index)), out)
+}
+
+/// Writes the first value `>= value` to `out`, returning false if there is none.
+///
+/// # Safety
+/// `ef` as for `pef_len`, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pef_next_geq(ef: *const PefEliasFano, value: u64, out: *mut u64) -> bool {
+    write_value(ef.as_ref().and_then(|ef| ef.0.next_geq_u64(value)), out)
+}
+
+/// Number of stored values strictly less than `value`.
+///
+/// # Safety
+/// `ef` as for `pef_len`.
+#[no_mangle]
+pub unsafe extern "C" fn pef_rank(ef: *const PefEliasFano, value: u64) -> usize {
+    ef.as_ref().map_or(0, |ef| ef.0.rank_u64(value))
+}
+
+/// Serializes into a new buffer, empty if `ef` is null.
+///
+/// # Safety
+/// `ef` as for `pef_len`.
+#[no_mangle]
+pub unsafe extern "C" fn pef_serialize(ef: *const PefEliasFano) -> PefBytes {
+    let Some(ef) = ef.as_ref() else {
+        return PefBytes { data: ptr::null_mut(), len: 0 };
+    };
+    let bytes = ef.0.as_bytes().into_boxed_slice();
+    let len = bytes.len();
+    PefBytes { data: Box::into_raw(bytes) as *mut u8, len }
+}
+
+/// # Safety
+/// `bytes` must come from `pef_serialize` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn pef_bytes_free(bytes: PefBytes) {
+    if !bytes.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes.data, bytes.len)));
+    }
+}
+
+/// Releases a handle. Null is ignored.
+///
+/// # Safety
+/// `ef` must come from `pef_create` or `pef_from_bytes` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn pef_free(ef: *mut PefEliasFano) {
+    if !ef.is_null() {
+        drop(Box::from_raw(ef));
+    }
+}
+
+unsafe fn write_value(value: Option<u64>, out: *mut u64) -> bool {
+    match value {
+        Some(value) if !out.is_null() => {
+            *out = value;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_query_free() {
+        let ids = [2u64, 3, 5, 7, 1 << 40];
+        unsafe {
+            let ef = pef_create(ids.as_ptr(), ids.len());
+            assert!(!ef.is_null());
+            assert_eq!(pef_len(ef), 5);
+            let mut out = 0;
+            assert!(pef_get(ef, 4, &mut out));
+            assert_eq!(out, 1 << 40);
+            assert!(pef_next_geq(ef, 6, &mut out));
+            assert_eq!(out, 7);
+            assert!(!pef_get(ef, 5, &mut out));
+            assert!(!pef_next_geq(ef, (1 << 40) + 1, &mut out));
+            assert_eq!(pef_rank(ef, 5), 2);
+            pef_free(ef);
+        }
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let ids = [1u64, 10, 100];
+        unsafe {
+            let ef = pef_create(ids.as_ptr(), ids.len());
+            let bytes = pef_serialize(ef);
+            let loaded = pef_from_bytes(bytes.data, bytes.len);
+            assert!(!loaded.is_null());
+            assert_eq!(pef_rank(loaded, 50), 2);
+            assert!(pef_from_bytes(bytes.data, bytes.len - 1).is_null());
+            pef_bytes_free(bytes);
+            pef_free(loaded);
+            pef_free(ef);
+        }
+    }
+
+    #[test]
+    fn invalid_input_gives_null() {
+        let unsorted = [3u64, 2];
+        unsafe {
+            assert!(pef_create(unsorted.as_ptr(), 2).is_null());
+            assert!(pef_create(ptr::null(), 0).is_null());
+            assert_eq!(pef_len(ptr::null()), 0);
+            pef_free(ptr::null_mut());
+        }
+    }
+}
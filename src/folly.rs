@@ -0,0 +1,171 @@
+use crate::bits::Bits;
+use crate::elias_fano::{parameters, EliasFano};
+use crate::errors::Error;
+use crate::select_index::{LazySelectIndex, DEFAULT_SAMPLE_RATE};
+use crate::sequence::Strictness;
+use std::sync::Arc;
+
+/// An Elias-Fano list in the layout of Folly's `EliasFanoCompressedList`, as written by
+/// `EliasFanoEncoderV2` with no skip or forward pointers (both quanta 0).
+///
+/// `data` holds the lower bits followed by the upper bits. Lower bits are packed LSB-first at bit
+/// `i * num_lower_bits`; the upper bits have bit `(value >> num_lower_bits) + i` set for the
+/// `i`th value. Both regions are little-endian byte arrays, `(size * num_lower_bits + 7) / 8` and
+/// `upper_size_bytes` long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollyList {
+    pub size: usize,
+    pub num_lower_bits: u8,
+    pub upper_size_bytes: usize,
+    pub data: Vec<u8>,
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Exports into Folly's layout. Our upper bits already match it bit for bit; the lower bits
+    /// are stored MSB-first here and get repacked.
+    pub fn to_folly(&self) -> FollyList {
+        let lower_len = lower_size_bytes(self.size, self.num_lower_bits);
+        let last = self.last().expect("size is at least 1");
+        let upper_size_bytes = (self.size + (last >> self.num_lower_bits)).div_ceil(8);
+
+        let mut data = vec![0; lower_len + upper_size_bytes];
+        for (i, value) in self.iter().enumerate() {
+            write_bits(&mut data, i * self.num_lower_bits, self.num_lower_bits, value as u64);
+        }
+        let upper = self.upper_bits.as_bytes();
+        data[lower_len..].copy_from_slice(&upper[..upper_size_bytes]);
+
+        FollyList { size: self.size, num_lower_bits: self.num_lower_bits as u8, upper_size_bytes, data }
+    }
+}
+
+impl EliasFano<Vec<u64>> {
+    /// Imports a list in Folly's layout, keeping its `num_lower_bits` so `to_folly` gives the same
+    /// bytes back. `data` may carry trailing padding. Folly allows repeated values, which this
+    /// crate doesn't, so those are rejected as unsorted.
+    pub fn from_folly(size: usize, num_lower_bits: u8, upper_size_bytes: usize, data: &[u8]) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::no_ids());
+        }
+        let num_lower_bits = num_lower_bits as usize;
+        if num_lower_bits > 63 {
+            return Err(Error::invalid_bits_data(data.len()));
+        }
+        let lower_len = size.checked_mul(num_lower_bits)
+            .map(|bits| bits.div_ceil(8))
+            .ok_or(Error::invalid_bits_data(data.len()))?;
+        let upper = lower_len.checked_add(upper_size_bytes)
+            .and_then(|end| data.get(lower_len..end))
+            .ok_or(Error::invalid_bits_data(data.len()))?;
+
+        let mut lower_bits = Bits::with_capacity(size * num_lower_bits);
+        let mut upper_bits = Bits::new();
+        let mut bucket = 0;
+        let mut previous = None;
+        let mut ones = upper.iter().enumerate()
+            .flat_map(|(i, byte)| (0..8).filter(move |bit| byte >> bit & 1 == 1).map(move |bit| i * 8 + bit));
+        for i in 0..size {
+            let position = ones.next().ok_or(Error::invalid_bits_data(data.len()))?;
+            let high = position - i;
+            let value = (high << num_lower_bits) | read_bits(data, i * num_lower_bits, num_lower_bits) as usize;
+            if let Some(p) = previous.filter(|p| *p >= value) {
+                return Err(Error::out_of_order(i, p as u64, value as u64));
+            }
+            previous = Some(value);
+
+            lower_bits.append_from(value as u64, num_lower_bits);
+            upper_bits.append_zeros(high - bucket).append_ones(1);
+            bucket = high;
+        }
+        upper_bits.append_zeros(1);
+
+        let universe = previous.expect("size is at least 1");
+        let ef = Self {
+            upper_index: Arc::new(LazySelectIndex::new(DEFAULT_SAMPLE_RATE)),
+            upper_bits,
+            lower_bits,
+            num_lower_bits,
+            num_upper_bits: parameters(size, universe as u64).1,
+            size,
+            universe: universe as u64,
+            strictness: Strictness::StrictlyIncreasing,
+        };
+        debug_assert!(ef.invariants_hold());
+        Ok(ef)
+    }
+}
+
+fn lower_size_bytes(size: usize, num_lower_bits: usize) -> usize {
+    (size * num_lower_bits).div_ceil(8)
+}
+
+/// Reads `len` bits starting at bit `start` of a little-endian byte array.
+fn read_bits(data: &[u8], start: usize, len: usize) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    let first = start / 8;
+    let count = (start % 8 + len).div_ceil(8);
+    let raw = data[first..first + count].iter().rev().fold(0u128, |acc, byte| acc << 8 | *byte as u128);
+    (raw >> (start % 8)) as u64 & (u64::MAX >> (64 - len))
+}
+
+/// ORs the low `len` bits of `value` into a little-endian byte array at bit `start`.
+fn write_bits(data: &mut [u8], start: usize, len: usize, value: u64) {
+    if len == 0 {
+        return;
+    }
+    let shifted = ((value & (u64::MAX >> (64 - len))) as u128) << (start % 8);
+    let count = (start % 8 + len).div_ceil(8);
+    for (k, byte) in data[start / 8..start / 8 + count].iter_mut().enumerate() {
+        *byte |= (shifted >> (8 * k)) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_matches_folly_layout() {
+        // 3 lower bits: lows 5, 6, 5 and highs 0, 1, 2
+        let ef = EliasFano::new(vec![5, 14, 21]).expect("elias fano encoding");
+        assert_eq!(ef.num_lower_bits, 3);
+        let list = ef.to_folly();
+        assert_eq!(list.size, 3);
+        assert_eq!(list.upper_size_bytes, 1);
+        // 5, 6, 5 packed LSB-first, then ones at 0 + 0, 1 + 1 and 2 + 2
+        assert_eq!(list.data, vec![0b0111_0101, 0b0000_0001, 0b0001_0101]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<usize> = (0..1000).map(|i| i * i + 3 * i).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let list = ef.to_folly();
+        let imported = EliasFano::from_folly(list.size, list.num_lower_bits, list.upper_size_bytes, &list.data).expect("imported");
+        assert_eq!(imported.iter().collect::<Vec<_>>(), data);
+        assert_eq!(imported.as_bytes(), ef.as_bytes());
+        assert_eq!(imported.to_folly(), list);
+    }
+
+    #[test]
+    fn import_keeps_foreign_parameters() {
+        // Folly picks floor(log2(universe / size)) lower bits, fewer than this crate would
+        let list = FollyList { size: 3, num_lower_bits: 2, upper_size_bytes: 1, data: vec![0b0001_1001, 0b0001_0101] };
+        let ef = EliasFano::from_folly(list.size, list.num_lower_bits, list.upper_size_bytes, &list.data).expect("imported");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), vec![1, 6, 9]);
+        assert_eq!(ef.rank(7), 2);
+        assert_eq!(ef.to_folly(), list);
+    }
+
+    #[test]
+    fn import_rejects_bad_input() {
+        assert!(EliasFano::from_folly(0, 2, 1, &[0; 2]).is_err());
+        assert!(EliasFano::from_folly(3, 2, 1, &[0; 1]).is_err());
+        // Not enough ones in the upper bits for three values
+        assert!(EliasFano::from_folly(3, 2, 1, &[0, 0b11]).is_err());
+        // Repeated values
+        assert!(EliasFano::from_folly(2, 0, 1, &[0b11]).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::bits::Bits;
+use crate::elias_fano::validate_sorted;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::sequence::CompressedSequence;
+use std::borrow::Borrow;
+
+// Gaps between consecutive ids are written as a unary quotient (ones terminated by a zero)
+// followed by `rice_bits` bits of remainder, see https://en.wikipedia.org/wiki/Golomb_coding
+#[derive(Debug)]
+pub struct GolombRice<V: AsRef<[u64]>> {
+    bits: Bits<V>,
+    rice_bits: usize,
+    size: usize,
+}
+
+/// `floor(log2(m / n))`, or 0 when `m < n`, in integer math so every platform agrees.
+fn rice_bits(n: usize, m: usize) -> usize {
+    (m / n).checked_ilog2().unwrap_or(0) as usize
+}
+
+impl GolombRice<Vec<u64>> {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+
+        validate_sorted(&ids)?;
+
+        let m = *ids.last().ok_or(Error::no_ids())?;
+        let n = ids.len();
+        let rice_bits = rice_bits(n, m);
+
+        let mut bits = Bits::new();
+        let mut previous = None;
+        for id in ids.iter() {
+            let gap = match previous {
+                None => *id,
+                Some(p) => id - p - 1,
+            };
+            bits.append_ones(gap >> rice_bits);
+            bits.append_zeros(1);
+            bits.append_from(gap as u64, rice_bits);
+            previous = Some(*id);
+        }
+
+        Ok(Self { bits, rice_bits, size: n })
+    }
+
+    /// Upper bound on the length of `as_bytes()`: every id pays the terminator and remainder bits,
+    /// and the unary quotients can't add up to more than `universe >> rice_bits`.
+    pub fn estimate_size(n: usize, universe: usize) -> usize {
+        let n = n.max(1);
+        let rice_bits = rice_bits(n, universe);
+        let total_bits = n * (rice_bits + 1) + (universe >> rice_bits);
+        16 + 8 * (total_bits / 64 + 1)
+    }
+}
+
+impl<V: AsRef<[u64]>> GolombRice<V> {
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.iter().nth(index)
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.iter().find(|v| *v >= value)
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.iter().take_while(|v| *v < value).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The size and the Rice parameter as big-endian u64s, then the bits as little-endian words.
+    /// Unlike the Elias-Fano blob there is no magic, version or checksum.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.size as u64).to_be_bytes().to_vec();
+        vec.append(&mut (self.rice_bits as u64).to_be_bytes().to_vec());
+        vec.append(&mut self.bits.as_bytes());
+        vec
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        let mut position = 0;
+        let mut previous: Option<usize> = None;
+        (0..self.size).map_while(move |_| {
+            let terminator = self.bits.next_0(position)?;
+            let quotient = terminator - position;
+            let end = (terminator + 1).checked_add(self.rice_bits)?;
+            let remainder = self.bits.slice(terminator + 1, end)? as usize;
+            position = end;
+            let gap = (quotient << self.rice_bits) | remainder;
+            let value = match previous {
+                None => gap,
+                Some(p) => p.checked_add(gap)?.checked_add(1)?,
+            };
+            previous = Some(value);
+            Some(value)
+        })
+    }
+}
+
+impl<V: AsRef<[u64]>> CompressedSequence for GolombRice<V> {
+    fn len(&self) -> usize {
+        GolombRice::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<usize> {
+        GolombRice::get(self, index)
+    }
+
+    fn next_geq(&self, value: usize) -> Option<usize> {
+        GolombRice::next_geq(self, value)
+    }
+
+    fn rank(&self, value: usize) -> usize {
+        GolombRice::rank(self, value)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_> {
+        Box::new(GolombRice::iter(self))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.as_bytes()
+    }
+
+    fn estimate_size(n: usize, universe: usize) -> usize {
+        GolombRice::estimate_size(n, universe)
+    }
+}
+
+impl<'a> GolombRice<&'a [u64]> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let rice_bits = be_u64_at(data, 8)? as usize;
+        // Gaps are shifted left by it
+        if rice_bits >= 64 {
+            return Err(Error::corrupt_header(8));
+        }
+        Ok(Self {
+            size: be_u64_at(data, 0)? as usize,
+            rice_bits,
+            bits: Bits::from_bytes(rest_from(data, 16)?)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rice_get() {
+        let gr = GolombRice::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("rice encoding");
+        assert_eq!(gr.get(0), Some(2));
+        assert_eq!(gr.get(1), Some(3));
+        assert_eq!(gr.get(4), Some(11));
+        assert_eq!(gr.get(6), Some(24));
+        assert_eq!(gr.get(7), None);
+    }
+
+    #[test]
+    fn rice_next_geq_and_rank() {
+        let gr = GolombRice::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("rice encoding");
+        assert_eq!(gr.next_geq(4), Some(5));
+        assert_eq!(gr.next_geq(14), Some(24));
+        assert_eq!(gr.next_geq(25), None);
+        assert_eq!(gr.rank(12), 5);
+    }
+
+    #[test]
+    fn estimate_size_is_an_upper_bound() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let gr = GolombRice::new(data.clone()).expect("rice encoding");
+        assert!(GolombRice::estimate_size(data.len(), *data.last().unwrap()) >= gr.as_bytes().len());
+    }
+
+    #[test]
+    fn rice_bits_near_2_53() {
+        // 2^60 - 1 rounds up to 2^60 as an f64, which took the log to 60
+        assert_eq!(rice_bits(1, (1 << 60) - 1), 59);
+        assert_eq!(rice_bits(1 << 53, (1 << 53) + 1), 0);
+        assert_eq!(rice_bits(1, usize::MAX), usize::BITS as usize - 1);
+        assert_eq!(rice_bits(10, 9), 0);
+    }
+
+    #[test]
+    fn rice_rejects_unsorted() {
+        assert!(GolombRice::new(vec![3, 2]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let data: Vec<usize> = (0..300).map(|i| i * 13 + i % 7).chain(10_000..10_003).collect();
+        let gr = GolombRice::new(data.clone()).expect("rice encoding");
+        let gr_serialized = gr.as_bytes();
+        let gr = GolombRice::from_bytes(gr_serialized.as_slice()).expect("deserialized");
+        assert_eq!(gr.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn rejects_rice_bits_past_a_word() {
+        let mut data = GolombRice::new(vec![2, 3, 5]).expect("rice encoding").as_bytes();
+        data[8..16].copy_from_slice(&64u64.to_be_bytes());
+        assert_eq!(GolombRice::from_bytes(&data).map(|_| ()), Err(Error::corrupt_header(8)));
+    }
+}
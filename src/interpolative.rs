@@ -0,0 +1,255 @@
+use crate::bits::Bits;
+use crate::elias_fano::validate_sorted;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::sequence::CompressedSequence;
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+
+// Binary interpolative coding (Moffat & Stuiver): the middle id is written relative to the range
+// its neighbours leave open, then both halves are coded recursively. Runs of consecutive ids leave
+// no room at all and cost zero bits, which is why this beats EF on clustered lists.
+#[derive(Debug)]
+pub struct Interpolative<V: AsRef<[u64]>> {
+    bits: Bits<V>,
+    universe: usize,
+    size: usize,
+}
+
+impl Interpolative<Vec<u64>> {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+
+        validate_sorted(&ids)?;
+
+        let universe = *ids.last().ok_or(Error::no_ids())?;
+        let mut bits = Bits::new();
+        encode(&mut bits, &ids, 0, universe);
+
+        Ok(Self { bits, universe, size: ids.len() })
+    }
+
+    /// Rough length of `as_bytes()` for evenly spread ids; clustered lists come out far smaller.
+    pub fn estimate_size(n: usize, universe: usize) -> usize {
+        let n = n.max(1);
+        let total_bits = n * (bit_width(universe / n) + 1);
+        16 + 8 * (total_bits / 64 + 1)
+    }
+}
+
+fn bit_width(range: usize) -> usize {
+    (usize::BITS - range.leading_zeros()) as usize
+}
+
+fn encode(bits: &mut Bits<Vec<u64>>, ids: &[usize], low: usize, high: usize) {
+    if ids.is_empty() {
+        return;
+    }
+    let mid = ids.len() / 2;
+    let value = ids[mid];
+    let lowest = low + mid;
+    let highest = high - (ids.len() - mid - 1);
+    bits.append_from((value - lowest) as u64, bit_width(highest - lowest));
+    if mid > 0 {
+        encode(bits, &ids[..mid], low, value - 1);
+    }
+    encode(bits, &ids[mid + 1..], value + 1, high);
+}
+
+impl<V: AsRef<[u64]>> Interpolative<V> {
+    /// Decodes the whole sequence. Interpolative coding has no random access, so every query walks
+    /// the values in order up to its answer.
+    pub fn decode(&self) -> Vec<usize> {
+        let mut out = Vec::with_capacity(self.size);
+        self.walk(|value| {
+            out.push(value);
+            true
+        });
+        out
+    }
+
+    /// Calls `visit` on the values in order until it returns false. The bits were checked when
+    /// built or loaded, so they always decode.
+    fn walk(&self, visit: impl FnMut(usize) -> bool) {
+        self.try_walk(visit).expect("checked when built or loaded");
+    }
+
+    /// `walk`, returning `None` if the bits don't decode to values within the universe.
+    fn try_walk(&self, mut visit: impl FnMut(usize) -> bool) -> Option<()> {
+        let mut position = 0;
+        self.walk_range(self.size, &mut position, 0, self.universe, &mut visit)?;
+        Some(())
+    }
+
+    /// Walks `len` values in `low..=high`, written middle first. Their bits are in that order too,
+    /// so the middle is read before the left half is walked, but visited after it. Returns whether
+    /// to carry on.
+    fn walk_range(&self, len: usize, position: &mut usize, low: usize, high: usize, visit: &mut impl FnMut(usize) -> bool) -> Option<bool> {
+        if len == 0 {
+            return Some(true);
+        }
+        let mid = len / 2;
+        let lowest = low.checked_add(mid)?;
+        let highest = high.checked_sub(len - mid - 1)?;
+        let width = bit_width(highest.checked_sub(lowest)?);
+        let value = lowest.checked_add(self.bits.slice(*position, position.checked_add(width)?)? as usize)?;
+        if value > highest {
+            return None;
+        }
+        *position += width;
+        if mid > 0 && !self.walk_range(mid, position, low, value - 1, visit)? {
+            return Some(false);
+        }
+        if !visit(value) {
+            return Some(false);
+        }
+        self.walk_range(len - mid - 1, position, value + 1, high, visit)
+    }
+
+    pub fn get(&self, index: usize) -> Option<usize> {
+        if index >= self.size {
+            return None;
+        }
+        let (mut seen, mut found) = (0, None);
+        self.walk(|value| {
+            if seen == index {
+                found = Some(value);
+            }
+            seen += 1;
+            found.is_none()
+        });
+        found
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        let mut found = None;
+        self.walk(|v| {
+            if v >= value {
+                found = Some(v);
+            }
+            found.is_none()
+        });
+        found
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        let mut rank = 0;
+        self.walk(|v| {
+            if v < value {
+                rank += 1;
+            }
+            v < value
+        });
+        rank
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.size as u64).to_be_bytes().to_vec();
+        vec.append(&mut (self.universe as u64).to_be_bytes().to_vec());
+        vec.append(&mut self.bits.as_bytes());
+        vec
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> {
+        self.decode().into_iter()
+    }
+}
+
+impl<V: AsRef<[u64]>> CompressedSequence for Interpolative<V> {
+    fn len(&self) -> usize {
+        Interpolative::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<usize> {
+        Interpolative::get(self, index)
+    }
+
+    fn next_geq(&self, value: usize) -> Option<usize> {
+        Interpolative::next_geq(self, value)
+    }
+
+    fn rank(&self, value: usize) -> usize {
+        Interpolative::rank(self, value)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_> {
+        Box::new(Interpolative::iter(self))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.as_bytes()
+    }
+
+    fn estimate_size(n: usize, universe: usize) -> usize {
+        Interpolative::estimate_size(n, universe)
+    }
+}
+
+impl<'a> Interpolative<&'a [u64]> {
+    /// Checks the whole payload decodes, so queries on the result can't meet corrupt bits.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let universe = usize::try_from(be_u64_at(data, 8)?).map_err(|_| Error::corrupt_header(8))?;
+        // Strictly increasing values up to the universe can't be more than it allows
+        let size = usize::try_from(be_u64_at(data, 0)?).ok()
+            .filter(|size| *size <= universe.saturating_add(1))
+            .ok_or(Error::corrupt_header(0))?;
+        let bic = Self { size, universe, bits: Bits::from_bytes(rest_from(data, 16)?)? };
+        bic.try_walk(|_| true).ok_or(Error::invalid_bits_data(data.len()))?;
+        Ok(bic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elias_fano::EliasFano;
+
+    #[test]
+    fn bic_get() {
+        let bic = Interpolative::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("interpolative encoding");
+        assert_eq!(bic.get(0), Some(2));
+        assert_eq!(bic.get(4), Some(11));
+        assert_eq!(bic.get(6), Some(24));
+        assert_eq!(bic.get(7), None);
+        assert_eq!(bic.next_geq(14), Some(24));
+        assert_eq!(bic.rank(12), 5);
+    }
+
+    #[test]
+    fn bic_beats_ef_on_clustered_ids() {
+        let ids: Vec<usize> = (0..1000).chain(50_000..51_000).chain(90_000..90_500).collect();
+        let bic = Interpolative::new(ids.clone()).expect("interpolative encoding");
+        let ef = EliasFano::new(ids.clone()).expect("elias fano encoding");
+        assert_eq!(bic.iter().collect::<Vec<_>>(), ids);
+        assert!(bic.as_bytes().len() < ef.as_bytes().len());
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let data: Vec<usize> = (0..300).map(|i| i * 13 + i % 7).collect();
+        let bic = Interpolative::new(data.clone()).expect("interpolative encoding");
+        let bic_serialized = bic.as_bytes();
+        let bic = Interpolative::from_bytes(bic_serialized.as_slice()).expect("deserialized");
+        assert_eq!(bic.iter().collect::<Vec<_>>(), data);
+        assert_eq!((bic.get(150), bic.next_geq(data[150] + 1), bic.rank(data[150])), (Some(data[150]), Some(data[151]), 150));
+    }
+
+    #[test]
+    fn from_bytes_rejects_impossible_headers() {
+        let bic = Interpolative::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("interpolative encoding");
+        let mut data = bic.as_bytes();
+        data[..8].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Interpolative::from_bytes(&data).is_err());
+        data[..8].copy_from_slice(&26u64.to_be_bytes());
+        assert!(Interpolative::from_bytes(&data).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::metadata::{parse_header, Codec, Metadata};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// A blob whose header is parsed on open but whose bits aren't read until the first query, e.g.
+/// one of thousands of sequences in a memory-mapped archive of which few are used. The checksum
+/// and the bits are checked by that first `sequence` call; the outcome is kept, so later calls
+/// cost nothing and a corrupt blob fails every time.
+#[derive(Debug)]
+pub struct LazyEliasFano<'a> {
+    data: &'a [u8],
+    metadata: Metadata,
+    loaded: OnceLock<Result<EliasFano<Cow<'a, [u64]>>, Error>>,
+}
+
+impl<'a> LazyEliasFano<'a> {
+    /// Reads only the header at the start of `data`, and checks `data` is long enough for the rest.
+    pub fn open(data: &'a [u8]) -> Result<Self, Error> {
+        let metadata = parse_header(data)?;
+        if metadata.codec != Codec::EliasFano {
+            return Err(Error::unknown_codec(metadata.codec.tag()));
+        }
+        let total_len = metadata.total_len();
+        if data.len() < total_len {
+            return Err(Error::truncated_data(data.len(), total_len));
+        }
+        Ok(Self { data: &data[..total_len], metadata, loaded: OnceLock::new() })
+    }
+
+    /// The parsed header. The checksum, stored after the bits, is left as `None`.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn len(&self) -> usize {
+        self.metadata.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata.size == 0
+    }
+
+    /// Whether the bits have been read yet.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.get().is_some()
+    }
+
+    /// The sequence, loaded zero-copy if `data` is aligned on the first call.
+    pub fn sequence(&self) -> Result<&EliasFano<Cow<'a, [u64]>>, Error> {
+        self.loaded.get_or_init(|| EliasFano::load(self.data)).as_ref().map_err(Clone::clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_on_first_query() {
+        let data: Vec<usize> = (0..5000).map(|i| i * 7 + i % 3).collect();
+        let mut bytes = EliasFano::new(&data).expect("elias fano encoding").as_bytes();
+        bytes.extend_from_slice(b"trailing");
+
+        let lazy = LazyEliasFano::open(&bytes).expect("opened");
+        assert_eq!(lazy.len(), 5000);
+        assert!(!lazy.is_loaded());
+        assert_eq!(lazy.sequence().expect("loaded").get(4999), Some(data[4999]));
+        assert!(lazy.is_loaded());
+
+        // Corruption in the bits only shows once they are read
+        bytes[1000] ^= 1;
+        let lazy = LazyEliasFano::open(&bytes).expect("opened");
+        assert!(lazy.sequence().is_err());
+        assert!(lazy.sequence().is_err());
+        assert!(LazyEliasFano::open(&bytes[..100]).is_err());
+    }
+}
@@ -1,9 +1,110 @@
+// With `safe`, nothing in the crate uses unsafe code. Memory maps and the C interface can't do
+// without it, so they lift the guarantee when enabled.
+#![cfg_attr(all(feature = "safe", not(any(feature = "mmap", feature = "ffi"))), forbid(unsafe_code))]
+
+// With `tracing`, enters a debug span for the rest of the enclosing block, its fields recording
+// the size of the work, so services can attribute latency to it. Without, it expands to nothing.
+macro_rules! trace_span {
+    ($name:expr $(, $field:ident = $value:expr)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!($name $(, $field = $value)*).entered();
+    };
+}
+
+mod advice;
+mod appendable;
+mod archive;
 mod bits;
+#[cfg(feature = "bitvec")]
+mod bitvec;
+mod checksum;
+mod compress;
+mod cursor;
 mod elias_fano;
+mod elias_fano128;
+mod elias_fano32;
 mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod folly;
+mod golomb_rice;
+mod interpolative;
+mod lazy;
+mod mem_usage;
+mod masked;
+mod metadata;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod out_of_core;
+mod pairs;
+mod permuted;
+mod quantized;
+mod query;
+#[cfg(feature = "rayon")]
+mod rayon;
+#[cfg(feature = "roaring")]
+mod roaring;
+#[cfg(feature = "rand")]
+mod sampling;
+mod sd_vector;
+mod sdsl;
+mod select_index;
+mod sequence;
+#[cfg(feature = "serde")]
+mod serde_support;
+mod set_ops;
+mod signed;
+mod simple8b;
+mod split;
+mod stats;
+#[cfg(feature = "tokio")]
+mod tokio;
+mod vbyte;
+mod versioned;
+mod view;
+#[cfg(feature = "wasm")]
+mod wasm;
 
+pub use advice::*;
+pub use appendable::*;
+pub use archive::*;
+pub use bits::*;
+pub use compress::*;
+pub use cursor::*;
 pub use elias_fano::*;
+pub use elias_fano128::*;
+pub use elias_fano32::*;
 pub use errors::*;
+pub use folly::*;
+pub use golomb_rice::*;
+pub use interpolative::*;
+pub use lazy::*;
+pub use mem_usage::*;
+pub use masked::*;
+pub use metadata::*;
+#[cfg(feature = "mmap")]
+pub use mmap::*;
+pub use out_of_core::*;
+pub use pairs::*;
+pub use permuted::*;
+pub use quantized::*;
+pub use query::*;
+#[cfg(feature = "rayon")]
+pub use crate::rayon::*;
+#[cfg(feature = "roaring")]
+pub use crate::roaring::*;
+pub use sd_vector::*;
+pub use sequence::*;
+pub use set_ops::*;
+pub use signed::*;
+pub use simple8b::*;
+pub use split::*;
+pub use stats::*;
+pub use vbyte::*;
+pub use versioned::*;
+pub use view::*;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
 
 #[cfg(test)]
 mod tests {
@@ -19,7 +120,7 @@ mod tests {
             let ids: Vec<_> = ids.iter().flat_map(|i| (*i as u32).to_be_bytes()).collect();
             let mut out = vec![];
             let mut encoder = flate2::write::GzEncoder::new(&mut out, Compression::new(9));
-            encoder.write(ids.as_slice()).expect("wrote bytes");
+            encoder.write_all(ids.as_slice()).expect("wrote bytes");
             encoder.finish().expect("finished");
             out.len()
         };
@@ -0,0 +1,146 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::set_ops::merge_disjoint;
+
+/// A sequence with values deleted logically: `base` is left as it is, e.g. memory-mapped, and the
+/// deleted values are kept in a second, small sequence that queries subtract on the fly. `compact`
+/// rebuilds without them once there are enough to be worth it.
+#[derive(Debug, Clone)]
+pub struct MaskedEliasFano<V: AsRef<[u64]>> {
+    base: EliasFano<V>,
+    // Always a subset of `base`, each value repeated as often as there, so counts can be subtracted
+    deleted: EliasFano<Vec<u64>>,
+}
+
+impl<V: AsRef<[u64]>> MaskedEliasFano<V> {
+    pub fn new(base: EliasFano<V>) -> Self {
+        Self { base, deleted: EliasFano::empty() }
+    }
+
+    /// Deletes `values`, ignoring any not present, and returns how many were, counting every
+    /// repeat of a repeated value.
+    pub fn delete(&mut self, values: &[u64]) -> usize {
+        let mut present: Vec<u64> = values.iter().copied().filter(|v| self.contains_u64(*v)).collect();
+        present.sort_unstable();
+        present.dedup();
+        let repeats = present.iter().flat_map(|v| std::iter::repeat(*v).take(self.base.occurrences(*v)));
+        let deleted: Vec<u64> = merge_disjoint(self.deleted.iter_u64(), repeats).collect();
+        let before = self.deleted.len();
+        if deleted.len() > before {
+            self.deleted = EliasFano::from_u64s_with_strictness(deleted, self.base.strictness()).expect("present values merged in order");
+        }
+        self.deleted.len() - before
+    }
+
+    pub fn base(&self) -> &EliasFano<V> {
+        &self.base
+    }
+
+    pub fn deleted(&self) -> &EliasFano<Vec<u64>> {
+        &self.deleted
+    }
+
+    /// Number of values not deleted.
+    pub fn len(&self) -> usize {
+        self.base.len() - self.deleted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.contains_u64(value as u64)
+    }
+
+    pub fn contains_u64(&self, value: u64) -> bool {
+        self.base.contains_u64(value) && !self.deleted.contains_u64(value)
+    }
+
+    /// Number of values not deleted that are strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.rank_u64(value as u64)
+    }
+
+    pub fn rank_u64(&self, value: u64) -> usize {
+        self.base.rank_u64(value) - self.deleted.rank_u64(value)
+    }
+
+    /// The `index`th value not deleted.
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.get_u64(index).map(|v| v as usize)
+    }
+
+    pub fn get_u64(&self, index: usize) -> Option<u64> {
+        // The answer's rank in `base` is `index` plus the deletions up to it. Starting from `index`
+        // and adding the deletions up to the current guess never overshoots it, and stops there
+        let mut rank = index;
+        loop {
+            let value = self.base.get_u64(rank)?;
+            let next = index + self.deleted.rank_u64(value) + self.deleted.occurrences(value);
+            if next == rank {
+                return Some(value);
+            }
+            rank = next;
+        }
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.next_geq_u64(value as u64).map(|v| v as usize)
+    }
+
+    pub fn next_geq_u64(&self, value: u64) -> Option<u64> {
+        let mut cursor = self.base.cursor_at(self.base.rank_u64(value));
+        std::iter::from_fn(|| cursor.next_u64()).find(|v| !self.deleted.contains_u64(*v))
+    }
+
+    /// The values not deleted, repeats included. The deleted values are skipped with a cursor
+    /// that only moves forward.
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        let mut deleted = self.deleted.cursor();
+        self.base.iter_u64().filter(move |v| deleted.advance_to_u64(*v) != Some(*v)).map(|v| v as usize)
+    }
+
+    /// Re-encodes the values not deleted, streaming them from `base`. Fails as `patched` does on a
+    /// corrupt loaded base.
+    pub fn compact(&self) -> Result<EliasFano<Vec<u64>>, Error> {
+        self.base.patched(&[], &self.deleted.iter_u64().collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::Strictness;
+
+    #[test]
+    fn queries_skip_deleted_values() {
+        let data: Vec<u64> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let mut masked = MaskedEliasFano::new(EliasFano::from_u64s(&data).expect("elias fano encoding"));
+        let deleted: Vec<u64> = data.iter().copied().filter(|v| v % 5 == 0 || (100..300).contains(v)).collect();
+        assert_eq!(masked.delete(&deleted), deleted.len());
+        assert_eq!(masked.delete(&[deleted[0], 1]), 0);
+
+        let kept: Vec<u64> = data.iter().copied().filter(|v| !deleted.contains(v)).collect();
+        assert_eq!(masked.len(), kept.len());
+        assert_eq!(masked.iter().map(|v| v as u64).collect::<Vec<_>>(), kept);
+        for (index, value) in kept.iter().enumerate() {
+            assert_eq!(masked.get_u64(index), Some(*value));
+            assert_eq!(masked.rank_u64(*value), index);
+        }
+        assert_eq!(masked.get_u64(kept.len()), None);
+        assert_eq!(masked.next_geq_u64(100), kept.iter().copied().find(|v| *v >= 100));
+        assert!(!masked.contains_u64(deleted[3]) && masked.contains_u64(kept[3]));
+        assert_eq!(masked.compact().expect("compacted").iter_u64().collect::<Vec<_>>(), kept);
+    }
+
+    #[test]
+    fn deleting_a_repeated_value_deletes_every_repeat() {
+        let base = EliasFano::from_u64s_with_strictness([1u64, 5, 5, 9, 9], Strictness::NonDecreasing).expect("elias fano encoding");
+        let mut masked = MaskedEliasFano::new(base);
+        assert_eq!(masked.delete(&[5, 5, 2]), 2);
+        assert_eq!((masked.len(), masked.iter().collect::<Vec<_>>()), (3, vec![1, 9, 9]));
+        assert_eq!((masked.get_u64(1), masked.get_u64(2), masked.rank_u64(9)), (Some(9), Some(9), 1));
+        assert_eq!(masked.compact().expect("compacted").iter_u64().collect::<Vec<_>>(), [1, 9, 9]);
+    }
+}
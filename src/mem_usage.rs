@@ -0,0 +1,103 @@
+use crate::elias_fano::EliasFano;
+use crate::select_index::{LazySelectIndex, SelectIndex};
+use std::borrow::Cow;
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// Bytes of memory behind each part of an `EliasFano`, from `mem_usage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemUsage {
+    /// Heap allocated for the upper bits, 0 when their words are borrowed.
+    pub upper_bits: usize,
+    /// Heap allocated for the lower bits, 0 when their words are borrowed.
+    pub lower_bits: usize,
+    /// The select index over the upper bits, with its `Arc` allocation. Clones share one index,
+    /// which takes little until the first query builds it.
+    pub select_index: usize,
+    /// Bytes of borrowed words the bits refer to, e.g. in a memory map or a loaded buffer. They
+    /// belong to someone else, so `total` leaves them out.
+    pub borrowed: usize,
+}
+
+impl MemUsage {
+    /// Heap bytes the sequence holds itself.
+    pub fn total(&self) -> usize {
+        self.upper_bits + self.lower_bits + self.select_index
+    }
+}
+
+/// Storage for the words of an `EliasFano` that can tell `mem_usage` whether it owns them.
+pub trait WordStorage: AsRef<[u64]> {
+    /// Heap bytes allocated for the words, or `None` when they're borrowed.
+    fn heap_size(&self) -> Option<usize>;
+}
+
+impl WordStorage for Vec<u64> {
+    fn heap_size(&self) -> Option<usize> {
+        Some(self.capacity() * size_of::<u64>())
+    }
+}
+
+impl WordStorage for &[u64] {
+    fn heap_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl WordStorage for Cow<'_, [u64]> {
+    fn heap_size(&self) -> Option<usize> {
+        match self {
+            Cow::Owned(words) => words.heap_size(),
+            Cow::Borrowed(_) => None,
+        }
+    }
+}
+
+/// Counted in full, though clones share the allocation.
+impl WordStorage for Arc<[u64]> {
+    fn heap_size(&self) -> Option<usize> {
+        Some(self.len() * size_of::<u64>() + 2 * size_of::<usize>())
+    }
+}
+
+impl<V: WordStorage> EliasFano<V> {
+    /// Heap bytes held by each part, for cache accounting. Owned bits count their whole
+    /// allocation, spare capacity included; borrowed ones are reported apart, in `borrowed`.
+    pub fn mem_usage(&self) -> MemUsage {
+        let upper_bits = self.upper_bits.bits.heap_size();
+        let lower_bits = self.lower_bits.bits.heap_size();
+        let borrowed = upper_bits.map_or(self.upper_bits.byte_len(), |_| 0) + lower_bits.map_or(self.lower_bits.byte_len(), |_| 0);
+        MemUsage {
+            upper_bits: upper_bits.unwrap_or(0),
+            lower_bits: lower_bits.unwrap_or(0),
+            select_index: self.upper_index.built().map_or(0, SelectIndex::heap_size) + size_of::<LazySelectIndex>() + 2 * size_of::<usize>(),
+            borrowed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mem_usage_covers_every_part() {
+        let ef = EliasFano::new((0..10_000).map(|i| i * 37 + i % 5)).expect("elias fano encoding");
+        let unbuilt = ef.mem_usage();
+        ef.build_select_index();
+        let usage = ef.mem_usage();
+        assert!(unbuilt.select_index < usage.select_index);
+        assert_eq!(usage.upper_bits, ef.upper_bits.bits.capacity() * 8);
+        assert_eq!(usage.lower_bits, ef.lower_bits.bits.capacity() * 8);
+        // Counts and samples only, the words are the upper bits'
+        assert!(usage.select_index < usage.upper_bits);
+        assert_eq!(usage.total(), usage.upper_bits + usage.lower_bits + usage.select_index);
+        assert_eq!(usage.borrowed, 0);
+
+        let data = ef.as_bytes();
+        let loaded = EliasFano::from_bytes(&data).expect("deserialized");
+        let usage = loaded.mem_usage();
+        assert_eq!((usage.upper_bits, usage.lower_bits), (0, 0));
+        assert_eq!(usage.borrowed, ef.upper_bits.byte_len() + ef.lower_bits.byte_len());
+    }
+}
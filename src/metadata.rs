@@ -0,0 +1,573 @@
+use crate::checksum::crc32;
+use crate::errors::Error;
+use crate::select_index::{DEFAULT_SAMPLE_RATE, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use crate::sequence::Strictness;
+use std::convert::{TryFrom, TryInto};
+
+pub const MAGIC: [u8; 4] = *b"PEF\0";
+
+pub const VERSION: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    EliasFano,
+}
+
+impl Codec {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Codec::EliasFano => 0,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(Codec::EliasFano),
+            _ => Err(Error::unknown_codec(tag)),
+        }
+    }
+}
+
+/// Everything in a serialized header, read without touching the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub codec: Codec,
+    /// Format version, 0 for legacy blobs written before the magic prefix existed.
+    pub version: u8,
+    pub size: usize,
+    pub num_upper_bits: usize,
+    pub num_lower_bits: usize,
+    pub header_len: usize,
+    /// Both bit arrays start at a multiple of this many bytes from the start of the blob.
+    pub alignment: usize,
+    pub upper_bits_offset: usize,
+    pub upper_bits_len: usize,
+    /// The declared largest value, stored by full headers from version 5 on. Other headers leave
+    /// it `None`, meaning the last value. Kept as a `u64` so a 32-bit target reads it whole.
+    pub universe: Option<u64>,
+    /// Ones and zeros per select sample the index is rebuilt with, stored by full headers from
+    /// version 5 on and 512 otherwise.
+    pub select_sample_rate: usize,
+    /// Whether values may repeat, recorded by full headers from version 5 on. Everything else
+    /// was written strictly increasing.
+    pub strictness: Strictness,
+    pub lower_bits_offset: usize,
+    pub lower_bits_len: usize,
+    /// CRC-32 of everything before it, stored from version 2 on.
+    pub checksum: Option<u32>,
+}
+
+/*
+Serialized layout (version 5). Every multi-byte integer is little-endian, the same order the bit
+payload words have always used.
+
+ 0..4   magic "PEF\0"
+ 4      version
+ 5      codec tag
+ 6      log2 of the alignment of both bit arrays, at least 3
+ 7      bits 0-6: log2 of the select sample rate, or 0 for the default of 512
+        bit 7: set when values may repeat
+ 8..16  size
+16..24  num_upper_bits
+24..32  num_lower_bits
+32..40  upper_bits_len in bytes
+40..48  universe, the declared largest value, at least the last one
+48..    zero padding up to the alignment, upper bits, zero padding, lower bits, as u64 words
+last 4  CRC-32 of everything before it
+
+Sequences small enough that the four fields fit in 7 bytes of varints, up to a few thousand values,
+get a compact header instead, unless an alignment is asked for, the universe is above the last
+value, the select sample rate isn't the default or values may repeat, none of which the compact
+header can record:
+
+ 0      0xE0 | codec tag
+ 1..8   size, num_upper_bits, num_lower_bits, upper_bits_len as LEB128 varints, zero padded
+ 8..    upper bits, lower bits, as u64 words
+last 4  CRC-32 of everything before it
+
+A legacy blob can't start with 0xE0, that would take a size of at least 0xE0 << 56.
+
+Older layouts are still read, taking the universe to be the last value:
+- version 4: the header ends at 40, with no universe, and byte 7 is reserved
+- version 3: byte 6 is reserved and the alignment is always 8, so there is no padding
+- version 2: the header fields and the checksum are big-endian
+- version 1: as version 2, with no checksum
+- legacy (version 0): as version 1, without the 8 byte prefix
+
+This covers the Elias-Fano blob only. The other codecs (Golomb-Rice, VByte, Simple-8b,
+interpolative, quantized, signed, split and the 128-bit Elias-Fano) still start with bare big-endian
+u64 fields, with no magic, version or checksum, and keep that layout until they get framing of their
+own; `be_u64_at` reads them.
+ */
+pub const PREFIX_LEN: usize = 8;
+pub const SIZE_OFFSET: usize = 8;
+pub const NUM_UPPER_BITS_OFFSET: usize = 16;
+pub const NUM_LOWER_BITS_OFFSET: usize = 24;
+pub const UPPER_BITS_LEN_OFFSET: usize = 32;
+pub const UNIVERSE_OFFSET: usize = 40;
+pub const HEADER_LEN: usize = 48;
+pub const CHECKSUM_LEN: usize = 4;
+pub const ALIGNMENT_OFFSET: usize = 6;
+pub const SAMPLE_RATE_OFFSET: usize = 7;
+/// Set in the sample rate byte when values may repeat.
+pub const NON_DECREASING_FLAG: u8 = 0x80;
+pub const DEFAULT_ALIGNMENT: usize = 8;
+pub const COMPACT_MARKER: u8 = 0xE0;
+pub const COMPACT_HEADER_LEN: usize = 8;
+
+/// What a header records, whichever layout it is written in.
+pub(crate) struct HeaderFields {
+    pub(crate) codec: Codec,
+    pub(crate) size: usize,
+    pub(crate) num_upper_bits: usize,
+    pub(crate) num_lower_bits: usize,
+    pub(crate) upper_bits_len: usize,
+    pub(crate) universe: u64,
+    /// The last value, which the universe has to equal for the compact header to be used.
+    pub(crate) last: u64,
+    pub(crate) select_sample_rate: usize,
+    pub(crate) strictness: Strictness,
+}
+
+pub(crate) fn write_header(vec: &mut Vec<u8>, fields: &HeaderFields, alignment: usize) {
+    vec.extend_from_slice(&MAGIC);
+    // The default rate is written as 0, so headers from before the rate was configurable are unchanged
+    let sample_rate_log2 = if fields.select_sample_rate == DEFAULT_SAMPLE_RATE { 0 } else { fields.select_sample_rate.trailing_zeros() as u8 };
+    let sample_rate_log2 = match fields.strictness {
+        Strictness::StrictlyIncreasing => sample_rate_log2,
+        Strictness::NonDecreasing => sample_rate_log2 | NON_DECREASING_FLAG,
+    };
+    vec.extend_from_slice(&[VERSION, fields.codec.tag(), alignment.trailing_zeros() as u8, sample_rate_log2]);
+    for field in [fields.size, fields.num_upper_bits, fields.num_lower_bits, fields.upper_bits_len] {
+        vec.extend_from_slice(&(field as u64).to_le_bytes());
+    }
+    vec.extend_from_slice(&fields.universe.to_le_bytes());
+}
+
+/// Writes the compact header if the fields fit in it, returning whether they did.
+pub(crate) fn write_compact_header(vec: &mut Vec<u8>, fields: &HeaderFields) -> bool {
+    if fields.universe != fields.last || fields.select_sample_rate != DEFAULT_SAMPLE_RATE
+        || fields.strictness != Strictness::StrictlyIncreasing {
+        return false;
+    }
+    let mut header = vec![COMPACT_MARKER | fields.codec.tag()];
+    for field in [fields.size, fields.num_upper_bits, fields.num_lower_bits, fields.upper_bits_len] {
+        write_varint(&mut header, field as u64);
+    }
+    if header.len() > COMPACT_HEADER_LEN {
+        return false;
+    }
+    header.resize(COMPACT_HEADER_LEN, 0);
+    vec.extend_from_slice(&header);
+    true
+}
+
+/// The header `write_to` uses: compact if the fields fit, otherwise the full one with the default
+/// alignment.
+pub(crate) fn write_default_header(vec: &mut Vec<u8>, fields: &HeaderFields) {
+    if !write_compact_header(vec, fields) {
+        write_header(vec, fields, DEFAULT_ALIGNMENT);
+    }
+}
+
+/// Parses the header of a blob written by `as_bytes`, including older versions.
+pub fn describe(data: &[u8]) -> Result<Metadata, Error> {
+    let mut metadata = parse_header(data)?;
+    let total_len = metadata.total_len();
+    if data.len() < total_len {
+        return Err(Error::truncated_data(data.len(), total_len));
+    }
+    if metadata.version >= 2 {
+        let bytes = data[total_len - CHECKSUM_LEN..total_len].try_into().expect("length checked above");
+        metadata.checksum = Some(read_checksum(bytes, metadata.version));
+    }
+    Ok(metadata)
+}
+
+/// How many bytes the header at the start of `data` takes, which its first 8 bytes decide.
+pub(crate) fn header_len(data: &[u8]) -> usize {
+    if data.starts_with(&MAGIC) {
+        if data.get(4).is_some_and(|version| *version >= 5) { HEADER_LEN } else { UNIVERSE_OFFSET }
+    } else if is_compact(data) {
+        COMPACT_HEADER_LEN
+    } else {
+        UNIVERSE_OFFSET - PREFIX_LEN
+    }
+}
+
+fn is_compact(data: &[u8]) -> bool {
+    data.first().is_some_and(|byte| byte & 0xF0 == COMPACT_MARKER)
+}
+
+/// Parses just the header bytes. Payload lengths follow from the header alone, but the checksum
+/// lives after the payload and is left as `None`.
+pub(crate) fn parse_header(data: &[u8]) -> Result<Metadata, Error> {
+    if is_compact(data) {
+        return parse_compact_header(data);
+    }
+    let (version, codec) = if data.starts_with(&MAGIC) {
+        if data.len() < PREFIX_LEN {
+            return Err(Error::truncated_data(data.len(), PREFIX_LEN));
+        }
+        if !(1..=VERSION).contains(&data[4]) {
+            return Err(Error::unsupported_version(data[4]));
+        }
+        (data[4], Codec::from_tag(data[5])?)
+    } else {
+        (0, Codec::EliasFano)
+    };
+
+    // Offsets below are for the current layout; legacy blobs start 8 bytes earlier
+    let header_len = header_len(data);
+    let shift = if version == 0 { PREFIX_LEN } else { 0 };
+    if data.len() < header_len {
+        return Err(Error::truncated_data(data.len(), header_len));
+    }
+    let alignment = if version >= 4 {
+        let log2 = data[ALIGNMENT_OFFSET];
+        if !(3..32).contains(&log2) {
+            return Err(Error::corrupt_header(ALIGNMENT_OFFSET));
+        }
+        1 << log2
+    } else {
+        DEFAULT_ALIGNMENT
+    };
+    let flags = if version >= 5 { data[SAMPLE_RATE_OFFSET] } else { 0 };
+    let strictness = if flags & NON_DECREASING_FLAG == 0 { Strictness::StrictlyIncreasing } else { Strictness::NonDecreasing };
+    let select_sample_rate = if flags & !NON_DECREASING_FLAG == 0 {
+        DEFAULT_SAMPLE_RATE
+    } else {
+        let log2 = (flags & !NON_DECREASING_FLAG) as u32;
+        if !(MIN_SAMPLE_RATE.trailing_zeros()..=MAX_SAMPLE_RATE.trailing_zeros()).contains(&log2) {
+            return Err(Error::corrupt_header(SAMPLE_RATE_OFFSET));
+        }
+        1 << log2
+    };
+    let field = |offset: usize| read_u64(data, offset - shift, version >= 3) as usize;
+    let universe = if version >= 5 {
+        Some(read_u64(data, UNIVERSE_OFFSET, true))
+    } else {
+        None
+    };
+    let size = field(SIZE_OFFSET);
+    let num_lower_bits = field(NUM_LOWER_BITS_OFFSET);
+    // Bits always holds one word more than it has filled
+    let lower_bits_len = size.checked_mul(num_lower_bits)
+        .map(|bits| (bits / 64 + 1) * 8)
+        .ok_or(Error::corrupt_header(NUM_LOWER_BITS_OFFSET - shift))?;
+    let upper_bits_len = field(UPPER_BITS_LEN_OFFSET);
+    let upper_bits_offset = align_up(header_len, alignment);
+    let lower_bits_offset = upper_bits_offset.checked_add(upper_bits_len)
+        .map(|end| align_up(end, alignment))
+        .ok_or(Error::corrupt_header(UPPER_BITS_LEN_OFFSET - shift))?;
+    // total_len adds these up, so make sure it can
+    lower_bits_offset.checked_add(lower_bits_len)
+        .and_then(|end| end.checked_add(CHECKSUM_LEN))
+        .ok_or(Error::corrupt_header(NUM_LOWER_BITS_OFFSET - shift))?;
+    Ok(Metadata {
+        codec,
+        version,
+        size,
+        num_upper_bits: field(NUM_UPPER_BITS_OFFSET),
+        num_lower_bits,
+        header_len,
+        alignment,
+        upper_bits_offset,
+        upper_bits_len,
+        universe,
+        select_sample_rate,
+        strictness,
+        lower_bits_offset,
+        lower_bits_len,
+        checksum: None,
+    })
+}
+
+fn parse_compact_header(data: &[u8]) -> Result<Metadata, Error> {
+    let header = data.get(..COMPACT_HEADER_LEN).ok_or(Error::truncated_data(data.len(), COMPACT_HEADER_LEN))?;
+    let codec = Codec::from_tag(header[0] & !COMPACT_MARKER)?;
+    let mut position = 1;
+    let mut field = || {
+        let start = position;
+        read_varint(header, &mut position)
+            .and_then(|value| usize::try_from(value).ok())
+            .ok_or(Error::corrupt_header(start))
+    };
+    let (size, num_upper_bits, num_lower_bits, upper_bits_len) = (field()?, field()?, field()?, field()?);
+    let lower_bits_len = size.checked_mul(num_lower_bits)
+        .map(|bits| (bits / 64 + 1) * 8)
+        .ok_or(Error::corrupt_header(1))?;
+    let lower_bits_offset = COMPACT_HEADER_LEN.checked_add(upper_bits_len).ok_or(Error::corrupt_header(1))?;
+    lower_bits_offset.checked_add(lower_bits_len)
+        .and_then(|end| end.checked_add(CHECKSUM_LEN))
+        .ok_or(Error::corrupt_header(1))?;
+    Ok(Metadata {
+        codec,
+        version: VERSION,
+        size,
+        num_upper_bits,
+        num_lower_bits,
+        header_len: COMPACT_HEADER_LEN,
+        alignment: DEFAULT_ALIGNMENT,
+        upper_bits_offset: COMPACT_HEADER_LEN,
+        upper_bits_len,
+        universe: None,
+        select_sample_rate: DEFAULT_SAMPLE_RATE,
+        strictness: Strictness::StrictlyIncreasing,
+        lower_bits_offset,
+        lower_bits_len,
+        checksum: None,
+    })
+}
+
+fn write_varint(vec: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        vec.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    vec.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7F) as u64).checked_shl(shift)?;
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+pub(crate) fn align_up(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+pub(crate) fn read_checksum(bytes: [u8; CHECKSUM_LEN], version: u8) -> u32 {
+    if version >= 3 { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+}
+
+impl Metadata {
+    /// Length of the whole blob: header, both bit arrays and the checksum if there is one.
+    pub fn total_len(&self) -> usize {
+        let trailer_len = if self.version >= 2 { CHECKSUM_LEN } else { 0 };
+        self.lower_bits_offset + self.lower_bits_len + trailer_len
+    }
+
+    /// Where `field`, one of the `_OFFSET`s of the current layout, is in this blob's header, for
+    /// errors. Compact headers have no fixed offsets, so for them it's where the fields start.
+    pub(crate) fn field_offset(&self, field: usize) -> usize {
+        match (self.header_len, self.version) {
+            (COMPACT_HEADER_LEN, _) => 1,
+            (_, 0) => field - PREFIX_LEN,
+            _ => field,
+        }
+    }
+}
+
+/// Rewrites a blob in any readable layout into the one `as_bytes` writes, with the default alignment. Only the
+/// header is rewritten: the payload words are copied over as they are, without decoding them. Checksums present
+/// in the input are verified. Older headers leave the universe at the last value, which is read from the payload.
+pub fn migrate(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let metadata = describe(data)?;
+    verify_checksum(data, &metadata)?;
+    if metadata.upper_bits_len % 8 != 0 {
+        return Err(Error::corrupt_header(metadata.field_offset(UPPER_BITS_LEN_OFFSET)));
+    }
+    // describe checked that the blob holds both
+    let upper = &data[metadata.upper_bits_offset..metadata.upper_bits_offset + metadata.upper_bits_len];
+    let lower = &data[metadata.lower_bits_offset..metadata.lower_bits_offset + metadata.lower_bits_len];
+    let last = match metadata.size {
+        0 => 0,
+        _ => last_value(&metadata, upper, lower).ok_or(Error::corrupt_header(metadata.field_offset(SIZE_OFFSET)))?,
+    };
+    let fields = HeaderFields {
+        codec: metadata.codec,
+        size: metadata.size,
+        num_upper_bits: metadata.num_upper_bits,
+        num_lower_bits: metadata.num_lower_bits,
+        upper_bits_len: metadata.upper_bits_len,
+        universe: metadata.universe.unwrap_or(last),
+        last,
+        select_sample_rate: metadata.select_sample_rate,
+        strictness: metadata.strictness,
+    };
+    let mut vec = Vec::with_capacity(HEADER_LEN + upper.len() + lower.len() + CHECKSUM_LEN);
+    write_default_header(&mut vec, &fields);
+    vec.extend_from_slice(upper);
+    vec.extend_from_slice(lower);
+    let checksum = crc32(&vec);
+    vec.extend_from_slice(&checksum.to_le_bytes());
+    Ok(vec)
+}
+
+/// The last value of a payload, from the highest set upper bit and the last lower bits, the way
+/// `EliasFano::last_u64` finds it. Words are little-endian, so bit `i` is in byte `i / 8`, and
+/// lower bits are stored most significant first.
+fn last_value(metadata: &Metadata, upper: &[u8], lower: &[u8]) -> Option<u64> {
+    let index = metadata.size.checked_sub(1)?;
+    let byte_index = upper.iter().rposition(|byte| *byte != 0)?;
+    let position = 8 * byte_index + 7 - upper[byte_index].leading_zeros() as usize;
+    let high = position.checked_sub(index)? as u64;
+    let l = metadata.num_lower_bits;
+    if l > 63 {
+        return None;
+    }
+    let start = index.checked_mul(l)?;
+    let low = (0..l).try_fold(0u64, |low, i| {
+        let bit = start.checked_add(i)?;
+        Some(low << 1 | (lower.get(bit / 8)? >> (bit % 8) & 1) as u64)
+    })?;
+    Some(high.checked_shl(l as u32)? | low)
+}
+
+pub(crate) fn verify_checksum(data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+    if let Some(expected) = metadata.checksum {
+        let actual = crc32(&data[..metadata.total_len() - CHECKSUM_LEN]);
+        if actual != expected {
+            return Err(Error::checksum_mismatch(expected, actual));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the big-endian u64 at `offset` that starts the headers of the codecs other than
+/// Elias-Fano. Those headers weren't moved to little-endian with the Elias-Fano one; see the
+/// layout above.
+pub(crate) fn be_u64_at(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let bytes = data.get(offset..offset + 8).ok_or(Error::truncated_data(data.len(), offset + 8))?;
+    Ok(u64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+}
+
+/// The bytes from `offset` on, failing if `data` ends before it.
+pub(crate) fn rest_from(data: &[u8], offset: usize) -> Result<&[u8], Error> {
+    data.get(offset..).ok_or(Error::truncated_data(data.len(), offset))
+}
+
+fn read_u64(data: &[u8], offset: usize, little_endian: bool) -> u64 {
+    let bytes = data[offset..offset + 8].try_into().expect("length checked by caller");
+    if little_endian { u64::from_le_bytes(bytes) } else { u64::from_be_bytes(bytes) }
+}
+
+/// Rewrites a current blob in the legacy layout, for testing that old data still loads.
+#[cfg(test)]
+pub(crate) fn to_legacy(data: &[u8]) -> Vec<u8> {
+    let metadata = describe(data).expect("metadata");
+    let mut vec = vec![];
+    for field in [metadata.size, metadata.num_upper_bits, metadata.num_lower_bits, metadata.upper_bits_len] {
+        vec.extend_from_slice(&(field as u64).to_be_bytes());
+    }
+    vec.extend_from_slice(&data[metadata.upper_bits_offset..data.len() - CHECKSUM_LEN]);
+    vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elias_fano::EliasFano;
+
+    /// `as_bytes` with the full header, which small sequences don't get by default.
+    fn full_header_bytes(ef: &EliasFano<Vec<u64>>) -> Vec<u8> {
+        let mut data = vec![];
+        ef.write_to_aligned(&mut data, DEFAULT_ALIGNMENT).expect("written");
+        data
+    }
+
+    #[test]
+    fn describe_ef() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        let metadata = describe(&data).expect("metadata");
+        assert_eq!(metadata.codec, Codec::EliasFano);
+        assert_eq!(metadata.version, VERSION);
+        assert_eq!(metadata.size, 7);
+        assert_eq!(metadata.num_lower_bits, 2);
+        assert_eq!(metadata.total_len(), data.len());
+        assert_eq!(metadata.upper_bits_offset, metadata.header_len);
+        assert_eq!(metadata.checksum, Some(crc32(&data[..data.len() - 4])));
+    }
+
+    #[test]
+    fn describe_rejects_short_data() {
+        assert!(describe(&[0; 31]).is_err());
+        let mut data = vec![0; 40];
+        data[31] = 16;
+        assert!(describe(&data).is_err());
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        assert!(describe(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn describe_legacy_layout() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        let metadata = describe(&to_legacy(&data)).expect("metadata");
+        assert_eq!(metadata.version, 0);
+        assert_eq!(metadata.size, 7);
+        assert_eq!(metadata.header_len, 32);
+    }
+
+    #[test]
+    fn describe_rejects_unknown_version_and_codec() {
+        let ef = EliasFano::new(vec![2, 3, 5]).expect("elias fano encoding");
+        let mut data = full_header_bytes(&ef);
+        data[4] = VERSION + 1;
+        assert_eq!(describe(&data).unwrap_err().to_string(), format!("Unsupported format version {}.", VERSION + 1));
+        data[4] = VERSION;
+        data[5] = 200;
+        assert!(describe(&data).is_err());
+    }
+
+    #[test]
+    fn migrate_legacy_to_current() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        assert_eq!(migrate(&to_legacy(&data)).expect("migrated"), data);
+        assert_eq!(migrate(&data).expect("migrated"), data);
+
+        let repeated = EliasFano::with_strictness((0..5000).map(|i| i / 3 * 7), Strictness::NonDecreasing).expect("elias fano encoding");
+        assert_eq!(migrate(&repeated.as_bytes()).expect("migrated"), repeated.as_bytes());
+        let sparse = EliasFano::with_universe(vec![2, 3, 5], 1 << 20).expect("elias fano encoding");
+        assert_eq!(migrate(&full_header_bytes(&sparse)).expect("migrated"), sparse.as_bytes());
+    }
+
+    #[test]
+    fn header_fields_are_little_endian() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = full_header_bytes(&ef);
+        assert_eq!(data[SIZE_OFFSET], 7);
+        assert_eq!(data[NUM_LOWER_BITS_OFFSET], 2);
+    }
+
+    #[test]
+    fn small_sequences_get_the_compact_header() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        assert_eq!(data.len(), COMPACT_HEADER_LEN + 16 + CHECKSUM_LEN);
+        assert_eq!(data.len(), ef.serialized_len());
+        let metadata = describe(&data).expect("metadata");
+        assert_eq!((metadata.header_len, metadata.size, metadata.num_lower_bits), (COMPACT_HEADER_LEN, 7, 2));
+        assert_eq!(EliasFano::from_bytes_owned(&data).expect("loaded").iter().collect::<Vec<_>>(), ef.iter().collect::<Vec<_>>());
+        assert_eq!(migrate(&full_header_bytes(&ef)).expect("migrated"), data);
+
+        let large = EliasFano::new(0..100_000).expect("elias fano encoding");
+        assert_eq!(describe(&large.as_bytes()).expect("metadata").header_len, HEADER_LEN);
+        assert_eq!(EliasFano::estimate_size(100_000, 99_999), large.serialized_len());
+        assert_eq!(EliasFano::estimate_size(7, 24), data.len());
+    }
+
+    #[test]
+    fn repeats_are_flagged_in_the_full_header() {
+        let ef = EliasFano::with_strictness(vec![2, 3, 3, 7], Strictness::NonDecreasing).expect("elias fano encoding");
+        let data = ef.as_bytes();
+        assert_eq!(data[SAMPLE_RATE_OFFSET], NON_DECREASING_FLAG);
+        let metadata = describe(&data).expect("metadata");
+        assert_eq!((metadata.header_len, metadata.strictness, metadata.select_sample_rate), (HEADER_LEN, Strictness::NonDecreasing, DEFAULT_SAMPLE_RATE));
+        let loaded = EliasFano::from_bytes_owned(&data).expect("loaded");
+        assert_eq!((loaded.strictness(), loaded.iter().collect::<Vec<_>>()), (Strictness::NonDecreasing, vec![2, 3, 3, 7]));
+        assert_eq!(describe(&EliasFano::new(vec![2, 3, 7]).expect("elias fano encoding").as_bytes()).expect("metadata").strictness, Strictness::StrictlyIncreasing);
+    }
+}
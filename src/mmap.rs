@@ -0,0 +1,92 @@
+use crate::bits::{words_from_bytes, Bits};
+use crate::elias_fano::{payload, EliasFano};
+use crate::errors::Error;
+use crate::metadata::{describe, verify_checksum};
+use crate::mem_usage::WordStorage;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A run of words inside a memory-mapped file. Clones share the mapping.
+#[derive(Clone)]
+pub struct MappedWords {
+    map: Arc<Mmap>,
+    start: usize,
+    len: usize,
+}
+
+impl MappedWords {
+    fn new(map: Arc<Mmap>, region: &[u8]) -> Result<Self, Error> {
+        // Maps are page aligned and every region starts at a multiple of 8, so this only fails on
+        // a corrupt header
+        words_from_bytes(region)?;
+        let start = region.as_ptr() as usize - map.as_ptr() as usize;
+        Ok(Self { map, start, len: region.len() })
+    }
+}
+
+impl AsRef<[u64]> for MappedWords {
+    fn as_ref(&self) -> &[u64] {
+        words_from_bytes(&self.map[self.start..self.start + self.len]).expect("checked when mapped")
+    }
+}
+
+/// Mapped pages belong to the page cache, not the heap.
+impl WordStorage for MappedWords {
+    fn heap_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl std::fmt::Debug for MappedWords {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MappedWords {{ start: {}, len: {} }}", self.start, self.len)
+    }
+}
+
+impl EliasFano<MappedWords> {
+    /// Maps a file written by `write_to` and queries it in place, without copying it to the heap.
+    /// The checksum is verified, which reads the whole file once.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(path, true)
+    }
+
+    /// Like `open` but skips the checksum, so beyond the upper bits, which are checked against the
+    /// header, only the pages queries touch are ever read.
+    pub fn open_unverified(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(path, false)
+    }
+
+    fn open_with(path: impl AsRef<Path>, verify: bool) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // The file must not be modified while mapped; that's on the caller, as with any mmap
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let metadata = describe(&map)?;
+        if verify {
+            verify_checksum(&map, &metadata)?;
+        }
+        let (upper, lower) = payload(&map, &metadata)?;
+        let upper_bits = Bits::from_words(MappedWords::new(map.clone(), upper)?);
+        let lower_bits = Bits::from_words(MappedWords::new(map.clone(), lower)?);
+        Self::from_parts(&metadata, upper_bits, lower_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_mapped_file() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let path = std::env::temp_dir().join(format!("pef-mmap-{}.ef", std::process::id()));
+        ef.write_to(&mut File::create(&path).expect("created")).expect("written");
+
+        let mapped = EliasFano::open(&path).expect("opened");
+        assert_eq!(mapped.iter().collect::<Vec<_>>(), data);
+        assert_eq!(mapped.next_geq(1000), Some(1002));
+        std::fs::remove_file(&path).expect("removed");
+    }
+}
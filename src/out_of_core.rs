@@ -0,0 +1,187 @@
+use crate::bits::Bits;
+use crate::checksum::Crc32Writer;
+use crate::elias_fano::parameters;
+use crate::errors::Error;
+use crate::metadata::{write_default_header, Codec, HeaderFields, CHECKSUM_LEN, HEADER_LEN};
+use crate::select_index::DEFAULT_SAMPLE_RATE;
+use crate::sequence::Strictness;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Builds an Elias-Fano blob from a stream of ids without holding it in memory. Completed words of
+/// both bit arrays are spilled to temporary files in `dir`, which `finish` concatenates into the
+/// output. The output is byte for byte what `EliasFano::new(ids).as_bytes()` would give.
+///
+/// Elias-Fano parameters depend on the number of ids and the largest one, so both must be known up
+/// front.
+pub struct OutOfCoreBuilder {
+    upper: SpilledBits,
+    lower: SpilledBits,
+    num_lower_bits: usize,
+    num_upper_bits: usize,
+    size: usize,
+    universe: usize,
+    pushed: usize,
+    bucket: usize,
+    previous: Option<usize>,
+    spill_words: usize,
+}
+
+impl OutOfCoreBuilder {
+    pub fn new(dir: impl AsRef<Path>, size: usize, universe: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::no_ids());
+        }
+        let (num_lower_bits, num_upper_bits) = parameters(size, universe as u64);
+        Ok(Self {
+            upper: SpilledBits::new(dir.as_ref())?,
+            lower: SpilledBits::new(dir.as_ref())?,
+            num_lower_bits,
+            num_upper_bits,
+            size,
+            universe,
+            pushed: 0,
+            bucket: 0,
+            previous: None,
+            spill_words: 1 << 16,
+        })
+    }
+
+    /// How many words each bit array may hold in memory before spilling, 64K (512KiB) by default.
+    pub fn spill_words(mut self, words: usize) -> Self {
+        self.spill_words = words.max(1);
+        self
+    }
+
+    pub fn push(&mut self, id: usize) -> Result<(), Error> {
+        if let Some(p) = self.previous.filter(|p| *p >= id) {
+            return Err(Error::out_of_order(self.pushed, p as u64, id as u64));
+        }
+        if id > self.universe {
+            return Err(Error::value_above_universe(id, self.universe));
+        }
+        if self.pushed == self.size {
+            return Err(Error::unexpected_count(self.size, self.pushed + 1));
+        }
+
+        // Close every bucket before this id's, then mark the id in its own
+        let bucket = id >> self.num_lower_bits;
+        self.upper.bits.append_zeros(bucket - self.bucket).append_ones(1);
+        self.bucket = bucket;
+        self.lower.bits.append_from(id as u64, self.num_lower_bits);
+
+        self.previous = Some(id);
+        self.pushed += 1;
+        self.upper.maybe_spill(self.spill_words)?;
+        self.lower.maybe_spill(self.spill_words)?;
+        Ok(())
+    }
+
+    /// Writes the finished blob, returning the number of bytes written.
+    pub fn finish(mut self, writer: &mut impl Write) -> Result<usize, Error> {
+        trace_span!("pef::out_of_core_finish", size = self.size);
+        if self.pushed != self.size {
+            return Err(Error::unexpected_count(self.size, self.pushed));
+        }
+        self.upper.bits.append_zeros((self.universe >> self.num_lower_bits) + 1 - self.bucket);
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        let fields = HeaderFields {
+            codec: Codec::EliasFano,
+            size: self.size,
+            num_upper_bits: self.num_upper_bits,
+            num_lower_bits: self.num_lower_bits,
+            upper_bits_len: self.upper.byte_len(),
+            universe: self.universe as u64,
+            last: self.previous.unwrap_or(0) as u64,
+            select_sample_rate: DEFAULT_SAMPLE_RATE,
+            strictness: Strictness::StrictlyIncreasing,
+        };
+        write_default_header(&mut header, &fields);
+
+        let mut writer = Crc32Writer::new(writer);
+        writer.write_all(&header)?;
+        self.upper.copy_to(&mut writer)?;
+        self.lower.copy_to(&mut writer)?;
+        let (writer, checksum, written) = writer.finish();
+        writer.write_all(&checksum.to_le_bytes())?;
+        Ok(written + CHECKSUM_LEN)
+    }
+}
+
+/// Bits whose filled words live in a temporary file, removed again on drop.
+struct SpilledBits {
+    bits: Bits<Vec<u64>>,
+    file: BufWriter<File>,
+    path: PathBuf,
+    spilled: usize,
+}
+
+impl SpilledBits {
+    fn new(dir: &Path) -> io::Result<Self> {
+        let name = format!("pef-{}-{}.spill", std::process::id(), NEXT_FILE.fetch_add(1, Ordering::Relaxed));
+        let path = dir.join(name);
+        let file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(Self { bits: Bits::new(), file: BufWriter::new(file), path, spilled: 0 })
+    }
+
+    fn maybe_spill(&mut self, spill_words: usize) -> io::Result<()> {
+        if self.bits.byte_len() / 8 > spill_words {
+            self.spilled += self.bits.spill_full_words(&mut self.file)?;
+        }
+        Ok(())
+    }
+
+    fn byte_len(&self) -> usize {
+        self.spilled + self.bits.byte_len()
+    }
+
+    fn copy_to(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(file, writer)?;
+        self.bits.write_to(writer)?;
+        Ok(())
+    }
+}
+
+impl Drop for SpilledBits {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elias_fano::EliasFano;
+
+    #[test]
+    fn matches_in_memory_construction() {
+        let ids: Vec<usize> = (0..5000).map(|i| i * 11 + i % 3).chain(100_000..100_010).collect();
+        let mut builder = OutOfCoreBuilder::new(std::env::temp_dir(), ids.len(), *ids.last().unwrap())
+            .expect("builder")
+            .spill_words(4);
+        for id in ids.iter() {
+            builder.push(*id).expect("pushed");
+        }
+        let mut out = vec![];
+        let written = builder.finish(&mut out).expect("finished");
+        assert_eq!(written, out.len());
+        assert_eq!(out, EliasFano::new(ids).expect("elias fano encoding").as_bytes());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut builder = OutOfCoreBuilder::new(std::env::temp_dir(), 2, 10).expect("builder");
+        builder.push(5).expect("pushed");
+        assert!(builder.push(5).is_err());
+        assert!(builder.push(11).is_err());
+        assert!(builder.finish(&mut vec![]).is_err());
+    }
+}
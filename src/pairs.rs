@@ -0,0 +1,82 @@
+use crate::bits::Bits;
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+
+/// `(key, value)` pairs sorted by strictly increasing key. Keys are Elias-Fano encoded and values
+/// packed next to them at a fixed width, enough bits for the largest, so one structure answers
+/// what would otherwise take two kept in step by hand.
+#[derive(Debug, Clone)]
+pub struct EliasFanoPairs {
+    keys: EliasFano<Vec<u64>>,
+    values: Bits<Vec<u64>>,
+    value_bits: usize,
+}
+
+impl EliasFanoPairs {
+    pub fn new(pairs: impl IntoIterator<Item=(u64, u64)>) -> Result<Self, Error> {
+        let (keys, values): (Vec<u64>, Vec<u64>) = pairs.into_iter().unzip();
+        let keys = EliasFano::from_u64s(keys)?;
+        let value_bits = values.iter().map(|v| (u64::BITS - v.leading_zeros()) as usize).max().unwrap_or(0);
+        let mut packed = Bits::with_capacity(values.len() * value_bits);
+        for value in values {
+            packed.append_from(value, value_bits);
+        }
+        Ok(Self { keys, values: packed, value_bits })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<(u64, u64)> {
+        Some((self.keys.get_u64(index)?, self.value(index)?))
+    }
+
+    /// The pair with the smallest key `>= key`.
+    pub fn next_geq_key(&self, key: u64) -> Option<(u64, u64)> {
+        self.get(self.keys.rank_u64(key))
+    }
+
+    /// The value stored under exactly `key`.
+    pub fn value_of(&self, key: u64) -> Option<u64> {
+        self.next_geq_key(key).filter(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn keys(&self) -> &EliasFano<Vec<u64>> {
+        &self.keys
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(u64, u64)> + '_ {
+        self.keys.iter_u64().enumerate().map_while(move |(index, key)| Some((key, self.value(index)?)))
+    }
+
+    fn value(&self, index: usize) -> Option<u64> {
+        let start = index.checked_mul(self.value_bits)?;
+        self.values.slice(start, start + self.value_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_values() {
+        let pairs: Vec<(u64, u64)> = (0..1000u64).map(|i| (i * 13 + i % 4, (i * 7919) % 5000)).collect();
+        let encoded = EliasFanoPairs::new(pairs.clone()).expect("encoded");
+        assert_eq!(encoded.iter().collect::<Vec<_>>(), pairs);
+        assert_eq!(encoded.get(400), Some(pairs[400]));
+        assert_eq!(encoded.next_geq_key(pairs[400].0 + 1), Some(pairs[401]));
+        assert_eq!(encoded.value_of(pairs[10].0), Some(pairs[10].1));
+        assert_eq!(encoded.value_of(pairs[10].0 + 1), None);
+        assert_eq!(encoded.next_geq_key(u64::MAX), None);
+
+        let zeros = EliasFanoPairs::new([(1, 0), (2, 0)]).expect("encoded");
+        assert_eq!(zeros.iter().collect::<Vec<_>>(), vec![(1, 0), (2, 0)]);
+        assert!(EliasFanoPairs::new([(2, 1), (2, 3)]).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::bits::Bits;
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use std::borrow::Borrow;
+
+/// Integers in any order, repeats allowed, e.g. a column that isn't sorted. The distinct values
+/// are Elias-Fano encoded, and each position stores the rank of its value among them in
+/// `log2(distinct)` bits.
+#[derive(Debug, Clone)]
+pub struct PermutedSequence {
+    distinct: EliasFano<Vec<u64>>,
+    ranks: Bits<Vec<u64>>,
+    rank_bits: usize,
+    size: usize,
+}
+
+impl PermutedSequence {
+    pub fn new(values: impl IntoIterator<Item=impl Borrow<u64>>) -> Result<Self, Error> {
+        let values: Vec<u64> = values.into_iter().map(|v| *v.borrow()).collect();
+        let mut sorted = values.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let distinct = EliasFano::from_u64s(&sorted)?;
+
+        let rank_bits = (usize::BITS - (sorted.len() - 1).leading_zeros()) as usize;
+        let mut ranks = Bits::with_capacity(values.len() * rank_bits);
+        for value in &values {
+            let rank = sorted.binary_search(value).expect("every value was sorted in");
+            ranks.append_from(rank as u64, rank_bits);
+        }
+        Ok(Self { distinct, ranks, rank_bits, size: values.len() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Value at `index` in the original order.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.size {
+            return None;
+        }
+        let start = index * self.rank_bits;
+        let rank = self.ranks.slice(start, start + self.rank_bits)?;
+        self.distinct.get_u64(rank as usize)
+    }
+
+    /// The distinct values, sorted.
+    pub fn distinct(&self) -> &EliasFano<Vec<u64>> {
+        &self.distinct
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u64> + '_ {
+        (0..self.size).map_while(move |index| self.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_original_order() {
+        let values: Vec<u64> = (0..1000u64).map(|i| (i * 7919) % 613).collect();
+        let sequence = PermutedSequence::new(&values).expect("encoded");
+        assert_eq!(sequence.iter().collect::<Vec<_>>(), values);
+        assert_eq!(sequence.get(999), Some(values[999]));
+        assert_eq!(sequence.get(1000), None);
+        assert_eq!(sequence.distinct().len(), 613);
+
+        let single = PermutedSequence::new([5u64, 5, 5]).expect("encoded");
+        assert_eq!(single.iter().collect::<Vec<_>>(), vec![5, 5, 5]);
+        assert!(PermutedSequence::new(Vec::<u64>::new()).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::sequence::Strictness;
+use std::borrow::Borrow;
+
+/// Sorted values kept only to a multiple of a quantum, e.g. millisecond timestamps queried by the
+/// second with a quantum of 1000. Values are divided by the quantum before encoding, which
+/// shrinks the universe by that factor; the serialized form keeps the quantum in front of the
+/// Elias-Fano blob. Values sharing a quantum are all kept, so `len` and ranks still count the
+/// original values, and every value comes back rounded down to a multiple of the quantum.
+#[derive(Debug, Clone)]
+pub struct QuantizedEliasFano {
+    quantum: u64,
+    inner: EliasFano<Vec<u64>>,
+}
+
+impl QuantizedEliasFano {
+    /// Panics if `quantum` is 0.
+    pub fn new(values: impl IntoIterator<Item=impl Borrow<u64>>, quantum: u64) -> Result<Self, Error> {
+        assert!(quantum > 0, "quantum must be positive");
+        let values: Vec<u64> = values.into_iter().map(|v| *v.borrow() / quantum).collect();
+        let inner = EliasFano::from_u64s_with_strictness(values, Strictness::NonDecreasing)?;
+        Ok(Self { quantum, inner })
+    }
+
+    pub fn quantum(&self) -> u64 {
+        self.quantum
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<u64> {
+        self.inner.get_u64(index).map(|v| v * self.quantum)
+    }
+
+    /// The first rounded value `>= value`.
+    pub fn next_geq(&self, value: u64) -> Option<u64> {
+        self.inner.next_geq_u64(value.div_ceil(self.quantum)).map(|v| v * self.quantum)
+    }
+
+    /// Number of rounded values strictly less than `value`.
+    pub fn rank(&self, value: u64) -> usize {
+        self.inner.rank_u64(value.div_ceil(self.quantum))
+    }
+
+    /// Whether any value was in the same quantum as `value`.
+    pub fn contains(&self, value: u64) -> bool {
+        self.inner.contains_u64(value / self.quantum)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u64> + '_ {
+        self.inner.iter_u64().map(move |v| v * self.quantum)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = self.quantum.to_be_bytes().to_vec();
+        vec.append(&mut self.inner.as_bytes());
+        vec
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let quantum = be_u64_at(data, 0)?;
+        let inner = EliasFano::from_bytes_owned(rest_from(data, 8)?)?;
+        // Every stored value times the quantum has to fit, as it did when encoded
+        if quantum == 0 || inner.last_u64().is_some_and(|last| last.checked_mul(quantum).is_none()) {
+            return Err(Error::corrupt_header(0));
+        }
+        Ok(Self { quantum, inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_down_to_the_quantum() {
+        let millis: Vec<u64> = (0..5000).map(|i| 1_700_000_000_000 + i * 377).collect();
+        let seconds = QuantizedEliasFano::new(&millis, 1000).expect("elias fano encoding");
+        assert_eq!(seconds.len(), 5000);
+        assert_eq!(seconds.iter().collect::<Vec<_>>(), millis.iter().map(|v| v / 1000 * 1000).collect::<Vec<_>>());
+        assert_eq!(seconds.get(3), Some(1_700_000_001_000));
+        assert_eq!(seconds.next_geq(1_700_000_000_001), Some(1_700_000_001_000));
+        assert_eq!(seconds.rank(1_700_000_001_000), 3);
+        assert!(seconds.contains(1_700_000_001_999) && !seconds.contains(1_699_999_999_999));
+        assert!(seconds.as_bytes().len() < EliasFano::from_u64s(&millis).expect("elias fano encoding").as_bytes().len());
+
+        let loaded = QuantizedEliasFano::from_bytes(&seconds.as_bytes()).expect("loaded");
+        assert_eq!((loaded.quantum(), loaded.iter().collect::<Vec<_>>()), (1000, seconds.iter().collect()));
+        assert!(QuantizedEliasFano::new([5u64, 3], 2).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::cursor::Cursor;
+use crate::elias_fano::EliasFano;
+
+/// A boolean query over sequences. `evaluate` streams the matching values in order, moving every
+/// cursor forward with `advance_to` so no list is decoded beyond what the answer needs.
+#[derive(Debug, Clone)]
+pub enum Expr<'a, V: AsRef<[u64]>> {
+    Term(&'a EliasFano<V>),
+    /// Values in every operand. An empty `And` matches nothing.
+    And(Vec<Expr<'a, V>>),
+    Or(Vec<Expr<'a, V>>),
+    /// Values from 0 up to and including the universe that the operand doesn't match.
+    Not(Box<Expr<'a, V>>, u64),
+}
+
+impl<'a, V: AsRef<[u64]>> Expr<'a, V> {
+    pub fn not(expr: Expr<'a, V>, universe: u64) -> Self {
+        Expr::Not(Box::new(expr), universe)
+    }
+
+    pub fn evaluate(&self) -> Evaluation<'a, V> {
+        Evaluation { root: self.plan(), target: Some(0) }
+    }
+
+    /// Upper bound on the number of matches, for ordering `And` operands.
+    fn estimate(&self) -> u64 {
+        match self {
+            Expr::Term(ef) => ef.len() as u64,
+            Expr::And(operands) => operands.iter().map(Expr::estimate).min().unwrap_or(0),
+            Expr::Or(operands) => operands.iter().map(Expr::estimate).fold(0, u64::saturating_add),
+            Expr::Not(_, universe) => universe.saturating_add(1),
+        }
+    }
+
+    fn plan(&self) -> Node<'a, V> {
+        match self {
+            Expr::Term(ef) => Node::Cursor(ef.cursor()),
+            Expr::And(operands) => {
+                // The most selective operand proposes candidates, the rest only confirm them
+                let mut operands: Vec<_> = operands.iter().collect();
+                operands.sort_by_key(|expr| expr.estimate());
+                Node::And(operands.into_iter().map(Expr::plan).collect())
+            }
+            Expr::Or(operands) => Node::Or(operands.iter().map(Expr::plan).collect()),
+            Expr::Not(operand, universe) => Node::Not(Box::new(operand.plan()), *universe),
+        }
+    }
+}
+
+/// Matches of an `Expr`, in increasing order.
+#[derive(Debug, Clone)]
+pub struct Evaluation<'a, V: AsRef<[u64]>> {
+    root: Node<'a, V>,
+    target: Option<u64>,
+}
+
+impl<'a, V: AsRef<[u64]>> Iterator for Evaluation<'a, V> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let value = self.root.advance_to(self.target?)?;
+        self.target = value.checked_add(1);
+        Some(value as usize)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node<'a, V: AsRef<[u64]>> {
+    Cursor(Cursor<'a, V>),
+    And(Vec<Node<'a, V>>),
+    Or(Vec<Node<'a, V>>),
+    Not(Box<Node<'a, V>>, u64),
+}
+
+impl<'a, V: AsRef<[u64]>> Node<'a, V> {
+    /// Smallest match `>= value`. Like `Cursor::advance_to`, only ever moves forward.
+    fn advance_to(&mut self, value: u64) -> Option<u64> {
+        match self {
+            Node::Cursor(cursor) => cursor.advance_to_u64(value),
+            Node::And(operands) => {
+                let mut target = value;
+                'candidates: loop {
+                    for operand in operands.iter_mut() {
+                        let found = operand.advance_to(target)?;
+                        if found != target {
+                            target = found;
+                            continue 'candidates;
+                        }
+                    }
+                    return if operands.is_empty() { None } else { Some(target) };
+                }
+            }
+            Node::Or(operands) => operands.iter_mut().filter_map(|operand| operand.advance_to(value)).min(),
+            Node::Not(operand, universe) => {
+                let mut candidate = value;
+                while candidate <= *universe {
+                    if operand.advance_to(candidate) != Some(candidate) {
+                        return Some(candidate);
+                    }
+                    candidate = candidate.checked_add(1)?;
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(values: &[usize], keep: impl Fn(usize) -> bool) -> Vec<usize> {
+        values.iter().copied().filter(|v| keep(*v)).collect()
+    }
+
+    #[test]
+    fn and_or_not() {
+        let evens = EliasFano::new((0..500).map(|i| i * 2)).expect("elias fano encoding");
+        let threes = EliasFano::new((0..400).map(|i| i * 3)).expect("elias fano encoding");
+        let fives = EliasFano::new((0..200).map(|i| i * 5)).expect("elias fano encoding");
+        let all: Vec<usize> = (0..=1200).collect();
+        let even = |v: usize| v % 2 == 0 && v < 1000;
+        let three = |v: usize| v % 3 == 0 && v < 1200;
+        let five = |v: usize| v % 5 == 0 && v < 1000;
+
+        let and = Expr::And(vec![Expr::Term(&evens), Expr::Term(&threes)]);
+        assert_eq!(and.evaluate().collect::<Vec<_>>(), decoded(&all, |v| even(v) && three(v)));
+
+        let or = Expr::Or(vec![Expr::Term(&threes), Expr::Term(&fives)]);
+        assert_eq!(or.evaluate().collect::<Vec<_>>(), decoded(&all, |v| three(v) || five(v)));
+
+        let and_not = Expr::And(vec![Expr::Term(&evens), Expr::not(Expr::Term(&threes), 1200)]);
+        assert_eq!(and_not.evaluate().collect::<Vec<_>>(), decoded(&all, |v| even(v) && !three(v)));
+
+        let nested = Expr::And(vec![
+            Expr::Or(vec![Expr::Term(&evens), Expr::Term(&fives)]),
+            Expr::not(Expr::And(vec![Expr::Term(&threes), Expr::Term(&evens)]), 1200),
+        ]);
+        assert_eq!(nested.evaluate().collect::<Vec<_>>(), decoded(&all, |v| (even(v) || five(v)) && !(three(v) && even(v))));
+
+        let not = Expr::not(Expr::Term(&evens), 20);
+        assert_eq!(not.evaluate().collect::<Vec<_>>(), vec![1, 3, 5, 7, 9, 11, 13, 15, 17, 19]);
+    }
+
+    #[test]
+    fn empty_operands_match_nothing() {
+        assert_eq!(Expr::<Vec<u64>>::And(vec![]).evaluate().next(), None);
+        assert_eq!(Expr::<Vec<u64>>::Or(vec![]).evaluate().next(), None);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::cursor::Cursor;
+use crate::elias_fano::EliasFano;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::ParallelIterator;
+use rayon::slice::ParallelSlice;
+
+// Probes per batch job. Each job walks its own cursor through its probes
+const PROBE_CHUNK_LEN: usize = 1024;
+
+// Below this many values a range isn't split further; positioning each cursor costs a select
+const MIN_SPLIT_LEN: usize = 4096;
+
+/// Parallel iterator over the values of an `EliasFano`. It splits by rank and each piece decodes
+/// with its own cursor, so pieces share nothing but the read-only bits.
+#[derive(Debug, Clone)]
+pub struct ParIter<'a, V: AsRef<[u64]> + Sync> {
+    ef: &'a EliasFano<V>,
+    start: usize,
+    end: usize,
+}
+
+impl<V: AsRef<[u64]> + Sync> EliasFano<V> {
+    pub fn par_iter(&self) -> ParIter<'_, V> {
+        ParIter { ef: self, start: 0, end: self.len() }
+    }
+}
+
+/// Batch queries on the global rayon pool, answered in input order. Probes are split into chunks
+/// and each chunk is walked by one cursor, so sorted probes cost one pass per chunk.
+impl<V: AsRef<[u64]> + Sync> EliasFano<V> {
+    pub fn par_next_geq_many(&self, probes: &[usize]) -> Vec<Option<usize>> {
+        self.par_probe_many(probes, |cursor, probe| cursor.advance_to(probe))
+    }
+
+    pub fn par_rank_many(&self, probes: &[usize]) -> Vec<usize> {
+        self.par_probe_many(probes, |cursor, probe| {
+            cursor.advance_to(probe);
+            Cursor::position(cursor)
+        })
+    }
+
+    pub fn par_contains_many(&self, probes: &[usize]) -> Vec<bool> {
+        self.par_probe_many(probes, |cursor, probe| cursor.advance_to(probe) == Some(probe))
+    }
+
+    fn par_probe_many<T: Send>(&self, probes: &[usize], answer: impl Fn(&mut Cursor<'_, V>, usize) -> T + Sync) -> Vec<T> {
+        probes.par_chunks(PROBE_CHUNK_LEN)
+            .flat_map_iter(|chunk| self.probe_many(chunk, &answer))
+            .collect()
+    }
+}
+
+impl<'a, V: AsRef<[u64]> + Sync> ParallelIterator for ParIter<'a, V> {
+    type Item = usize;
+
+    fn drive_unindexed<C: UnindexedConsumer<usize>>(self, consumer: C) -> C::Result {
+        bridge_unindexed(self, consumer)
+    }
+}
+
+impl<'a, V: AsRef<[u64]> + Sync> UnindexedProducer for ParIter<'a, V> {
+    type Item = usize;
+
+    fn split(self) -> (Self, Option<Self>) {
+        let len = self.end - self.start;
+        if len < 2 * MIN_SPLIT_LEN {
+            return (self, None);
+        }
+        let middle = self.start + len / 2;
+        (ParIter { end: middle, ..self }, Some(ParIter { start: middle, ..self }))
+    }
+
+    fn fold_with<F: Folder<usize>>(self, folder: F) -> F {
+        folder.consume_iter(self.ef.cursor_at(self.start).take(self.end - self.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn par_iter_matches_iter() {
+        let data: Vec<usize> = (0..50_000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        assert_eq!(ef.par_iter().collect::<Vec<_>>(), data);
+        assert_eq!(ef.par_iter().map(|v| v as u64).sum::<u64>(), data.iter().map(|v| *v as u64).sum::<u64>());
+        assert_eq!(EliasFano::empty().par_iter().count(), 0);
+    }
+
+    #[test]
+    fn batch_queries_keep_input_order() {
+        let data: Vec<usize> = (0..10_000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data).expect("elias fano encoding");
+        let probes: Vec<usize> = (0..5000).map(|i| (i * 7919) % 71_000).collect();
+        assert_eq!(ef.par_next_geq_many(&probes), probes.iter().map(|p| ef.next_geq(*p)).collect::<Vec<_>>());
+        assert_eq!(ef.par_rank_many(&probes), probes.iter().map(|p| ef.rank(*p)).collect::<Vec<_>>());
+        assert_eq!(ef.par_contains_many(&probes), probes.iter().map(|p| ef.contains(*p)).collect::<Vec<_>>());
+    }
+}
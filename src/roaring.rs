@@ -0,0 +1,97 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use roaring::RoaringBitmap;
+use std::convert::TryFrom;
+
+impl From<&RoaringBitmap> for EliasFano<Vec<u64>> {
+    fn from(bitmap: &RoaringBitmap) -> Self {
+        if bitmap.is_empty() {
+            return Self::empty();
+        }
+        Self::new(bitmap.iter().map(|v| v as usize)).expect("bitmaps iterate in increasing order")
+    }
+}
+
+impl<V: AsRef<[u64]>> TryFrom<&EliasFano<V>> for RoaringBitmap {
+    type Error = Error;
+
+    /// Fails if a value doesn't fit in the bitmap's `u32`.
+    fn try_from(ef: &EliasFano<V>) -> Result<Self, Error> {
+        let values = ef.iter()
+            .map(|v| u32::try_from(v).map_err(|_| Error::universe_too_large(v, u32::MAX as usize)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RoaringBitmap::from_sorted_iter(values).expect("values are strictly increasing"))
+    }
+}
+
+/// Serialized size of the same set as Elias-Fano (`as_bytes`) and as a Roaring bitmap
+/// (`RoaringBitmap::serialize_into`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeComparison {
+    pub elias_fano: usize,
+    pub roaring: usize,
+}
+
+impl SizeComparison {
+    pub fn elias_fano_is_smaller(&self) -> bool {
+        self.elias_fano < self.roaring
+    }
+}
+
+/// Sizes `bitmap` both ways without building the Elias-Fano encoding.
+pub fn compare_sizes(bitmap: &RoaringBitmap) -> SizeComparison {
+    let elias_fano = match bitmap.max() {
+        Some(max) => EliasFano::estimate_size(bitmap.len() as usize, max as usize),
+        None => EliasFano::empty().serialized_len(),
+    };
+    SizeComparison { elias_fano, roaring: bitmap.serialized_size() }
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Like `compare_sizes`, starting from this side. Fails if a value doesn't fit in a `u32`.
+    pub fn compare_with_roaring(&self) -> Result<SizeComparison, Error> {
+        let bitmap = RoaringBitmap::try_from(self)?;
+        Ok(SizeComparison { elias_fano: self.serialized_len(), roaring: bitmap.serialized_size() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bitmap: RoaringBitmap = (0..100_000).step_by(3).collect();
+        let ef = EliasFano::from(&bitmap);
+        assert_eq!(ef.len(), bitmap.len() as usize);
+        assert_eq!(ef.next_geq(10), Some(12));
+        assert_eq!(RoaringBitmap::try_from(&ef).expect("bitmap"), bitmap);
+    }
+
+    #[test]
+    fn empty_bitmap() {
+        let ef = EliasFano::from(&RoaringBitmap::new());
+        assert!(ef.is_empty());
+        assert_eq!(ef.iter().next(), None);
+        assert!(RoaringBitmap::try_from(&ef).expect("bitmap").is_empty());
+    }
+
+    #[test]
+    fn values_above_u32_fail() {
+        let ef = EliasFano::new(vec![1, 1 << 33]).expect("elias fano encoding");
+        assert!(RoaringBitmap::try_from(&ef).is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        // Sparse sets favour Elias-Fano, dense ones favour Roaring's bitmap containers
+        let sparse: RoaringBitmap = (0..1000).map(|i| i * 4001).collect();
+        let comparison = compare_sizes(&sparse);
+        assert!(comparison.elias_fano_is_smaller());
+        assert_eq!(comparison, EliasFano::from(&sparse).compare_with_roaring().expect("sizes"));
+        assert_eq!(comparison.elias_fano, EliasFano::from(&sparse).as_bytes().len());
+
+        let dense: RoaringBitmap = (0..1_000_000).collect();
+        assert!(!compare_sizes(&dense).elias_fano_is_smaller());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::elias_fano::EliasFano;
+use rand::seq::index;
+use rand::Rng;
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// A uniformly random stored value, found by select rather than by decoding up to it.
+    pub fn choose(&self, rng: &mut impl Rng) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        self.get(rng.gen_range(0..self.len()))
+    }
+
+    /// `k` distinct stored values picked uniformly at random, in increasing order. All of them if
+    /// there are no more than `k`.
+    pub fn sample(&self, rng: &mut impl Rng, k: usize) -> Vec<usize> {
+        let mut ranks = index::sample(rng, self.len(), k.min(self.len())).into_vec();
+        ranks.sort_unstable();
+        ranks.into_iter().filter_map(|rank| self.get(rank)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn samples_are_distinct_stored_values() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let sample = ef.sample(&mut rng, 50);
+        assert_eq!(sample.len(), 50);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(sample.iter().all(|v| data.binary_search(v).is_ok()));
+        assert_eq!(ef.sample(&mut rng, 5000), data);
+
+        let chosen = ef.choose(&mut rng).expect("not empty");
+        assert!(data.binary_search(&chosen).is_ok());
+        assert_eq!(EliasFano::empty().choose(&mut rng), None);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use std::borrow::Borrow;
+
+/// A sparse bit vector in the style of sdsl's `sd_vector`: `len` bits of which only the positions
+/// of the ones are stored, Elias-Fano encoded. Queries use bit vector terms, `rank_1(pos)` is the
+/// number of ones before `pos` and `select_1(k)` the position of the `k`th one, both from 0.
+#[derive(Debug, Clone)]
+pub struct SdVector {
+    len: usize,
+    ones: EliasFano<Vec<u64>>,
+}
+
+impl SdVector {
+    /// `positions` are the set bits, strictly increasing and below `len`.
+    pub fn new(len: usize, positions: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let positions: Vec<usize> = positions.into_iter().map(|p| *p.borrow()).collect();
+        if let Some(last) = positions.last().filter(|last| **last >= len) {
+            return Err(Error::value_above_universe(*last, len));
+        }
+        let ones = if positions.is_empty() { EliasFano::empty() } else { EliasFano::from_sorted_slice(&positions)? };
+        Ok(Self { len, ones })
+    }
+
+    /// Number of bits, set or not.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.ones.len()
+    }
+
+    pub fn get(&self, position: usize) -> bool {
+        self.ones.contains(position)
+    }
+
+    pub fn rank_1(&self, position: usize) -> usize {
+        self.ones.rank(position.min(self.len))
+    }
+
+    pub fn rank_0(&self, position: usize) -> usize {
+        position.min(self.len) - self.rank_1(position)
+    }
+
+    pub fn select_1(&self, k: usize) -> Option<usize> {
+        self.ones.get(k)
+    }
+
+    pub fn select_0(&self, k: usize) -> Option<usize> {
+        // The ith one has `ones[i] - i` zeros before it, which never decreases, so binary search
+        // for the number of ones before the kth zero
+        let (mut low, mut high) = (0, self.ones.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let zeros_before = self.ones.get(mid).map_or(usize::MAX, |one| one - mid);
+            if zeros_before <= k {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Some(k + low).filter(|position| *position < self.len)
+    }
+
+    /// The positions of the ones, in order.
+    pub fn ones(&self) -> &EliasFano<Vec<u64>> {
+        &self.ones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_vector_queries() {
+        let positions: Vec<usize> = (0..300).map(|i| i * 7 + i % 3).collect();
+        let bits = SdVector::new(2200, &positions).expect("encoded");
+        let dense: Vec<bool> = (0..2200).map(|p| positions.contains(&p)).collect();
+        for position in 0..=2200 {
+            assert_eq!(bits.rank_1(position), dense[..position].iter().filter(|b| **b).count());
+            assert_eq!(bits.rank_0(position), dense[..position].iter().filter(|b| !**b).count());
+        }
+        let zeros: Vec<usize> = (0..2200).filter(|p| !dense[*p]).collect();
+        for (k, zero) in zeros.iter().enumerate() {
+            assert_eq!(bits.select_0(k), Some(*zero));
+        }
+        assert_eq!(bits.select_0(zeros.len()), None);
+        assert_eq!(bits.select_1(299), Some(positions[299]));
+        assert!(bits.get(8) && !bits.get(7));
+
+        assert!(SdVector::new(10, [3, 10]).is_err());
+        let empty = SdVector::new(5, Vec::<usize>::new()).expect("encoded");
+        assert_eq!((empty.count_ones(), empty.select_0(4), empty.select_0(5)), (0, Some(4), None));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use std::convert::TryInto;
+
+/*
+sdsl-lite's `sd_vector<>` as written by `serialize` / `store_to_file`, in the byte order of the
+machine that wrote it, which is little-endian everywhere sdsl is used in practice:
+
+ u64            length of the bit vector
+ u8             wl, number of low bits per value
+ int_vector<>   low: u64 length in bits, u8 width, then ceil(bits / 64) words
+ bit_vector     high: u64 length in bits, then ceil(bits / 64) words. Bit (value >> wl) + i is set
+                for the i-th value
+ select_support_mcl<1> and select_support_mcl<0> over high
+
+A select support is its u64 argument count and, if that's non zero, an int_vector<> with the first
+argument position of each superblock of 4096 arguments, a bit_vector marking which superblocks are
+stored long, then one int_vector<> per superblock. We always write an empty marker, which sdsl
+reads as every superblock being a miniblock: 64 offsets from the superblock start, one for every
+64th argument.
+ */
+const SUPERBLOCK_LEN: usize = 4096;
+const MINIBLOCK_STRIDE: usize = 64;
+
+impl EliasFano<Vec<u64>> {
+    /// Reads an sdsl `sd_vector<>`, keeping the positions of its set bits. The select supports
+    /// stored after the bit arrays are only checked for being well formed, this crate rebuilds what
+    /// it needs, and anything after them is ignored.
+    pub fn from_sd_vector(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { data, offset: 0 };
+        let len = reader.u64()? as usize;
+        let wl = reader.u8()? as usize;
+        let (low, low_bits, low_width) = reader.int_vector()?;
+        let (high, high_len) = reader.bit_vector()?;
+        for _ in 0..2 {
+            reader.select_support()?;
+        }
+        // A shift by wl has to stay defined
+        if wl >= 64 || (wl > 0 && low_width != wl) {
+            return Err(Error::invalid_bits_data(data.len()));
+        }
+
+        let count = low_bits.checked_div(wl).unwrap_or(usize::MAX);
+        let mut values = Vec::new();
+        for position in (0..high_len).filter(|p| high[p / 64] >> (p % 64) & 1 == 1) {
+            let i = values.len();
+            if i >= count {
+                return Err(Error::invalid_bits_data(data.len()));
+            }
+            let value = ((position - i) << wl) | read_int(&low, i, wl) as usize;
+            if value >= len {
+                return Err(Error::invalid_bits_data(data.len()));
+            }
+            values.push(value);
+        }
+        EliasFano::new(values)
+    }
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Writes the values as the set bits of an sdsl `sd_vector<>` one bit longer than the largest
+    /// value, with sdsl's own choice of `wl` so the result matches what sdsl would build itself.
+    pub fn to_sd_vector(&self) -> Vec<u8> {
+        let m = self.size;
+        let len = self.get(m - 1).expect("size is at least 1") + 1;
+        let mut logm = bit_len(m);
+        let logn = bit_len(len);
+        if logm == logn {
+            logm -= 1;
+        }
+        let wl = logn - logm;
+
+        let mut low = vec![0; (m * wl).div_ceil(64)];
+        let high_len = m + (1 << logm);
+        let mut high = vec![0u64; high_len.div_ceil(64)];
+        for (i, value) in self.iter().enumerate() {
+            write_int(&mut low, i, wl, value as u64);
+            let position = (value >> wl) + i;
+            high[position / 64] |= 1 << (position % 64);
+        }
+
+        let mut out = vec![];
+        out.extend_from_slice(&(len as u64).to_le_bytes());
+        out.push(wl as u8);
+        write_int_vector(&mut out, &low, m * wl, Some(wl));
+        write_int_vector(&mut out, &high, high_len, None);
+        for arg in [true, false] {
+            let positions: Vec<usize> = (0..high_len).filter(|p| (high[p / 64] >> (p % 64) & 1 == 1) == arg).collect();
+            write_select_support(&mut out, &positions, high_len);
+        }
+        out
+    }
+}
+
+fn write_select_support(out: &mut Vec<u8>, positions: &[usize], len: usize) {
+    out.extend_from_slice(&(positions.len() as u64).to_le_bytes());
+    if positions.is_empty() {
+        return;
+    }
+    let superblocks: Vec<&[usize]> = positions.chunks(SUPERBLOCK_LEN).collect();
+    let width = bit_len(len).max(1);
+    let mut starts = vec![0; (superblocks.len() * width).div_ceil(64)];
+    for (i, block) in superblocks.iter().enumerate() {
+        write_int(&mut starts, i, width, block[0] as u64);
+    }
+    write_int_vector(out, &starts, superblocks.len() * width, Some(width));
+    write_int_vector(out, &[], 0, None);
+    for block in superblocks {
+        let width = bit_len(block[block.len() - 1] - block[0]).max(1);
+        let entries = SUPERBLOCK_LEN / MINIBLOCK_STRIDE;
+        let mut mini = vec![0; (entries * width).div_ceil(64)];
+        for (k, position) in block.iter().step_by(MINIBLOCK_STRIDE).enumerate() {
+            write_int(&mut mini, k, width, (position - block[0]) as u64);
+        }
+        write_int_vector(out, &mini, entries * width, Some(width));
+    }
+}
+
+/// `width` is `None` for a bit_vector, whose width isn't stored.
+fn write_int_vector(out: &mut Vec<u8>, words: &[u64], bits: usize, width: Option<usize>) {
+    out.extend_from_slice(&(bits as u64).to_le_bytes());
+    if let Some(width) = width {
+        out.push(width as u8);
+    }
+    out.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+}
+
+/// Number of bits needed for `value`, 0 for 0. sdsl writes this as `bits::hi(value) + 1`.
+fn bit_len(value: usize) -> usize {
+    (usize::BITS - value.leading_zeros()) as usize
+}
+
+fn read_int(words: &[u64], index: usize, width: usize) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+    let start = index * width;
+    let offset = start % 64;
+    let mut raw = words[start / 64] >> offset;
+    if offset + width > 64 {
+        raw |= words[start / 64 + 1] << (64 - offset);
+    }
+    raw & (u64::MAX >> (64 - width))
+}
+
+fn write_int(words: &mut [u64], index: usize, width: usize, value: u64) {
+    if width == 0 {
+        return;
+    }
+    let value = value & (u64::MAX >> (64 - width));
+    let start = index * width;
+    let offset = start % 64;
+    words[start / 64] |= value << offset;
+    if offset + width > 64 {
+        words[start / 64 + 1] |= value >> (64 - offset);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], Error> {
+        let bytes = self.offset.checked_add(len)
+            .and_then(|end| self.data.get(self.offset..end))
+            .ok_or(Error::invalid_bits_data(self.data.len()))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    fn words(&mut self, bits: usize) -> Result<Vec<u64>, Error> {
+        let bytes = self.take(bits.div_ceil(64).checked_mul(8).ok_or(Error::invalid_bits_data(self.data.len()))?)?;
+        Ok(bytes.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("chunks of 8"))).collect())
+    }
+
+    /// Returns the words, the length in bits and the width of each element.
+    fn int_vector(&mut self) -> Result<(Vec<u64>, usize, usize), Error> {
+        let bits = self.u64()? as usize;
+        let width = self.u8()? as usize;
+        Ok((self.words(bits)?, bits, width))
+    }
+
+    /// Returns the words and the length in bits.
+    fn bit_vector(&mut self) -> Result<(Vec<u64>, usize), Error> {
+        let bits = self.u64()? as usize;
+        Ok((self.words(bits)?, bits))
+    }
+
+    fn select_support(&mut self) -> Result<(), Error> {
+        let count = self.u64()? as usize;
+        if count == 0 {
+            return Ok(());
+        }
+        self.int_vector()?;
+        self.bit_vector()?;
+        for _ in 0..count.div_ceil(SUPERBLOCK_LEN) {
+            self.int_vector()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sd_vector_layout() {
+        // len 22, m 3: logn 5, logm 2, so wl 3 and high has 3 + 4 bits
+        let ef = EliasFano::new(vec![5, 14, 21]).expect("elias fano encoding");
+        let data = ef.to_sd_vector();
+        assert_eq!(u64::from_le_bytes(data[0..8].try_into().unwrap()), 22);
+        assert_eq!(data[8], 3);
+        // low: 9 bits of width 3, then one word holding 5, 6, 5
+        assert_eq!(u64::from_le_bytes(data[9..17].try_into().unwrap()), 9);
+        assert_eq!(data[17], 3);
+        assert_eq!(u64::from_le_bytes(data[18..26].try_into().unwrap()), 5 | 6 << 3 | 5 << 6);
+        // high: 7 bits with ones at 0 + 0, 1 + 1 and 2 + 2
+        assert_eq!(u64::from_le_bytes(data[26..34].try_into().unwrap()), 7);
+        assert_eq!(u64::from_le_bytes(data[34..42].try_into().unwrap()), 0b10101);
+        // select_1 counts three ones
+        assert_eq!(u64::from_le_bytes(data[42..50].try_into().unwrap()), 3);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<usize> = (0..10_000).map(|i| i * 5 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let sd = ef.to_sd_vector();
+        let read = EliasFano::from_sd_vector(&sd).expect("sd_vector");
+        assert_eq!(read.iter().collect::<Vec<_>>(), data);
+        // Trailing data after the structure is left alone
+        let mut padded = sd.clone();
+        padded.extend_from_slice(&[1, 2, 3]);
+        assert!(EliasFano::from_sd_vector(&padded).is_ok());
+    }
+
+    #[test]
+    fn rejects_full_width_low_bits() {
+        // One value at position 0 with wl 64, which would shift the high part out of a u64
+        let mut sd = vec![];
+        sd.extend_from_slice(&1u64.to_le_bytes());
+        sd.push(64);
+        write_int_vector(&mut sd, &[0], 64, Some(64));
+        write_int_vector(&mut sd, &[1], 1, None);
+        sd.extend_from_slice(&[0; 16]);
+        assert!(EliasFano::from_sd_vector(&sd).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let ef = EliasFano::new(vec![1, 2, 3, 1000]).expect("elias fano encoding");
+        let sd = ef.to_sd_vector();
+        for len in [0, 8, 20, sd.len() - 1] {
+            assert!(EliasFano::from_sd_vector(&sd[..len]).is_err());
+        }
+    }
+}
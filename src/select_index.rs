@@ -0,0 +1,349 @@
+use crate::bits::nth_set_bit;
+use crate::checksum::crc32;
+use crate::errors::Error;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Debug, Formatter};
+use std::mem::size_of;
+use std::sync::OnceLock;
+
+/// Words of bits per block, one 64-byte cache line of the upper bits.
+const WORDS_PER_BLOCK: usize = 8;
+
+const BITS_PER_BLOCK: usize = 64 * WORDS_PER_BLOCK;
+
+/// The block holding every this many'th one, and zero, is sampled, unless a sequence was built
+/// with another rate. Rates are powers of two from `MIN_SAMPLE_RATE` to `MAX_SAMPLE_RATE`.
+pub(crate) const DEFAULT_SAMPLE_RATE: usize = 512;
+pub(crate) const MIN_SAMPLE_RATE: usize = 8;
+pub(crate) const MAX_SAMPLE_RATE: usize = 1 << 16;
+
+pub(crate) const INDEX_MAGIC: [u8; 4] = *b"PEFI";
+
+pub(crate) const INDEX_VERSION: u8 = 2;
+
+/*
+Serialized index (version 2), every integer little-endian:
+
+ 0..4   magic "PEFI"
+ 4      version
+ 5      log2 of the sample rate
+ 6..8   reserved, zero
+ 8..12  CRC-32 of the upper bits the index was built over, as serialized
+12..16  reserved, zero
+16..24  number of blocks
+24..32  number of one samples
+32..40  number of zero samples
+40..    ones before each block, then the one samples, then the zero samples, as u64s
+last 4  CRC-32 of everything before it
+
+Version 1 had blocks of 7 words, so its block counts don't fit version 2's.
+ */
+const INDEX_HEADER_LEN: usize = 40;
+
+/// Select over the upper bits without scanning them from the start. The bits are split into blocks
+/// of a cache line each, and the index keeps the number of ones before every block plus, every
+/// `sample_rate` ones and zeros, the block that one or zero is in. A select reads one sample, walks
+/// the counts to the right block and scans just that block. The words stay the sequence's own and
+/// are passed in with `over`; the counts add one u64 per block, an eighth of the upper bits.
+///
+/// The blocks are cache-line aligned but the counts and samples are not interleaved with the
+/// words: the words may be borrowed or memory-mapped as serialized, so they can't be rearranged,
+/// and a select reads the count's cache line as well as the block's.
+#[derive(Clone)]
+pub(crate) struct SelectIndex {
+    /// Ones before each block.
+    blocks: Vec<u64>,
+    one_samples: Vec<usize>,
+    zero_samples: Vec<usize>,
+    len: usize,
+    sample_rate: usize,
+}
+
+impl Debug for SelectIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SelectIndex").field("blocks", &self.blocks.len()).finish_non_exhaustive()
+    }
+}
+
+/// A `SelectIndex` together with the words it was built over.
+#[derive(Clone, Copy)]
+pub(crate) struct Select<'a> {
+    index: &'a SelectIndex,
+    words: &'a [u64],
+}
+
+impl SelectIndex {
+    pub(crate) fn build(words: &[u64]) -> Self {
+        Self::build_sampled(words, DEFAULT_SAMPLE_RATE)
+    }
+
+    /// Builds with one sample every `sample_rate` ones and zeros: lower rates make selects faster
+    /// and the samples bigger. The rate must be one `normalize_sample_rate` returns.
+    pub(crate) fn build_sampled(words: &[u64], sample_rate: usize) -> Self {
+        debug_assert_eq!(sample_rate, normalize_sample_rate(sample_rate));
+        let mut blocks = Vec::with_capacity(words.len().div_ceil(WORDS_PER_BLOCK));
+        let mut one_samples = Vec::new();
+        let mut zero_samples = Vec::new();
+        let mut ones = 0;
+        for (block_index, chunk) in words.chunks(WORDS_PER_BLOCK).enumerate() {
+            blocks.push(ones as u64);
+
+            let zeros = block_index * BITS_PER_BLOCK - ones;
+            let block_ones: usize = chunk.iter().map(|w| w.count_ones() as usize).sum();
+            // The last block may be short
+            let block_zeros = chunk.len() * 64 - block_ones;
+            while one_samples.len() * sample_rate < ones + block_ones {
+                one_samples.push(block_index);
+            }
+            while zero_samples.len() * sample_rate < zeros + block_zeros {
+                zero_samples.push(block_index);
+            }
+            ones += block_ones;
+        }
+        Self { blocks, one_samples, zero_samples, len: words.len() * 64, sample_rate }
+    }
+
+    /// Bytes allocated for the blocks and samples.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.blocks.capacity() * size_of::<u64>() + (self.one_samples.capacity() + self.zero_samples.capacity()) * size_of::<usize>()
+    }
+
+    /// Serializes everything but the words, tagged with `words_crc`, the CRC-32 of the words.
+    pub(crate) fn to_bytes(&self, words_crc: u32) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(INDEX_HEADER_LEN + 8 * (self.blocks.len() + self.one_samples.len() + self.zero_samples.len()) + 4);
+        vec.extend_from_slice(&INDEX_MAGIC);
+        vec.extend_from_slice(&[INDEX_VERSION, self.sample_rate.trailing_zeros() as u8, 0, 0]);
+        vec.extend_from_slice(&words_crc.to_le_bytes());
+        vec.extend_from_slice(&[0; 4]);
+        for count in [self.blocks.len(), self.one_samples.len(), self.zero_samples.len()] {
+            vec.extend_from_slice(&(count as u64).to_le_bytes());
+        }
+        let samples = self.one_samples.iter().chain(&self.zero_samples).map(|sample| *sample as u64);
+        for value in self.blocks.iter().copied().chain(samples) {
+            vec.extend_from_slice(&value.to_le_bytes());
+        }
+        vec.extend_from_slice(&crc32(&vec).to_le_bytes());
+        vec
+    }
+
+    /// Reads what `to_bytes` wrote back over `words`, whose CRC-32 must be `words_crc`. Beyond the
+    /// checksums, the counts are checked to be consistent enough that no select can index out of
+    /// bounds or underflow; `EliasFano::load_select_index` documents the rest.
+    pub(crate) fn from_bytes(words: &[u64], words_crc: u32, data: &[u8]) -> Result<Self, Error> {
+        if data.len() < INDEX_HEADER_LEN + 4 {
+            return Err(Error::truncated_data(data.len(), INDEX_HEADER_LEN + 4));
+        }
+        if !data.starts_with(&INDEX_MAGIC) {
+            return Err(Error::invalid_bits_data(data.len()));
+        }
+        if data[4] != INDEX_VERSION {
+            return Err(Error::unsupported_version(data[4]));
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().expect("8 bytes"));
+        let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().expect("4 bytes"));
+        let counts = [16, 24, 32].map(|offset| usize::try_from(u64_at(offset)).ok());
+        let [Some(num_blocks), Some(num_ones), Some(num_zeros)] = counts else {
+            return Err(Error::corrupt_header(16));
+        };
+        let len = num_blocks.checked_add(num_ones).and_then(|n| n.checked_add(num_zeros))
+            .and_then(|n| n.checked_mul(8))
+            .and_then(|n| n.checked_add(INDEX_HEADER_LEN + 4))
+            .ok_or(Error::corrupt_header(16))?;
+        if data.len() < len {
+            return Err(Error::truncated_data(data.len(), len));
+        }
+        let (expected, actual) = (u32_at(len - 4), crc32(&data[..len - 4]));
+        if expected != actual {
+            return Err(Error::checksum_mismatch(expected, actual));
+        }
+        if u32_at(8) != words_crc {
+            return Err(Error::checksum_mismatch(u32_at(8), words_crc));
+        }
+        let sample_rate = 1usize.checked_shl(data[5] as u32).filter(|rate| normalize_sample_rate(*rate) == *rate).ok_or(Error::corrupt_header(5))?;
+        if num_blocks != words.len().div_ceil(WORDS_PER_BLOCK) {
+            return Err(Error::corrupt_header(16));
+        }
+
+        let values = |start: usize, count: usize| (0..count).map(move |i| u64_at(INDEX_HEADER_LEN + 8 * (start + i)));
+        let blocks: Vec<u64> = values(0, num_blocks).collect();
+        let one_samples: Vec<usize> = values(num_blocks, num_ones).map(|sample| sample as usize).collect();
+        let zero_samples: Vec<usize> = values(num_blocks + num_ones, num_zeros).map(|sample| sample as usize).collect();
+
+        // Each block has at most a block's bits of ones, and each sample's block starts at or
+        // before the sampled one or zero, so selects neither go out of bounds nor underflow
+        let ones_before = |b: usize| blocks[b] as usize;
+        let valid = blocks.first().map_or(true, |ones| *ones == 0)
+            && (1..blocks.len()).all(|b| ones_before(b).checked_sub(ones_before(b - 1)).is_some_and(|ones| ones <= BITS_PER_BLOCK))
+            && one_samples.iter().enumerate().all(|(k, b)| *b < blocks.len() && ones_before(*b) <= k * sample_rate)
+            && zero_samples.iter().enumerate().all(|(k, b)| *b < blocks.len() && b * BITS_PER_BLOCK - ones_before(*b) <= k * sample_rate);
+        if !valid {
+            return Err(Error::corrupt_header(INDEX_HEADER_LEN));
+        }
+        Ok(Self { blocks, one_samples, zero_samples, len: words.len() * 64, sample_rate })
+    }
+
+    /// The index with the words it was built over, to select on.
+    pub(crate) fn over<'a>(&'a self, words: &'a [u64]) -> Select<'a> {
+        debug_assert_eq!(words.len() * 64, self.len);
+        Select { index: self, words }
+    }
+}
+
+impl Select<'_> {
+    /// Same answer as `Bits::select_1` on the indexed words.
+    pub(crate) fn select_1(&self, index: usize) -> Option<usize> {
+        let blocks = &self.index.blocks;
+        let mut block_index = *self.index.one_samples.get(index / self.index.sample_rate)?;
+        while blocks.get(block_index + 1).is_some_and(|next| *next as usize <= index) {
+            block_index += 1;
+        }
+        let remaining = index - blocks[block_index] as usize;
+        select_in(block_index, self.block(block_index).iter().copied(), remaining)
+    }
+
+    /// Same answer as `Bits::select_0` on the indexed words.
+    pub(crate) fn select_0(&self, index: usize) -> Option<usize> {
+        let mut block_index = *self.index.zero_samples.get(index / self.index.sample_rate)?;
+        while block_index + 1 < self.index.blocks.len() && self.zeros_before(block_index + 1) <= index {
+            block_index += 1;
+        }
+        self.select_0_in(block_index, index)
+    }
+
+    /// Same answer as `select_0`, `index` counting zeros from the start, given that the zero is at
+    /// or after bit `from`, or `None` if it is before. It gallops forward over the blocks from
+    /// `from`'s, or from the zero's sample if that is further on, so a zero a few blocks ahead
+    /// costs a few block reads however far apart the samples are. Unlike `Bits::select_0_from`,
+    /// `index` isn't counted from `from`.
+    pub(crate) fn select_0_at_or_after(&self, from: usize, index: usize) -> Option<usize> {
+        let num_blocks = self.index.blocks.len();
+        let sampled = *self.index.zero_samples.get(index / self.index.sample_rate)?;
+        let mut block_index = sampled.max(from / BITS_PER_BLOCK);
+        if block_index >= num_blocks {
+            return None;
+        }
+        // Double the step until it overshoots, then halve it back down onto the zero's block
+        let within = |block_index: usize| block_index < num_blocks && self.zeros_before(block_index) <= index;
+        let mut step = 1;
+        while within(block_index + step) {
+            block_index += step;
+            step *= 2;
+        }
+        while step > 1 {
+            step /= 2;
+            if within(block_index + step) {
+                block_index += step;
+            }
+        }
+        self.select_0_in(block_index, index)
+    }
+
+    fn block(&self, block_index: usize) -> &[u64] {
+        let start = block_index * WORDS_PER_BLOCK;
+        &self.words[start..(start + WORDS_PER_BLOCK).min(self.words.len())]
+    }
+
+    fn zeros_before(&self, block_index: usize) -> usize {
+        block_index * BITS_PER_BLOCK - self.index.blocks[block_index] as usize
+    }
+
+    /// The `index`th zero, which is in block `block_index`, or `None` if it is before the block.
+    fn select_0_in(&self, block_index: usize, index: usize) -> Option<usize> {
+        let remaining = index.checked_sub(self.zeros_before(block_index))?;
+        select_in(block_index, self.block(block_index).iter().map(|w| !w), remaining)
+    }
+}
+
+fn select_in(block_index: usize, words: impl Iterator<Item=u64>, mut remaining: usize) -> Option<usize> {
+    for (word_index, word) in words.enumerate() {
+        let c = word.count_ones() as usize;
+        if remaining < c {
+            return Some(block_index * BITS_PER_BLOCK + word_index * 64 + nth_set_bit(word, remaining));
+        }
+        remaining -= c;
+    }
+    None
+}
+
+/// A `SelectIndex` built on first use, so sequences that are only loaded and written out again,
+/// e.g. by `migrate` or a bulk copy, never build one. Whoever holds it passes the words each time,
+/// and must pass the same ones.
+#[derive(Debug)]
+pub(crate) struct LazySelectIndex {
+    index: OnceLock<SelectIndex>,
+    sample_rate: usize,
+}
+
+impl LazySelectIndex {
+    pub(crate) fn new(sample_rate: usize) -> Self {
+        Self { index: OnceLock::new(), sample_rate: normalize_sample_rate(sample_rate) }
+    }
+
+    pub(crate) fn built_from(index: SelectIndex) -> Self {
+        let sample_rate = index.sample_rate;
+        Self { index: OnceLock::from(index), sample_rate }
+    }
+
+    pub(crate) fn get_or_build(&self, words: &[u64]) -> &SelectIndex {
+        self.index.get_or_init(|| SelectIndex::build_sampled(words, self.sample_rate))
+    }
+
+    /// The index if something has needed it yet.
+    pub(crate) fn built(&self) -> Option<&SelectIndex> {
+        self.index.get()
+    }
+
+    pub(crate) fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+}
+
+/// `rate` rounded up to a power of two and clamped to the supported range.
+pub(crate) fn normalize_sample_rate(rate: usize) -> usize {
+    rate.clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE).next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bits::Bits;
+
+    #[test]
+    fn matches_scanning_select() {
+        let mut bits = Bits::new();
+        for i in 0..3000usize {
+            bits.append_ones(i % 5).append_zeros(1 + i % 3);
+        }
+        let ones: usize = bits.bits.iter().map(|w| w.count_ones() as usize).sum();
+        let zeros = bits.byte_len() * 8 - ones;
+        for sample_rate in [MIN_SAMPLE_RATE, DEFAULT_SAMPLE_RATE, MAX_SAMPLE_RATE] {
+            let built = SelectIndex::build_sampled(bits.bits.as_ref(), sample_rate);
+            let index = built.over(bits.bits.as_ref());
+            for i in 0..=ones {
+                assert_eq!(index.select_1(i), bits.select_1(i), "select_1({})", i);
+            }
+            for i in 0..=zeros {
+                assert_eq!(index.select_0(i), bits.select_0(i), "select_0({})", i);
+            }
+            for i in (0..zeros).step_by(97) {
+                let target = bits.select_0(i).expect("zero");
+                for from in [0, target / 2, target.saturating_sub(700), target] {
+                    assert_eq!(index.select_0_at_or_after(from, i), Some(target), "select_0_at_or_after({}, {})", from, i);
+                }
+            }
+            // The first zero is long before the last block
+            assert_eq!(index.select_0_at_or_after(bits.byte_len() * 8 - 1, 0), None);
+        }
+    }
+
+    #[test]
+    fn lazy_index_builds_once_on_demand() {
+        let mut bits = Bits::new();
+        bits.append_ones(3).append_zeros(100).append_ones(2);
+        let lazy = LazySelectIndex::new(100);
+        assert!(lazy.built().is_none());
+        assert_eq!(lazy.get_or_build(bits.bits.as_ref()).over(bits.bits.as_ref()).select_1(3), Some(103));
+        assert!(lazy.built().is_some());
+        assert_eq!(lazy.sample_rate(), 128);
+    }
+}
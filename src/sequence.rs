@@ -0,0 +1,62 @@
+/// Whether construction accepts equal adjacent ids. Elias-Fano stores repeats fine, but sets of
+/// ids normally shouldn't have them, so `new` rejects them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    #[default]
+    StrictlyIncreasing,
+    /// Repeats allowed, e.g. prefix sums of counts that can be zero.
+    NonDecreasing,
+}
+
+impl Strictness {
+    pub(crate) fn out_of_order(self, previous: u64, id: u64) -> bool {
+        match self {
+            Strictness::StrictlyIncreasing => previous >= id,
+            Strictness::NonDecreasing => previous > id,
+        }
+    }
+}
+
+/// Shared query surface for the sorted integer codecs in this crate, so callers can pick a codec
+/// per list and still work through generics or `dyn CompressedSequence`.
+pub trait CompressedSequence {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, index: usize) -> Option<usize>;
+
+    fn next_geq(&self, value: usize) -> Option<usize>;
+
+    /// Number of stored values strictly less than `value`.
+    fn rank(&self, value: usize) -> usize;
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_>;
+
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Approximate serialized size for `n` ids with largest value `universe`, for choosing a codec
+    /// before paying for construction. Gap codecs assume evenly spread ids.
+    fn estimate_size(n: usize, universe: usize) -> usize where Self: Sized;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elias_fano::EliasFano;
+
+    #[test]
+    fn can_query_through_dyn() {
+        let data = vec![2, 3, 5, 7, 11, 13, 24];
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let seq: &dyn CompressedSequence = &ef;
+        assert_eq!(seq.len(), 7);
+        assert_eq!(seq.get(4), Some(11));
+        assert_eq!(seq.next_geq(12), Some(13));
+        assert_eq!(seq.rank(12), 5);
+        assert_eq!(seq.iter().collect::<Vec<_>>(), data);
+        assert_eq!(seq.serialize(), ef.as_bytes());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::bits::Bits;
+use crate::elias_fano::EliasFano;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+
+// Both types go through their compact byte format rather than field by field, so embedding them
+// costs what as_bytes costs and the checksum travels along.
+
+impl<V: AsRef<[u64]>> Serialize for EliasFano<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for EliasFano<Vec<u64>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        EliasFano::from_bytes_owned(&bytes).map_err(de::Error::custom)
+    }
+}
+
+impl<V: AsRef<[u64]>> Serialize for Bits<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for Bits<Vec<u64>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        Bits::read_from(&mut bytes.as_slice(), bytes.len()).map_err(de::Error::custom)
+    }
+}
+
+/// Accepts native byte strings as well as sequences of u8, which is how formats without a bytes
+/// type (JSON) write them.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "serialized pef bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 16));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_through_json() {
+        let data: Vec<usize> = (0..300).map(|i| i * 7).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let json = serde_json::to_string(&ef).expect("serialized");
+        let ef: EliasFano<Vec<u64>> = serde_json::from_str(&json).expect("deserialized");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        let mut bits = Bits::new();
+        bits.append_zeros(4).append_ones(4);
+        let json = serde_json::to_string(&bits).expect("serialized");
+        let bits: Bits<Vec<u64>> = serde_json::from_str(&json).expect("deserialized");
+        assert_eq!(bits.slice(2, 6), Some(3));
+    }
+
+    #[test]
+    fn corrupt_bytes_fail() {
+        assert!(serde_json::from_str::<EliasFano<Vec<u64>>>("[1, 2, 3]").is_err());
+    }
+}
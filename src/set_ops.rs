@@ -0,0 +1,445 @@
+//! Set operations over sorted sequences. A sequence built with `Strictness::NonDecreasing` is
+//! taken as the set of its values, so a repeated value counts once in every result and count.
+
+use crate::cursor::Cursor;
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::sequence::Strictness;
+use std::cmp::Reverse;
+use std::collections::binary_heap::PeekMut;
+use std::collections::BinaryHeap;
+
+/// Values in both of two sequences, found by leapfrogging one cursor past the other with
+/// `advance_to` rather than decoding either list in full.
+#[derive(Debug, Clone)]
+pub struct Intersection<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> {
+    left: Cursor<'a, V>,
+    right: Cursor<'a, W>,
+    // The shorter list proposes candidates, so the longer one mostly skips
+    left_leads: bool,
+}
+
+/// Values in the first sequence but not the second, e.g. ids with a deletion mask applied. The
+/// second cursor only ever skips ahead to the value being checked.
+#[derive(Debug, Clone)]
+pub struct Difference<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> {
+    left: Cursor<'a, V>,
+    right: Cursor<'a, W>,
+}
+
+/// Values in exactly one of two sequences, from a merge walk of both cursors.
+#[derive(Debug, Clone)]
+pub struct SymmetricDifference<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> {
+    left: Cursor<'a, V>,
+    right: Cursor<'a, W>,
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    pub fn difference<'a, W: AsRef<[u64]>>(&'a self, other: &'a EliasFano<W>) -> Difference<'a, V, W> {
+        Difference { left: self.cursor(), right: other.cursor() }
+    }
+
+    pub fn symmetric_difference<'a, W: AsRef<[u64]>>(&'a self, other: &'a EliasFano<W>) -> SymmetricDifference<'a, V, W> {
+        SymmetricDifference { left: self.cursor(), right: other.cursor() }
+    }
+
+    pub fn intersect<'a, W: AsRef<[u64]>>(&'a self, other: &'a EliasFano<W>) -> Intersection<'a, V, W> {
+        Intersection { left: self.cursor(), right: other.cursor(), left_leads: self.len() <= other.len() }
+    }
+
+    /// Whether every value here is also in `other`, stopping at the first that isn't.
+    pub fn is_subset<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> bool {
+        // Repeats here can make a subset longer than the set it's in
+        if self.strictness == Strictness::StrictlyIncreasing && self.len() > other.len() {
+            return false;
+        }
+        let mut theirs = other.cursor();
+        self.iter_u64().all(|value| theirs.advance_to_u64(value) == Some(value))
+    }
+
+    pub fn is_superset<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Whether no value is in both, stopping at the first common one.
+    pub fn is_disjoint<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> bool {
+        self.intersect(other).next().is_none()
+    }
+
+    /// Number of values in both, counted without collecting them.
+    pub fn intersection_len<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> usize {
+        self.intersect(other).count()
+    }
+
+    /// `intersection_len` capped at `k`, stopping as soon as `k` common values are found, e.g. to
+    /// check a selectivity threshold.
+    pub fn intersection_len_at_most<W: AsRef<[u64]>>(&self, other: &EliasFano<W>, k: usize) -> usize {
+        self.intersect(other).take(k).count()
+    }
+
+    /// Jaccard similarity `|A ∩ B| / |A ∪ B|`, 1.0 when both are empty.
+    pub fn jaccard<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> f64 {
+        let common = self.intersection_len(other);
+        let union = self.distinct_len() + other.distinct_len() - common;
+        if union == 0 { 1.0 } else { common as f64 / union as f64 }
+    }
+
+    /// Overlap coefficient `|A ∩ B| / min(|A|, |B|)`, 1.0 when either is empty since the empty
+    /// set is a subset of everything.
+    pub fn overlap<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> f64 {
+        let smaller = self.distinct_len().min(other.distinct_len());
+        if smaller == 0 { 1.0 } else { self.intersection_len(other) as f64 / smaller as f64 }
+    }
+
+    /// Number of different values, `len` unless values repeat.
+    fn distinct_len(&self) -> usize {
+        if self.strictness == Strictness::StrictlyIncreasing {
+            return self.len();
+        }
+        let mut cursor = self.cursor();
+        std::iter::from_fn(|| cursor.next_distinct_u64()).count()
+    }
+
+    /// Like `intersect` but encodes the result, which may be empty.
+    pub fn intersection<W: AsRef<[u64]>>(&self, other: &EliasFano<W>) -> Result<EliasFano<Vec<u64>>, Error> {
+        trace_span!("pef::intersection", left = self.len(), right = other.len());
+        encode(|| self.intersect(other).map(|v| v as u64))
+    }
+}
+
+impl<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> Iterator for Intersection<'a, V, W> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let value = if self.left_leads {
+            leapfrog(&mut self.left, &mut self.right)
+        } else {
+            leapfrog(&mut self.right, &mut self.left)
+        };
+        value.map(|v| v as usize)
+    }
+}
+
+impl<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> Iterator for Difference<'a, V, W> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let value = self.left.next_distinct_u64()?;
+            if self.right.advance_to_u64(value) != Some(value) {
+                return Some(value as usize);
+            }
+        }
+    }
+}
+
+impl<'a, V: AsRef<[u64]>, W: AsRef<[u64]>> Iterator for SymmetricDifference<'a, V, W> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let value = match (self.left.peek_u64(), self.right.peek_u64()) {
+                (None, None) => return None,
+                (Some(l), Some(r)) if l == r => {
+                    self.left.next_distinct_u64();
+                    self.right.next_distinct_u64();
+                    continue;
+                }
+                (Some(l), r) if r.map_or(true, |r| l < r) => self.left.next_distinct_u64(),
+                _ => self.right.next_distinct_u64(),
+            };
+            return value.map(|v| v as usize);
+        }
+    }
+}
+
+/// Next value both cursors hold, consuming it from both.
+fn leapfrog<V: AsRef<[u64]>, W: AsRef<[u64]>>(lead: &mut Cursor<'_, V>, follow: &mut Cursor<'_, W>) -> Option<u64> {
+    let mut candidate = lead.peek_u64()?;
+    loop {
+        let found = follow.advance_to_u64(candidate)?;
+        if found == candidate {
+            lead.next_distinct_u64();
+            follow.next_distinct_u64();
+            return Some(candidate);
+        }
+        candidate = lead.advance_to_u64(found)?;
+    }
+}
+
+/// Values in every one of several cursors, found by leapfrogging: the shortest list proposes a
+/// candidate and each other cursor skips ahead to it, with any overshoot becoming the next
+/// candidate. Nothing is allocated past the cursors themselves. No cursors match nothing.
+#[derive(Debug, Clone)]
+pub struct AndCursor<'a, V: AsRef<[u64]>> {
+    cursors: Vec<Cursor<'a, V>>,
+}
+
+impl<'a, V: AsRef<[u64]>> AndCursor<'a, V> {
+    pub fn new(mut cursors: Vec<Cursor<'a, V>>) -> Self {
+        cursors.sort_by_key(|cursor| cursor.size_hint().0);
+        AndCursor { cursors }
+    }
+
+    /// Consumes the next value in every cursor, as a `u64`.
+    pub fn next_u64(&mut self) -> Option<u64> {
+        let (lead, rest) = self.cursors.split_first_mut()?;
+        let mut candidate = lead.peek_u64()?;
+        let mut agreed = 0;
+        while agreed < rest.len() {
+            let found = rest[agreed].advance_to_u64(candidate)?;
+            if found == candidate {
+                agreed += 1;
+            } else {
+                candidate = lead.advance_to_u64(found)?;
+                agreed = 0;
+            }
+        }
+        self.cursors.iter_mut().for_each(|cursor| { cursor.next_distinct_u64(); });
+        Some(candidate)
+    }
+}
+
+/// An `AndCursor` over `sequences` from their start.
+pub fn intersect_all<'a, V: AsRef<[u64]>>(sequences: &[&'a EliasFano<V>]) -> AndCursor<'a, V> {
+    AndCursor::new(sequences.iter().map(|ef| ef.cursor()).collect())
+}
+
+impl<'a, V: AsRef<[u64]>> Iterator for AndCursor<'a, V> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.next_u64().map(|v| v as usize)
+    }
+}
+
+/// Values in any of several sequences, each once and in order. A heap holds the next value of
+/// every cursor, so each step costs `O(log k)` for `k` sequences.
+#[derive(Debug, Clone)]
+pub struct Union<'a, V: AsRef<[u64]>> {
+    counts: OrCursor<'a, V>,
+}
+
+pub fn union<'a, V: AsRef<[u64]>>(sequences: &[&'a EliasFano<V>]) -> Union<'a, V> {
+    Union { counts: union_counts(sequences) }
+}
+
+/// Re-encodes the union of `sequences`, e.g. to merge segments into one.
+pub fn merge<V: AsRef<[u64]>>(sequences: &[&EliasFano<V>]) -> Result<EliasFano<Vec<u64>>, Error> {
+    trace_span!("pef::merge", sequences = sequences.len());
+    encode(|| union(sequences).map(|v| v as u64))
+}
+
+impl<'a, V: AsRef<[u64]>> Iterator for Union<'a, V> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.counts.next_u64().map(|(value, _)| value as usize)
+    }
+}
+
+/// Values in any of several cursors, each once and in order, with the number of cursors holding
+/// it. Keeping counts of at least `k` answers "k of N" queries.
+#[derive(Debug, Clone)]
+pub struct OrCursor<'a, V: AsRef<[u64]>> {
+    cursors: Vec<Cursor<'a, V>>,
+    heads: BinaryHeap<Reverse<(u64, usize)>>,
+}
+
+impl<'a, V: AsRef<[u64]>> OrCursor<'a, V> {
+    pub fn new(cursors: Vec<Cursor<'a, V>>) -> Self {
+        let heads = cursors.iter().enumerate()
+            .filter_map(|(i, cursor)| cursor.peek_u64().map(|v| Reverse((v, i))))
+            .collect();
+        OrCursor { cursors, heads }
+    }
+
+    /// Consumes the next value from every cursor holding it, returning it with their count. A
+    /// cursor holding it several times, over a sequence with repeats, counts once.
+    pub fn next_u64(&mut self) -> Option<(u64, usize)> {
+        let Reverse((value, _)) = *self.heads.peek()?;
+        let mut hits = 0;
+        while let Some(Reverse((_, i))) = self.heads.peek_mut().filter(|head| head.0.0 == value).map(PeekMut::pop) {
+            let cursor = &mut self.cursors[i];
+            cursor.next_distinct_u64();
+            if let Some(next) = cursor.peek_u64() {
+                self.heads.push(Reverse((next, i)));
+            }
+            hits += 1;
+        }
+        Some((value, hits))
+    }
+
+    /// Values held by at least `k` different cursors.
+    pub fn at_least(self, k: usize) -> impl Iterator<Item=usize> + 'a {
+        self.filter_map(move |(value, hits)| (hits >= k).then_some(value))
+    }
+}
+
+/// An `OrCursor` over `sequences` from their start.
+pub fn union_counts<'a, V: AsRef<[u64]>>(sequences: &[&'a EliasFano<V>]) -> OrCursor<'a, V> {
+    OrCursor::new(sequences.iter().map(|ef| ef.cursor()).collect())
+}
+
+impl<'a, V: AsRef<[u64]>> Iterator for OrCursor<'a, V> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        self.next_u64().map(|(value, hits)| (value as usize, hits))
+    }
+}
+
+/// Merges two increasing streams with no value in both into one.
+pub(crate) fn merge_disjoint(left: impl Iterator<Item=u64>, right: impl Iterator<Item=u64>) -> impl Iterator<Item=u64> {
+    let (mut left, mut right) = (left.peekable(), right.peekable());
+    std::iter::from_fn(move || match (left.peek(), right.peek()) {
+        (Some(l), Some(r)) if r < l => right.next(),
+        (Some(_), _) => left.next(),
+        (None, _) => right.next(),
+    })
+}
+
+/// Encodes the strictly increasing output of a set operation, which unlike `new` may be empty.
+/// `values` is walked twice, once for the count and the last value and once streaming into the
+/// encoder, so nothing is buffered. Cursors over a corrupt loaded sequence can yield values out
+/// of order, which fails here rather than panicking.
+fn encode<I: Iterator<Item=u64>>(values: impl Fn() -> I) -> Result<EliasFano<Vec<u64>>, Error> {
+    let (n, last) = values().fold((0, 0), |(n, _), value| (n + 1, value));
+    if n == 0 {
+        return Ok(EliasFano::empty());
+    }
+    EliasFano::encode(n, last, values(), Strictness::StrictlyIncreasing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersect_matches_decoded() {
+        let a: Vec<usize> = (0..2000).map(|i| i * 3).collect();
+        let b: Vec<usize> = (0..300).map(|i| i * 7 + 1).chain(10_000..10_005).collect();
+        let expected: Vec<usize> = b.iter().copied().filter(|v| a.contains(v)).collect();
+        let ef_a = EliasFano::new(a).expect("elias fano encoding");
+        let ef_b = EliasFano::new(b).expect("elias fano encoding");
+        assert_eq!(ef_a.intersect(&ef_b).collect::<Vec<_>>(), expected);
+        assert_eq!(ef_b.intersect(&ef_a).collect::<Vec<_>>(), expected);
+        assert_eq!(ef_a.intersection(&ef_b).expect("encoded").iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn and_cursor_matches_every_list() {
+        let lists: Vec<Vec<usize>> = vec![(0..2000).map(|i| i * 2).collect(), (0..1000).map(|i| i * 3).collect(), (0..100).map(|i| i * 5 + 10).collect()];
+        let expected: Vec<usize> = lists[2].iter().copied().filter(|v| v % 6 == 0).collect();
+        let efs: Vec<_> = lists.into_iter().map(|l| EliasFano::new(l).expect("elias fano encoding")).collect();
+        let refs: Vec<_> = efs.iter().collect();
+        assert_eq!(intersect_all(&refs).collect::<Vec<_>>(), expected);
+        assert_eq!(intersect_all(&refs[..1]).count(), 2000);
+        assert_eq!(intersect_all::<Vec<u64>>(&[]).next(), None);
+    }
+
+    #[test]
+    fn or_cursor_counts_hits() {
+        let efs: Vec<_> = vec![vec![1, 3, 5, 7], vec![3, 4, 5], vec![5, 7, 9]].into_iter()
+            .map(|l| EliasFano::new(l).expect("elias fano encoding"))
+            .collect();
+        let refs: Vec<_> = efs.iter().collect();
+        let counts: Vec<_> = union_counts(&refs).collect();
+        assert_eq!(counts, vec![(1, 1), (3, 2), (4, 1), (5, 3), (7, 2), (9, 1)]);
+        assert_eq!(union_counts(&refs).at_least(2).collect::<Vec<_>>(), vec![3, 5, 7]);
+
+        let repeated = EliasFano::with_strictness(vec![1, 1, 4, 4, 4, 9], Strictness::NonDecreasing).expect("elias fano encoding");
+        let counts: Vec<_> = union_counts(&[&repeated, &efs[1]]).collect();
+        assert_eq!(counts, vec![(1, 1), (3, 1), (4, 2), (5, 1), (9, 1)]);
+        assert_eq!(union_counts(&[&repeated, &efs[1]]).at_least(2).collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn union_dedupes_across_sequences() {
+        let lists: Vec<Vec<usize>> = vec![(0..500).map(|i| i * 4).collect(), (0..300).map(|i| i * 6).collect(), vec![1, 2, 3, 5000]];
+        let mut expected: Vec<usize> = lists.iter().flatten().copied().collect();
+        expected.sort_unstable();
+        expected.dedup();
+        let efs: Vec<_> = lists.into_iter().map(|l| EliasFano::new(l).expect("elias fano encoding")).collect();
+        let refs: Vec<_> = efs.iter().collect();
+        assert_eq!(union(&refs).collect::<Vec<_>>(), expected);
+        assert_eq!(merge(&refs).expect("encoded").iter().collect::<Vec<_>>(), expected);
+        assert_eq!(union::<Vec<u64>>(&[]).next(), None);
+    }
+
+    #[test]
+    fn differences_match_decoded() {
+        let a: Vec<usize> = (0..2000).map(|i| i * 3).collect();
+        let b: Vec<usize> = (0..300).map(|i| i * 7 + 1).chain(10_000..10_005).collect();
+        let ef_a = EliasFano::new(a.clone()).expect("elias fano encoding");
+        let ef_b = EliasFano::new(b.clone()).expect("elias fano encoding");
+
+        let a_minus_b: Vec<usize> = a.iter().copied().filter(|v| !b.contains(v)).collect();
+        let b_minus_a: Vec<usize> = b.iter().copied().filter(|v| !a.contains(v)).collect();
+        assert_eq!(ef_a.difference(&ef_b).collect::<Vec<_>>(), a_minus_b);
+        assert_eq!(ef_b.difference(&ef_a).collect::<Vec<_>>(), b_minus_a);
+
+        let mut symmetric: Vec<usize> = a_minus_b.into_iter().chain(b_minus_a).collect();
+        symmetric.sort_unstable();
+        assert_eq!(ef_a.symmetric_difference(&ef_b).collect::<Vec<_>>(), symmetric);
+        assert_eq!(ef_b.symmetric_difference(&ef_a).collect::<Vec<_>>(), symmetric);
+        assert_eq!(ef_a.symmetric_difference(&ef_a).next(), None);
+    }
+
+    #[test]
+    fn subset_superset_disjoint() {
+        let all = EliasFano::new((0..1000).map(|i| i * 2)).expect("elias fano encoding");
+        let some = EliasFano::new(vec![0, 10, 500, 1998]).expect("elias fano encoding");
+        let odd = EliasFano::new(vec![1, 11, 1999]).expect("elias fano encoding");
+        let beyond = EliasFano::new(vec![0, 10, 2000]).expect("elias fano encoding");
+
+        assert!(some.is_subset(&all));
+        assert!(all.is_superset(&some));
+        assert!(!all.is_subset(&some));
+        assert!(!beyond.is_subset(&all));
+        assert!(all.is_subset(&all));
+        assert!(EliasFano::empty().is_subset(&some));
+
+        assert!(odd.is_disjoint(&all));
+        assert!(!some.is_disjoint(&all));
+        assert!(EliasFano::empty().is_disjoint(&all));
+    }
+
+    #[test]
+    fn similarity() {
+        let a = EliasFano::new(vec![1, 2, 3, 4]).expect("elias fano encoding");
+        let b = EliasFano::new(vec![3, 4, 5, 6, 7, 8]).expect("elias fano encoding");
+        assert_eq!(a.intersection_len(&b), 2);
+        assert_eq!((a.intersection_len_at_most(&b, 1), a.intersection_len_at_most(&b, 5)), (1, 2));
+        assert_eq!(a.jaccard(&b), 0.25);
+        assert_eq!(a.overlap(&b), 0.5);
+        assert_eq!(a.jaccard(&a), 1.0);
+        assert_eq!(EliasFano::empty().jaccard(&EliasFano::empty()), 1.0);
+        assert_eq!(a.jaccard(&EliasFano::empty()), 0.0);
+    }
+
+    #[test]
+    fn repeats_count_once() {
+        let repeated = EliasFano::with_strictness(vec![1, 3, 3, 3, 5, 8, 8], Strictness::NonDecreasing).expect("elias fano encoding");
+        let other = EliasFano::new(vec![3, 4, 8]).expect("elias fano encoding");
+        assert_eq!(repeated.intersect(&other).collect::<Vec<_>>(), vec![3, 8]);
+        assert_eq!(other.intersect(&repeated).collect::<Vec<_>>(), vec![3, 8]);
+        assert_eq!(repeated.intersection(&other).expect("encoded").iter().collect::<Vec<_>>(), vec![3, 8]);
+        assert_eq!(repeated.difference(&other).collect::<Vec<_>>(), vec![1, 5]);
+        assert_eq!(repeated.symmetric_difference(&other).collect::<Vec<_>>(), vec![1, 4, 5]);
+        assert_eq!(intersect_all(&[&repeated, &repeated]).collect::<Vec<_>>(), vec![1, 3, 5, 8]);
+        assert_eq!(merge(&[&repeated, &repeated]).expect("encoded").iter().collect::<Vec<_>>(), vec![1, 3, 5, 8]);
+        assert_eq!((repeated.jaccard(&other), repeated.overlap(&other)), (2.0 / 5.0, 2.0 / 3.0));
+
+        let set = EliasFano::new(vec![1, 3, 5, 8, 9]).expect("elias fano encoding");
+        assert!(repeated.is_subset(&set) && !repeated.is_subset(&other));
+    }
+
+    #[test]
+    fn disjoint_intersection_is_empty() {
+        let a = EliasFano::new(vec![1, 3, 5]).expect("elias fano encoding");
+        let b = EliasFano::new(vec![2, 4, 6]).expect("elias fano encoding");
+        assert_eq!(a.intersect(&b).next(), None);
+        assert!(a.intersection(&b).expect("encoded").is_empty());
+    }
+}
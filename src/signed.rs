@@ -0,0 +1,97 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use std::borrow::Borrow;
+
+/// Sorted `i64` values, e.g. timestamps or readings that dip below zero. They are stored as their
+/// distance from the minimum, which the serialized form keeps in front of the Elias-Fano blob.
+#[derive(Debug, Clone)]
+pub struct SignedEliasFano {
+    min: i64,
+    inner: EliasFano<Vec<u64>>,
+}
+
+impl SignedEliasFano {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<i64>>) -> Result<Self, Error> {
+        let ids: Vec<i64> = ids.into_iter().map(|i| *i.borrow()).collect();
+        let min = *ids.first().ok_or(Error::no_ids())?;
+        if let Some(position) = ids.windows(2).position(|pair| pair[0] >= pair[1]) {
+            return Err(Error::unsorted_ids(position + 1));
+        }
+        // Sorted, so every id is at least `min` and the distance fits a u64
+        let inner = EliasFano::from_u64s(ids.iter().map(|id| id.wrapping_sub(min) as u64))?;
+        Ok(Self { min, inner })
+    }
+
+    pub fn min(&self) -> i64 {
+        self.min
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        self.inner.get_u64(index).map(|v| self.unbias(v))
+    }
+
+    pub fn next_geq(&self, value: i64) -> Option<i64> {
+        self.inner.next_geq_u64(self.bias(value)).map(|v| self.unbias(v))
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: i64) -> usize {
+        self.inner.rank_u64(self.bias(value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=i64> + '_ {
+        self.inner.iter_u64().map(move |v| self.unbias(v))
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.min as u64).to_be_bytes().to_vec();
+        vec.append(&mut self.inner.as_bytes());
+        vec
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            min: be_u64_at(data, 0)? as i64,
+            inner: EliasFano::from_bytes_owned(rest_from(data, 8)?)?,
+        })
+    }
+
+    /// Distance of `value` from the minimum, with anything below it treated as the minimum.
+    fn bias(&self, value: i64) -> u64 {
+        value.max(self.min).wrapping_sub(self.min) as u64
+    }
+
+    fn unbias(&self, value: u64) -> i64 {
+        self.min.wrapping_add(value as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_values_round_trip() {
+        let ids: Vec<i64> = (-500..500).map(|i| i * 7).chain([i64::MAX]).collect();
+        let ef = SignedEliasFano::new(&ids).expect("elias fano encoding");
+        assert_eq!(ef.iter().collect::<Vec<_>>(), ids);
+        assert_eq!(ef.get(0), Some(-3500));
+        assert_eq!(ef.next_geq(-3), Some(0));
+        assert_eq!(ef.next_geq(i64::MIN), Some(-3500));
+        assert_eq!(ef.rank(-3499), 1);
+        assert_eq!(ef.rank(i64::MIN), 0);
+
+        let loaded = SignedEliasFano::from_bytes(&ef.as_bytes()).expect("loaded");
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), ids);
+        assert!(SignedEliasFano::new([3i64, -1]).is_err());
+    }
+}
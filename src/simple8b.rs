@@ -0,0 +1,243 @@
+use crate::bits::words_from_bytes;
+use crate::elias_fano::validate_sorted;
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::sequence::CompressedSequence;
+use std::borrow::Borrow;
+
+pub const MAX_BLOCK_LEN: usize = 240;
+
+const DATA_BITS: u32 = 60;
+
+// (gaps per word, bits per gap) for each 4-bit selector. Selectors 0 and 1 are runs of zero gaps.
+const SELECTORS: [(usize, u32); 16] = [
+    (240, 0), (120, 0), (60, 1), (30, 2), (20, 3), (15, 4), (12, 5), (10, 6),
+    (8, 7), (7, 8), (6, 10), (5, 12), (4, 15), (3, 20), (2, 30), (1, 60),
+];
+
+// Each u64 word holds a 4-bit selector in the top bits and as many equal-width gaps as fit in the
+// remaining 60, see Anh & Moffat, "Index compression using 64-bit words".
+#[derive(Debug)]
+pub struct Simple8b<V: AsRef<[u64]>> {
+    words: V,
+    size: usize,
+    /// The last word may hold fewer gaps than its selector has room for; the rest are padding.
+    last_block_len: usize,
+}
+
+impl Simple8b<Vec<u64>> {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+
+        validate_sorted(&ids)?;
+        if ids.is_empty() {
+            return Err(Error::no_ids());
+        }
+
+        let gaps: Vec<u64> = ids.iter().enumerate()
+            .map(|(i, id)| if i == 0 { *id as u64 } else { (id - ids[i - 1] - 1) as u64 })
+            .collect();
+        if let Some(gap) = gaps.iter().find(|g| **g >> DATA_BITS != 0) {
+            return Err(Error::gap_too_large(*gap as usize));
+        }
+
+        let mut words = vec![];
+        let mut position = 0;
+        let mut last_block_len = 0;
+        while position < gaps.len() {
+            // Selectors are ordered by how many gaps they pack, so the first that fits is the best
+            let (selector, count, bits) = SELECTORS.iter().enumerate()
+                .map(|(selector, (count, bits))| (selector, (*count).min(gaps.len() - position), *bits))
+                .find(|(_, count, bits)| gaps[position..position + count].iter().all(|g| g >> bits == 0))
+                .expect("a single gap always fits the last selector");
+
+            let mut word = (selector as u64) << DATA_BITS;
+            for (k, gap) in gaps[position..position + count].iter().enumerate() {
+                word |= gap << (k as u32 * bits);
+            }
+            words.push(word);
+            position += count;
+            last_block_len = count;
+        }
+
+        Ok(Self { words, size: ids.len(), last_block_len })
+    }
+
+    /// Length of `as_bytes()` if every gap were the average gap.
+    pub fn estimate_size(n: usize, universe: usize) -> usize {
+        let gap = (universe / n.max(1)) as u64;
+        let (count, _) = SELECTORS.iter()
+            .find(|(_, bits)| gap >> bits == 0)
+            .copied()
+            .unwrap_or(SELECTORS[15]);
+        8 + 8 * n.div_ceil(count)
+    }
+}
+
+impl<V: AsRef<[u64]>> Simple8b<V> {
+    pub fn num_blocks(&self) -> usize {
+        self.words.as_ref().len()
+    }
+
+    /// Decodes the values packed in word `block` into `out`, returning how many were written.
+    /// `previous` is the last value of the preceding block, or `None` for the first block.
+    pub fn decode_block(&self, block: usize, previous: Option<usize>, out: &mut [usize; MAX_BLOCK_LEN]) -> Option<usize> {
+        let word = *self.words.as_ref().get(block)?;
+        let (count, bits) = SELECTORS[(word >> DATA_BITS) as usize];
+        let count = if block + 1 == self.num_blocks() { self.last_block_len } else { count };
+        let mask = (1u64 << bits) - 1;
+        let mut previous = previous;
+        for (k, slot) in out.iter_mut().take(count).enumerate() {
+            let gap = ((word >> (k as u32 * bits)) & mask) as usize;
+            let value = match previous {
+                None => gap,
+                Some(p) => p.checked_add(gap)?.checked_add(1)?,
+            };
+            *slot = value;
+            previous = Some(value);
+        }
+        Some(count)
+    }
+
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.iter().nth(index)
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.iter().find(|v| *v >= value)
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.iter().take_while(|v| *v < value).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The size as a big-endian u64, then the words little-endian. Unlike the Elias-Fano blob
+    /// there is no magic, version or checksum.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.size as u64).to_be_bytes().to_vec();
+        vec.extend(self.words.as_ref().iter().flat_map(|i| i.to_le_bytes()));
+        vec
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        let mut buffer = [0; MAX_BLOCK_LEN];
+        let mut previous = None;
+        (0..self.num_blocks())
+            .flat_map(move |block| {
+                let count = self.decode_block(block, previous, &mut buffer).unwrap_or(0);
+                previous = buffer[..count].last().copied().or(previous);
+                buffer[..count].to_vec()
+            })
+            .take(self.size)
+    }
+}
+
+impl<V: AsRef<[u64]>> CompressedSequence for Simple8b<V> {
+    fn len(&self) -> usize {
+        Simple8b::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<usize> {
+        Simple8b::get(self, index)
+    }
+
+    fn next_geq(&self, value: usize) -> Option<usize> {
+        Simple8b::next_geq(self, value)
+    }
+
+    fn rank(&self, value: usize) -> usize {
+        Simple8b::rank(self, value)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_> {
+        Box::new(Simple8b::iter(self))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.as_bytes()
+    }
+
+    fn estimate_size(n: usize, universe: usize) -> usize {
+        Simple8b::estimate_size(n, universe)
+    }
+}
+
+impl<'a> Simple8b<&'a [u64]> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        let size = be_u64_at(data, 0)? as usize;
+        let words: &[u64] = words_from_bytes(rest_from(data, 8)?)?;
+        // Every word but the last is full, and the last holds whatever of `size` is left over
+        let full: usize = words.iter().rev().skip(1).map(|word| SELECTORS[(word >> DATA_BITS) as usize].0).sum();
+        let last_block_len = match words.last() {
+            None if size == 0 => 0,
+            None => return Err(Error::corrupt_header(0)),
+            Some(word) => size.checked_sub(full)
+                .filter(|len| (1..=SELECTORS[(word >> DATA_BITS) as usize].0).contains(len))
+                .ok_or(Error::corrupt_header(0))?,
+        };
+        Ok(Self { size, words, last_block_len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple8b_get() {
+        let s8b = Simple8b::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("simple8b encoding");
+        assert_eq!(s8b.get(0), Some(2));
+        assert_eq!(s8b.get(4), Some(11));
+        assert_eq!(s8b.get(6), Some(24));
+        assert_eq!(s8b.get(7), None);
+        assert_eq!(s8b.next_geq(14), Some(24));
+        assert_eq!(s8b.rank(12), 5);
+    }
+
+    #[test]
+    fn simple8b_packs_runs_into_one_word() {
+        let s8b = Simple8b::new(0..240usize).expect("simple8b encoding");
+        assert_eq!(s8b.num_blocks(), 1);
+        let mut buffer = [0; MAX_BLOCK_LEN];
+        assert_eq!(s8b.decode_block(0, None, &mut buffer), Some(240));
+        assert_eq!(buffer[239], 239);
+    }
+
+    #[test]
+    fn simple8b_partial_block_has_no_padding() {
+        let s8b = Simple8b::new(vec![1, 2, 3]).expect("simple8b encoding");
+        let mut buffer = [0; MAX_BLOCK_LEN];
+        assert_eq!(s8b.decode_block(0, None, &mut buffer), Some(3));
+        assert_eq!(buffer[..3], [1, 2, 3]);
+
+        let bytes = s8b.as_bytes();
+        let loaded = Simple8b::from_bytes(&bytes).expect("deserialized");
+        assert_eq!(loaded.decode_block(0, None, &mut buffer), Some(3));
+        let mut too_long = bytes.clone();
+        too_long[7] = 200;
+        assert!(Simple8b::from_bytes(&too_long).is_err());
+    }
+
+    #[test]
+    fn simple8b_rejects_huge_gaps() {
+        assert!(Simple8b::new(vec![0, 1 << 61]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let data: Vec<usize> = (0..300).map(|i| i * 13 + i % 7).chain(1 << 40..(1 << 40) + 3).collect();
+        let s8b = Simple8b::new(data.clone()).expect("simple8b encoding");
+        let s8b_serialized = s8b.as_bytes();
+        let s8b = Simple8b::from_bytes(s8b_serialized.as_slice()).expect("deserialized");
+        assert_eq!(s8b.iter().collect::<Vec<_>>(), data);
+    }
+}
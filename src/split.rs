@@ -0,0 +1,131 @@
+use crate::elias_fano::{validate_sorted, EliasFano};
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::advice::outlier_count;
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+
+/// Sorted ids whose few largest are kept aside as plain values when they are outliers, e.g. one
+/// id of 20000 after hundreds below 1400, so the rest are encoded with a universe that fits them.
+/// `advise` reports when this pays off; here the split is made whenever it saves anything.
+#[derive(Debug, Clone)]
+pub struct SplitEliasFano {
+    head: EliasFano<Vec<u64>>,
+    /// Every one is above the head's last value.
+    tail: Vec<u64>,
+}
+
+impl SplitEliasFano {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let ids: Vec<usize> = ids.into_iter().map(|i| *i.borrow()).collect();
+        validate_sorted(&ids)?;
+        let split = ids.len() - outlier_count(&ids).unwrap_or(0);
+        Ok(Self {
+            head: EliasFano::from_sorted_slice(&ids[..split])?,
+            tail: ids[split..].iter().map(|id| *id as u64).collect(),
+        })
+    }
+
+    /// The ids kept aside.
+    pub fn outliers(&self) -> &[u64] {
+        &self.tail
+    }
+
+    pub fn len(&self) -> usize {
+        self.head.len() + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<usize> {
+        match index.checked_sub(self.head.len()) {
+            Some(tail_index) => self.tail.get(tail_index).map(|v| *v as usize),
+            None => self.head.get(index),
+        }
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.head.next_geq(value).or_else(|| self.tail.iter().find(|v| **v >= value as u64).map(|v| *v as usize))
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.head.rank(value) + self.tail.partition_point(|v| *v < value as u64)
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.next_geq(value) == Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.head.iter().chain(self.tail.iter().map(|v| *v as usize))
+    }
+
+    /// The number of outliers and the outliers, then the Elias-Fano blob of the rest.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.tail.len() as u64).to_be_bytes().to_vec();
+        for value in &self.tail {
+            vec.extend_from_slice(&value.to_be_bytes());
+        }
+        vec.append(&mut self.head.as_bytes());
+        vec
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let count = usize::try_from(be_u64_at(data, 0)?).ok()
+            .filter(|count| *count <= data.len() / 8)
+            .ok_or(Error::corrupt_header(0))?;
+        let tail: Vec<u64> = (1..=count).map(|i| be_u64_at(data, 8 * i)).collect::<Result<_, _>>()?;
+        let head = EliasFano::from_bytes_owned(rest_from(data, 8 * (count + 1))?)?;
+        // Queries take every outlier to be above the head and the one before it
+        let mut previous = head.last_u64();
+        for (i, value) in tail.iter().enumerate() {
+            if previous.is_some_and(|previous| previous >= *value) {
+                return Err(Error::corrupt_header(8 * (i + 1)));
+            }
+            previous = Some(*value);
+        }
+        Ok(Self { head, tail })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+
+    #[test]
+    fn outliers_split_off_and_round_trip() {
+        let ids: Vec<usize> = (0..100).chain(1000..1023).chain(1060..1400).chain(20000..20001).collect();
+        let split = SplitEliasFano::new(&ids).expect("elias fano encoding");
+        assert_eq!(split.outliers(), [20000]);
+        assert!(split.as_bytes().len() * 3 < 2 * EliasFano::new(&ids).expect("elias fano encoding").as_bytes().len());
+        assert_eq!(split.iter().collect::<Vec<_>>(), ids);
+        assert_eq!((split.len(), split.get(463), split.get(464)), (464, Some(20000), None));
+        assert_eq!((split.next_geq(1400), split.next_geq(20001)), (Some(20000), None));
+        assert_eq!((split.rank(1400), split.rank(30000)), (463, 464));
+        assert!(split.contains(20000) && !split.contains(19999));
+
+        let loaded = SplitEliasFano::from_bytes(&split.as_bytes()).expect("loaded");
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), ids);
+        let spread: Vec<usize> = (0..1000).map(|i| i * 37).collect();
+        assert!(SplitEliasFano::new(&spread).expect("elias fano encoding").outliers().is_empty());
+    }
+
+    #[test]
+    fn from_bytes_rejects_outliers_out_of_order() {
+        let ids: Vec<usize> = (0..500).chain([50_000, 60_000]).collect();
+        let split = SplitEliasFano::new(&ids).expect("elias fano encoding");
+        assert_eq!(split.outliers(), [50_000, 60_000]);
+        let data = split.as_bytes();
+
+        let mut swapped = data.clone();
+        swapped[8..24].rotate_left(8);
+        assert_eq!(SplitEliasFano::from_bytes(&swapped).unwrap_err().kind(), &ErrorKind::CorruptHeader { offset: 16 });
+        let mut inside = data.clone();
+        inside[8..16].copy_from_slice(&499u64.to_be_bytes());
+        assert_eq!(SplitEliasFano::from_bytes(&inside).unwrap_err().kind(), &ErrorKind::CorruptHeader { offset: 8 });
+    }
+}
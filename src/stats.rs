@@ -0,0 +1,103 @@
+use crate::elias_fano::{validate_sorted, EliasFano};
+use crate::errors::Error;
+use std::collections::HashMap;
+
+/// Shape of a sorted id list, for working out why it compresses the way it does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapStats {
+    pub size: usize,
+    pub universe: usize,
+    /// `histogram[b]` counts the gaps needing exactly `b` bits, so `histogram[0]` is zero gaps.
+    /// The first id counts as a gap from zero.
+    pub histogram: Vec<usize>,
+    pub mean_gap: f64,
+    pub max_gap: usize,
+    /// Zero-order empirical entropy of the gap values, in bits per gap.
+    pub entropy: f64,
+    /// Number of maximal runs of consecutive ids; `size / runs` is the mean run length.
+    pub runs: usize,
+    /// Bits per id that Elias-Fano spends on this list, header included.
+    pub bits_per_element: f64,
+}
+
+pub fn analyze(ids: &[usize]) -> Result<GapStats, Error> {
+    validate_sorted(ids)?;
+    let universe = *ids.last().ok_or(Error::no_ids())?;
+    let ef_bytes = EliasFano::estimate_size(ids.len(), universe);
+    Ok(gap_stats(ids.iter().copied(), ef_bytes))
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    pub fn stats(&self) -> GapStats {
+        gap_stats(self.iter(), self.serialized_len())
+    }
+}
+
+fn gap_stats(ids: impl Iterator<Item=usize>, ef_bytes: usize) -> GapStats {
+    let mut histogram = vec![];
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    let mut size = 0;
+    let mut max_gap = 0;
+    let mut runs = 0;
+    let mut previous: Option<usize> = None;
+
+    for id in ids {
+        // Only a corrupt loaded blob can decrease; its gaps count as zero rather than wrapping
+        let gap = previous.map_or(id, |p| id.saturating_sub(p));
+        let bits = (usize::BITS - gap.leading_zeros()) as usize;
+        if histogram.len() <= bits {
+            histogram.resize(bits + 1, 0);
+        }
+        histogram[bits] += 1;
+        *counts.entry(gap).or_default() += 1;
+        if previous.is_none() || gap != 1 {
+            runs += 1;
+        }
+        max_gap = max_gap.max(gap);
+        size += 1;
+        previous = Some(id);
+    }
+
+    let universe = previous.unwrap_or(0);
+    let entropy = counts.values()
+        .map(|c| *c as f64 / size as f64)
+        .map(|p| -p * p.log2())
+        .sum();
+
+    GapStats {
+        size,
+        universe,
+        histogram,
+        mean_gap: universe as f64 / size.max(1) as f64,
+        max_gap,
+        entropy,
+        runs,
+        bits_per_element: (ef_bytes * 8) as f64 / size.max(1) as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_clustered_ids() {
+        let ids: Vec<usize> = (0..100).chain(1000..1100).collect();
+        let stats = analyze(&ids).expect("stats");
+        assert_eq!(stats.size, 200);
+        assert_eq!(stats.universe, 1099);
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.max_gap, 901);
+        assert_eq!(stats.histogram[1], 198);
+        assert_eq!(stats.histogram[10], 1);
+        assert!(stats.entropy < 1.0);
+    }
+
+    #[test]
+    fn ef_stats_match_analyze() {
+        let ids: Vec<usize> = (0..500).map(|i| i * 3).collect();
+        let ef = EliasFano::new(ids.clone()).expect("elias fano encoding");
+        assert_eq!(ef.stats(), analyze(&ids).expect("stats"));
+        assert!(ef.stats().entropy < 0.1);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::bits::Bits;
+use crate::checksum::Crc32;
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::metadata::{header_len, parse_header, read_checksum, write_default_header, Codec, CHECKSUM_LEN, HEADER_LEN, PREFIX_LEN};
+use std::convert::TryInto;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Bytes staged per write or read, so a large blob yields to the runtime between chunks instead of
+// being copied in one go.
+const CHUNK_LEN: usize = 64 * 1024;
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// Async version of `write_to`, writing the same bytes. It doesn't flush or shut `writer` down.
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        write_default_header(&mut header, &self.header_fields());
+        let mut crc = Crc32::new();
+        crc.update(&header);
+        writer.write_all(&header).await?;
+
+        let mut buffer = vec![0u8; CHUNK_LEN];
+        for bits in [&self.upper_bits, &self.lower_bits] {
+            for chunk in bits.bits.as_ref().chunks(CHUNK_LEN / 8) {
+                for (word, out) in chunk.iter().zip(buffer.chunks_exact_mut(8)) {
+                    out.copy_from_slice(&word.to_le_bytes());
+                }
+                let bytes = &buffer[..chunk.len() * 8];
+                crc.update(bytes);
+                writer.write_all(bytes).await?;
+            }
+        }
+        writer.write_all(&crc.finish().to_le_bytes()).await?;
+        Ok(self.serialized_len())
+    }
+}
+
+impl EliasFano<Vec<u64>> {
+    /// Async version of `read_from`, consuming exactly the blob's bytes from `reader`.
+    pub async fn read_from_async<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, Error> {
+        let mut crc = Crc32::new();
+        let mut header = vec![0; PREFIX_LEN];
+        reader.read_exact(&mut header).await?;
+        header.resize(header_len(&header), 0);
+        reader.read_exact(&mut header[PREFIX_LEN..]).await?;
+        crc.update(&header);
+        let metadata = parse_header(&header)?;
+        if metadata.codec != Codec::EliasFano {
+            return Err(Error::unknown_codec(metadata.codec.tag()));
+        }
+
+        read_words(reader, &mut crc, metadata.upper_bits_offset - metadata.header_len).await?;
+        let upper_bits = read_words(reader, &mut crc, metadata.upper_bits_len).await?;
+        read_words(reader, &mut crc, metadata.lower_bits_offset - metadata.upper_bits_offset - metadata.upper_bits_len).await?;
+        let lower_bits = read_words(reader, &mut crc, metadata.lower_bits_len).await?;
+
+        if metadata.version >= 2 {
+            let mut checksum = [0; CHECKSUM_LEN];
+            reader.read_exact(&mut checksum).await?;
+            let expected = read_checksum(checksum, metadata.version);
+            let actual = crc.finish();
+            if actual != expected {
+                return Err(Error::checksum_mismatch(expected, actual));
+            }
+        }
+
+        Self::from_parts(&metadata, Bits::from_words(upper_bits), Bits::from_words(lower_bits))
+    }
+}
+
+/// Reads `byte_len` bytes as little-endian words. Padding is read the same way and dropped.
+async fn read_words<R: AsyncRead + Unpin>(reader: &mut R, crc: &mut Crc32, byte_len: usize) -> Result<Vec<u64>, Error> {
+    if byte_len % 8 != 0 {
+        return Err(Error::invalid_bits_data(byte_len));
+    }
+    let mut buffer = vec![0u8; CHUNK_LEN.min(byte_len)];
+    // Don't trust byte_len for the allocation, a corrupt header could ask for anything
+    let mut words = Vec::with_capacity((byte_len / 8).min(1 << 16));
+    let mut remaining = byte_len;
+    while remaining > 0 {
+        let chunk = &mut buffer[..remaining.min(CHUNK_LEN)];
+        reader.read_exact(chunk).await?;
+        crc.update(chunk);
+        words.extend(chunk.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("chunks of 8"))));
+        remaining -= chunk.len();
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().expect("runtime").block_on(future)
+    }
+
+    #[test]
+    fn write_matches_as_bytes() {
+        let ef = EliasFano::new((0..100_000).map(|i| i * 3)).expect("elias fano encoding");
+        let mut out = vec![];
+        let written = block_on(ef.write_to_async(&mut out)).expect("written");
+        assert_eq!(written, out.len());
+        assert_eq!(out, ef.as_bytes());
+    }
+
+    #[test]
+    fn read_round_trip() {
+        let data: Vec<usize> = (0..100_000).map(|i| i * 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let mut bytes = ef.as_bytes();
+        bytes.extend_from_slice(b"next");
+        let mut reader = &bytes[..];
+        let read = block_on(EliasFano::read_from_async(&mut reader)).expect("read");
+        assert_eq!(read.iter().collect::<Vec<_>>(), data);
+        assert_eq!(reader, b"next");
+    }
+
+    #[test]
+    fn read_detects_corruption_and_truncation() {
+        let ef = EliasFano::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("elias fano encoding");
+        let mut bytes = ef.as_bytes();
+        assert!(block_on(EliasFano::read_from_async(&mut &bytes[..bytes.len() - 1])).is_err());
+        let last_payload_byte = bytes.len() - CHECKSUM_LEN - 1;
+        bytes[last_payload_byte] ^= 1;
+        assert!(block_on(EliasFano::read_from_async(&mut &bytes[..])).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::errors::Error;
+use crate::metadata::{be_u64_at, rest_from};
+use crate::sequence::CompressedSequence;
+use std::borrow::Borrow;
+
+// Gaps are written as LEB128 varints: seven bits per byte, high bit set on every byte but the last.
+// Byte aligned, so decoding is a tight loop with no bit shuffling across words.
+#[derive(Debug)]
+pub struct VByte<V: AsRef<[u8]>> {
+    bytes: V,
+    size: usize,
+}
+
+impl VByte<Vec<u8>> {
+    pub fn new(ids: impl IntoIterator<Item=impl Borrow<usize>>) -> Result<Self, Error> {
+        let mut bytes = vec![];
+        let mut previous: Option<usize> = None;
+        let mut size = 0;
+        for id in ids {
+            let id = *id.borrow();
+            let gap = match previous {
+                None => id,
+                Some(p) if p < id => id - p - 1,
+                Some(p) => return Err(Error::out_of_order(size, p as u64, id as u64)),
+            };
+            write_varint(&mut bytes, gap as u64);
+            previous = Some(id);
+            size += 1;
+        }
+        if size == 0 {
+            return Err(Error::no_ids());
+        }
+        Ok(Self { bytes, size })
+    }
+
+    /// Length of `as_bytes()` if every gap were the average gap.
+    pub fn estimate_size(n: usize, universe: usize) -> usize {
+        let gap = universe / n.max(1);
+        let gap_bits = (usize::BITS - gap.leading_zeros()) as usize;
+        8 + n * gap_bits.max(1).div_ceil(7)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+impl<V: AsRef<[u8]>> VByte<V> {
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.iter().nth(index)
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.iter().find(|v| *v >= value)
+    }
+
+    /// Number of stored values strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.iter().take_while(|v| *v < value).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The size as a big-endian u64, then the encoded bytes. Unlike the Elias-Fano blob there is
+    /// no magic, version or checksum.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut vec = (self.size as u64).to_be_bytes().to_vec();
+        vec.extend_from_slice(self.bytes.as_ref());
+        vec
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        let data = self.bytes.as_ref();
+        let mut position = 0;
+        let mut previous: Option<usize> = None;
+        (0..self.size).map_while(move |_| {
+            let gap = read_varint(data, &mut position)? as usize;
+            let value = match previous {
+                None => gap,
+                Some(p) => p.checked_add(gap)?.checked_add(1)?,
+            };
+            previous = Some(value);
+            Some(value)
+        })
+    }
+}
+
+impl<V: AsRef<[u8]>> CompressedSequence for VByte<V> {
+    fn len(&self) -> usize {
+        VByte::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<usize> {
+        VByte::get(self, index)
+    }
+
+    fn next_geq(&self, value: usize) -> Option<usize> {
+        VByte::next_geq(self, value)
+    }
+
+    fn rank(&self, value: usize) -> usize {
+        VByte::rank(self, value)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item=usize> + '_> {
+        Box::new(VByte::iter(self))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.as_bytes()
+    }
+
+    fn estimate_size(n: usize, universe: usize) -> usize {
+        VByte::estimate_size(n, universe)
+    }
+}
+
+impl<'a> VByte<&'a [u8]> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        Ok(Self {
+            size: be_u64_at(data, 0)? as usize,
+            bytes: rest_from(data, 8)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vbyte_get() {
+        let vb = VByte::new(vec![2, 3, 5, 7, 11, 13, 24]).expect("vbyte encoding");
+        assert_eq!(vb.get(0), Some(2));
+        assert_eq!(vb.get(4), Some(11));
+        assert_eq!(vb.get(6), Some(24));
+        assert_eq!(vb.get(7), None);
+        assert_eq!(vb.next_geq(14), Some(24));
+        assert_eq!(vb.rank(12), 5);
+    }
+
+    #[test]
+    fn vbyte_large_gaps() {
+        let data = vec![0, 127, 128, 300, 1 << 20, 1 << 40];
+        let vb = VByte::new(data.clone()).expect("vbyte encoding");
+        assert_eq!(vb.iter().collect::<Vec<_>>(), data);
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let data: Vec<usize> = (0..300).map(|i| i * 13 + i % 7).collect();
+        let vb = VByte::new(data.clone()).expect("vbyte encoding");
+        let vb_serialized = vb.as_bytes();
+        let vb = VByte::from_bytes(vb_serialized.as_slice()).expect("deserialized");
+        assert_eq!(vb.iter().collect::<Vec<_>>(), data);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::elias_fano::EliasFano;
+use crate::errors::Error;
+use crate::sequence::Strictness;
+use crate::set_ops::merge_disjoint;
+
+/// A base sequence plus a chain of deltas, one per committed version, as a log-structured index
+/// keeps them. Any version still in the chain can be read as it was; `freeze` folds the chain
+/// into a new base, after which only the latest version is readable.
+#[derive(Debug, Clone)]
+pub struct VersionedEliasFano<V: AsRef<[u64]>> {
+    base: EliasFano<V>,
+    base_version: usize,
+    deltas: Vec<Delta>,
+}
+
+/// What one version changed from the one before: values it added, none already present, and
+/// values it deleted, all of them present and each repeated as often as it was.
+#[derive(Debug, Clone)]
+struct Delta {
+    added: EliasFano<Vec<u64>>,
+    deleted: EliasFano<Vec<u64>>,
+}
+
+/// One version of a `VersionedEliasFano`, from `at` or `latest`.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot<'a, V: AsRef<[u64]>> {
+    versions: &'a VersionedEliasFano<V>,
+    // Number of deltas applied on top of the base
+    depth: usize,
+}
+
+impl<V: AsRef<[u64]>> VersionedEliasFano<V> {
+    /// Starts at version 0 with `base`.
+    pub fn new(base: EliasFano<V>) -> Self {
+        Self { base, base_version: 0, deltas: vec![] }
+    }
+
+    /// Records a version with `insertions` added and `deletions` removed, and returns its number.
+    /// As with `EliasFano::patched`, changes that do nothing are dropped and a value in both ends
+    /// up deleted.
+    pub fn commit(&mut self, insertions: &[u64], deletions: &[u64]) -> usize {
+        let latest = self.latest();
+        let mut deleted: Vec<u64> = deletions.iter().copied().filter(|v| latest.contains_u64(*v)).collect();
+        let mut added: Vec<u64> = insertions.iter().copied()
+            .filter(|v| !deletions.contains(v) && !latest.contains_u64(*v))
+            .collect();
+        for values in [&mut added, &mut deleted] {
+            values.sort_unstable();
+            values.dedup();
+        }
+        // Only the base can repeat a value, so only deletions have to
+        let deleted: Vec<u64> = deleted.iter().flat_map(|v| std::iter::repeat(*v).take(latest.occurrences(*v))).collect();
+        let encode = |values: Vec<u64>, strictness: Strictness| if values.is_empty() {
+            EliasFano::empty()
+        } else {
+            EliasFano::from_u64s_with_strictness(values, strictness).expect("sorted and counted")
+        };
+        self.deltas.push(Delta {
+            added: encode(added, Strictness::StrictlyIncreasing),
+            deleted: encode(deleted, self.base.strictness()),
+        });
+        self.version()
+    }
+
+    /// The latest version's number.
+    pub fn version(&self) -> usize {
+        self.base_version + self.deltas.len()
+    }
+
+    /// The oldest version that can still be read.
+    pub fn oldest_version(&self) -> usize {
+        self.base_version
+    }
+
+    /// `version` as it was committed, `None` if it is frozen away or not yet committed.
+    pub fn at(&self, version: usize) -> Option<Snapshot<'_, V>> {
+        let depth = version.checked_sub(self.base_version).filter(|depth| *depth <= self.deltas.len())?;
+        Some(Snapshot { versions: self, depth })
+    }
+
+    pub fn latest(&self) -> Snapshot<'_, V> {
+        Snapshot { versions: self, depth: self.deltas.len() }
+    }
+
+    /// Folds every delta into a new base holding the latest version, keeping its number.
+    pub fn freeze(&self) -> Result<VersionedEliasFano<Vec<u64>>, Error> {
+        Ok(VersionedEliasFano { base: self.latest().to_elias_fano()?, base_version: self.version(), deltas: vec![] })
+    }
+}
+
+impl<'a, V: AsRef<[u64]>> Snapshot<'a, V> {
+    pub fn version(&self) -> usize {
+        self.versions.base_version + self.depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas().fold(self.versions.base.len(), |len, delta| len + delta.added.len() - delta.deleted.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.contains_u64(value as u64)
+    }
+
+    pub fn contains_u64(&self, value: u64) -> bool {
+        // The newest delta that mentions the value decides
+        for delta in self.deltas().rev() {
+            if delta.added.contains_u64(value) {
+                return true;
+            }
+            if delta.deleted.contains_u64(value) {
+                return false;
+            }
+        }
+        self.versions.base.contains_u64(value)
+    }
+
+    /// How many times `value` is in this version, more than once only for repeats in the base.
+    fn occurrences(&self, value: u64) -> usize {
+        for delta in self.deltas().rev() {
+            if delta.added.contains_u64(value) {
+                return 1;
+            }
+            if delta.deleted.contains_u64(value) {
+                return 0;
+            }
+        }
+        self.versions.base.occurrences(value)
+    }
+
+    /// The values in order, streamed from the base through each delta in turn.
+    pub fn iter(&self) -> impl Iterator<Item=u64> + 'a {
+        let base: Box<dyn Iterator<Item=u64> + 'a> = Box::new(self.versions.base.iter_u64());
+        self.deltas().fold(base, |values, delta| {
+            let kept = values.filter(move |v| !delta.deleted.contains_u64(*v));
+            Box::new(merge_disjoint(kept, delta.added.iter_u64()))
+        })
+    }
+
+    /// Encodes this version on its own. A corrupt loaded base whose values aren't ordered or
+    /// counted as its header says fails with the encoding error.
+    pub fn to_elias_fano(&self) -> Result<EliasFano<Vec<u64>>, Error> {
+        let len = self.len();
+        if len == 0 {
+            return Ok(EliasFano::empty());
+        }
+        let universe = self.deltas().filter_map(|delta| delta.added.last_u64()).fold(self.versions.base.universe_u64(), u64::max);
+        EliasFano::encode(len, universe, self.iter(), self.versions.base.strictness())
+    }
+
+    fn deltas(&self) -> std::slice::Iter<'a, Delta> {
+        self.versions.deltas[..self.depth].iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_every_version() {
+        let mut versions = VersionedEliasFano::new(EliasFano::from_u64s([10u64, 20, 30, 40]).expect("elias fano encoding"));
+        assert_eq!(versions.commit(&[25, 50], &[10]), 1);
+        assert_eq!(versions.commit(&[10, 25], &[50, 99]), 2);
+        assert_eq!(versions.commit(&[], &[20, 30, 40]), 3);
+
+        let expected: [&[u64]; 4] = [&[10, 20, 30, 40], &[20, 25, 30, 40, 50], &[10, 20, 25, 30, 40], &[10, 25]];
+        for (version, values) in expected.iter().enumerate() {
+            let snapshot = versions.at(version).expect("kept");
+            assert_eq!(snapshot.iter().collect::<Vec<_>>(), *values);
+            assert_eq!(snapshot.len(), values.len());
+            assert!(values.iter().all(|v| snapshot.contains_u64(*v)));
+            assert_eq!(snapshot.to_elias_fano().expect("encoded").iter_u64().collect::<Vec<_>>(), *values);
+        }
+        assert!(!versions.at(1).expect("kept").contains_u64(10));
+        assert!(versions.at(4).is_none());
+
+        let frozen = versions.freeze().expect("frozen");
+        assert_eq!((frozen.oldest_version(), frozen.version()), (3, 3));
+        assert!(frozen.at(2).is_none());
+        assert_eq!(frozen.latest().iter().collect::<Vec<_>>(), vec![10, 25]);
+        assert_eq!(frozen.base.universe_u64(), 50);
+    }
+
+    #[test]
+    fn repeats_in_the_base_are_deleted_together() {
+        let base = EliasFano::from_u64s_with_strictness([1u64, 5, 5, 9], Strictness::NonDecreasing).expect("elias fano encoding");
+        let mut versions = VersionedEliasFano::new(base);
+        versions.commit(&[7], &[5]);
+        assert_eq!((versions.latest().len(), versions.latest().iter().collect::<Vec<_>>()), (3, vec![1, 7, 9]));
+        versions.commit(&[5], &[]);
+        assert_eq!(versions.latest().to_elias_fano().expect("encoded").iter_u64().collect::<Vec<_>>(), [1, 5, 7, 9]);
+        assert_eq!(versions.at(0).expect("kept").to_elias_fano().expect("encoded").iter_u64().collect::<Vec<_>>(), [1, 5, 5, 9]);
+        assert_eq!(versions.freeze().expect("frozen").latest().len(), 4);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::cursor::Cursor;
+use crate::elias_fano::EliasFano;
+use std::cmp::Ordering;
+use std::iter::Take;
+use std::ops::Range;
+
+/// The values of an `EliasFano` whose ranks fall in a range, queried in place over the same bits.
+/// Indexes and ranks are relative to the start of the range.
+#[derive(Debug, Clone, Copy)]
+pub struct EliasFanoSlice<'a, V: AsRef<[u64]>> {
+    ef: &'a EliasFano<V>,
+    start: usize,
+    end: usize,
+}
+
+impl<V: AsRef<[u64]>> EliasFano<V> {
+    /// A view of the values of rank `ranks.start` up to `ranks.end`, clamped to the sequence, e.g.
+    /// one shard or time window of a large structure. Nothing is copied or re-encoded.
+    pub fn view(&self, ranks: Range<usize>) -> EliasFanoSlice<'_, V> {
+        let end = ranks.end.min(self.len());
+        EliasFanoSlice { ef: self, start: ranks.start.min(end), end }
+    }
+
+    /// A view of every value, for code written against read-only sorted slices.
+    pub fn as_view(&self) -> EliasFanoSlice<'_, V> {
+        self.view(0..self.len())
+    }
+}
+
+impl<'a, V: AsRef<[u64]>> EliasFanoSlice<'a, V> {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.get_u64(index).map(|v| v as usize)
+    }
+
+    pub fn get_u64(&self, index: usize) -> Option<u64> {
+        if index >= self.len() {
+            return None;
+        }
+        self.ef.get_u64(self.start + index)
+    }
+
+    pub fn next_geq(&self, value: usize) -> Option<usize> {
+        self.next_geq_u64(value as u64).map(|v| v as usize)
+    }
+
+    pub fn next_geq_u64(&self, value: u64) -> Option<u64> {
+        self.get_u64(self.rank_u64(value))
+    }
+
+    /// Number of values in the view strictly less than `value`.
+    pub fn rank(&self, value: usize) -> usize {
+        self.rank_u64(value as u64)
+    }
+
+    pub fn rank_u64(&self, value: u64) -> usize {
+        self.ef.rank_u64(value).clamp(self.start, self.end) - self.start
+    }
+
+    /// `slice::binary_search_by` over the values, each probe decoded with `get`. Like the slice
+    /// method, any match may be returned when `f` finds several equal. A probe that fails to decode
+    /// counts as `Greater`.
+    pub fn binary_search_by(&self, mut f: impl FnMut(usize) -> Ordering) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.get(mid).map_or(Ordering::Greater, &mut f) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(low)
+    }
+
+    /// `slice::partition_point`, see `EliasFano::partition_point`.
+    pub fn partition_point(&self, mut pred: impl FnMut(usize) -> bool) -> usize {
+        self.binary_search_by(|v| if pred(v) { Ordering::Less } else { Ordering::Greater }).unwrap_or_else(|rank| rank)
+    }
+
+    pub fn iter(&self) -> Take<Cursor<'a, V>> {
+        self.ef.cursor_at(self.start).take(self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_stay_in_range() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let view = ef.view(200..300);
+        assert_eq!(view.len(), 100);
+        assert_eq!(view.iter().collect::<Vec<_>>(), data[200..300]);
+        assert_eq!(view.get(0), Some(data[200]));
+        assert_eq!(view.get(100), None);
+        assert_eq!(view.next_geq(0), Some(data[200]));
+        assert_eq!(view.next_geq(data[250] + 1), Some(data[251]));
+        assert_eq!(view.next_geq(data[299] + 1), None);
+        assert_eq!(view.rank(data[250]), 50);
+        assert_eq!(view.rank(usize::MAX), 100);
+
+        assert_eq!(ef.view(990..2000).len(), 10);
+        assert!(ef.view(2000..3000).is_empty());
+    }
+
+    #[test]
+    fn binary_search_by_matches_slice() {
+        let data: Vec<usize> = (0..1000).map(|i| i * 7 + i % 3).collect();
+        let ef = EliasFano::new(data.clone()).expect("elias fano encoding");
+        let view = ef.as_view();
+        for probe in (0..7100).step_by(3) {
+            assert_eq!(view.binary_search_by(|v| v.cmp(&probe)), data.binary_search(&probe));
+        }
+        assert_eq!(ef.view(200..300).binary_search_by(|v| v.cmp(&data[250])), Ok(50));
+        assert_eq!(ef.view(200..300).partition_point(|v| v < data[250]), 50);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::elias_fano::EliasFano;
+use wasm_bindgen::prelude::*;
+
+/// JavaScript handle to an owned `EliasFano`. Values cross as `BigInt` and arrays as
+/// `BigUint64Array`, so ids above 2^53 survive; serialized blobs cross as `Uint8Array`.
+#[wasm_bindgen(js_name = EliasFano)]
+pub struct WasmEliasFano {
+    inner: EliasFano<Vec<u64>>,
+}
+
+#[wasm_bindgen(js_class = EliasFano)]
+impl WasmEliasFano {
+    #[wasm_bindgen(constructor)]
+    pub fn new(ids: &[u64]) -> Result<WasmEliasFano, JsError> {
+        Ok(Self { inner: EliasFano::from_u64s(ids)? })
+    }
+
+    /// Loads a blob written by `toBytes` or by `as_bytes` on a server.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmEliasFano, JsError> {
+        Ok(Self { inner: EliasFano::from_bytes_owned(bytes)? })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<u64> {
+        self.inner.get_u64(index)
+    }
+
+    #[wasm_bindgen(js_name = nextGeq)]
+    pub fn next_geq(&self, value: u64) -> Option<u64> {
+        self.inner.next_geq_u64(value)
+    }
+
+    pub fn rank(&self, value: u64) -> usize {
+        self.inner.rank_u64(value)
+    }
+
+    #[wasm_bindgen(js_name = toArray)]
+    pub fn to_array(&self) -> Vec<u64> {
+        self.inner.iter_u64().collect()
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.inner.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // JsError can only be built on wasm, so only the success paths run natively
+
+    #[test]
+    fn construct_and_query() {
+        let ef = WasmEliasFano::new(&[3, 9, 1 << 60]).unwrap_or_else(|_| panic!("elias fano encoding"));
+        assert_eq!(ef.length(), 3);
+        assert_eq!(ef.get(2), Some(1 << 60));
+        assert_eq!(ef.next_geq(10), Some(1 << 60));
+        assert_eq!(ef.rank(9), 1);
+        assert_eq!(ef.to_array(), vec![3, 9, 1 << 60]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let server = EliasFano::new(vec![2, 3, 5, 7, 11]).expect("elias fano encoding");
+        let ef = WasmEliasFano::from_bytes(&server.as_bytes()).unwrap_or_else(|_| panic!("deserialized"));
+        assert_eq!(ef.to_bytes(), server.as_bytes());
+        assert_eq!(ef.to_array(), vec![2, 3, 5, 7, 11]);
+    }
+}